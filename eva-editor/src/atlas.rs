@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+use log::{debug, warn};
+
+// 单页图集纹理尺寸
+pub const ATLAS_PAGE_SIZE: i32 = 1024;
+// 字形之间留 1 像素空隙，避免线性采样时串色
+const GLYPH_PADDING: i32 = 1;
+
+// 图集中的字形键
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct GlyphKey {
+    pub face: usize,  // 回退链中的字体序号
+    pub glyph: u32,   // 字体内的字形 ID
+}
+
+// 图集页的像素格式：普通字形只有覆盖率，次像素字形每个颜色通道一份覆盖率，
+// 彩色 emoji 需要 RGBA
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AtlasFormat {
+    Alpha,
+    Lcd,
+    Rgba,
+}
+
+impl AtlasFormat {
+    fn gl_format(self) -> u32 {
+        match self {
+            AtlasFormat::Alpha => gl::RED,
+            AtlasFormat::Lcd => gl::RGB,
+            AtlasFormat::Rgba => gl::RGBA,
+        }
+    }
+}
+
+// 字形在图集中的位置
+#[derive(Clone, Copy, Debug)]
+pub struct AtlasRegion {
+    pub page: usize,
+    pub uv: [f32; 4], // u0, v0, u1, v1
+}
+
+struct AtlasEntry {
+    region: AtlasRegion,
+}
+
+// 按行（shelf）装箱的一页纹理
+struct AtlasPage {
+    texture_id: u32,
+    format: AtlasFormat,
+    shelves: Vec<Shelf>,
+    next_shelf_y: i32,
+    last_used: u64,
+}
+
+struct Shelf {
+    y: i32,
+    height: i32,
+    next_x: i32,
+}
+
+impl AtlasPage {
+    // 纹理等第一次上传字形时再建，装箱本身不碰 GL
+    fn new(format: AtlasFormat) -> Self {
+        AtlasPage {
+            texture_id: 0,
+            format,
+            shelves: Vec::new(),
+            next_shelf_y: 0,
+            last_used: 0,
+        }
+    }
+
+    fn texture(&mut self) -> u32 {
+        if self.texture_id != 0 {
+            return self.texture_id;
+        }
+        let (mut texture_id, format) = (0, self.format);
+        unsafe {
+            gl::GenTextures(1, &mut texture_id);
+            gl::BindTexture(gl::TEXTURE_2D, texture_id);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                format.gl_format() as i32,
+                ATLAS_PAGE_SIZE,
+                ATLAS_PAGE_SIZE,
+                0,
+                format.gl_format(),
+                gl::UNSIGNED_BYTE,
+                std::ptr::null(),
+            );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        }
+        self.texture_id = texture_id;
+        texture_id
+    }
+
+    // 在页内找一块 width x height 的空位，返回左上角坐标
+    fn allocate(&mut self, width: i32, height: i32) -> Option<(i32, i32)> {
+        let w = width + GLYPH_PADDING;
+        let h = height + GLYPH_PADDING;
+
+        // 优先放进高度最接近的已有行
+        let mut best: Option<usize> = None;
+        for (i, shelf) in self.shelves.iter().enumerate() {
+            if shelf.height >= h && shelf.next_x + w <= ATLAS_PAGE_SIZE {
+                let better = match best {
+                    Some(b) => shelf.height < self.shelves[b].height,
+                    None => true,
+                };
+                if better {
+                    best = Some(i);
+                }
+            }
+        }
+
+        if let Some(i) = best {
+            let shelf = &mut self.shelves[i];
+            let pos = (shelf.next_x, shelf.y);
+            shelf.next_x += w;
+            return Some(pos);
+        }
+
+        // 开一个新行
+        if self.next_shelf_y + h > ATLAS_PAGE_SIZE || w > ATLAS_PAGE_SIZE {
+            return None;
+        }
+        let y = self.next_shelf_y;
+        self.next_shelf_y += h;
+        self.shelves.push(Shelf { y, height: h, next_x: w });
+        Some((0, y))
+    }
+
+    fn clear(&mut self) {
+        self.shelves.clear();
+        self.next_shelf_y = 0;
+    }
+}
+
+impl Drop for AtlasPage {
+    fn drop(&mut self) {
+        if self.texture_id != 0 {
+            unsafe {
+                gl::DeleteTextures(1, &self.texture_id);
+            }
+        }
+    }
+}
+
+// 多页字形图集：字形首次使用时写入，写满后按 LRU 整页回收
+// 每种像素格式各自最多 max_pages 页
+pub struct GlyphAtlas {
+    pages: Vec<AtlasPage>,
+    entries: HashMap<GlyphKey, AtlasEntry>,
+    max_pages: usize,
+    frame: u64,
+}
+
+impl GlyphAtlas {
+    pub fn new(max_pages: usize) -> Self {
+        GlyphAtlas {
+            pages: Vec::new(),
+            entries: HashMap::new(),
+            max_pages: max_pages.max(1),
+            frame: 0,
+        }
+    }
+
+    // 每帧开始时调用，用于 LRU 计时
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    pub fn get(&mut self, key: &GlyphKey) -> Option<AtlasRegion> {
+        let region = self.entries.get(key)?.region;
+        self.pages[region.page].last_used = self.frame;
+        Some(region)
+    }
+
+    // 上传一个紧密排列的字形位图，Alpha 每像素 1 字节，Lcd 3 字节，Rgba 4 字节
+    pub fn insert(
+        &mut self,
+        key: GlyphKey,
+        format: AtlasFormat,
+        width: i32,
+        height: i32,
+        pixels: &[u8],
+    ) -> Option<AtlasRegion> {
+        let (region, x, y) = self.reserve(key, format, width, height)?;
+        let texture = self.pages[region.page].texture();
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexSubImage2D(
+                gl::TEXTURE_2D,
+                0,
+                x,
+                y,
+                width,
+                height,
+                format.gl_format(),
+                gl::UNSIGNED_BYTE,
+                pixels.as_ptr() as *const _,
+            );
+        }
+        Some(region)
+    }
+
+    // 给字形找好位置并登记，返回区域和左上角像素坐标；像素由 insert 上传
+    fn reserve(&mut self, key: GlyphKey, format: AtlasFormat, width: i32, height: i32) -> Option<(AtlasRegion, i32, i32)> {
+        if width <= 0 || height <= 0 {
+            return None;
+        }
+        let (page, x, y) = match self.allocate(format, width, height) {
+            Some(slot) => slot,
+            None => {
+                warn!("Glyph {:?} ({}x{}) does not fit in the atlas", key, width, height);
+                return None;
+            }
+        };
+
+        let size = ATLAS_PAGE_SIZE as f32;
+        let region = AtlasRegion {
+            page,
+            uv: [
+                x as f32 / size,
+                y as f32 / size,
+                (x + width) as f32 / size,
+                (y + height) as f32 / size,
+            ],
+        };
+        self.pages[page].last_used = self.frame;
+        self.entries.insert(key, AtlasEntry { region });
+        Some((region, x, y))
+    }
+
+    pub fn page_texture(&self, page: usize) -> u32 {
+        self.pages[page].texture_id
+    }
+
+    pub fn page_format(&self, page: usize) -> AtlasFormat {
+        self.pages[page].format
+    }
+
+    fn allocate(&mut self, format: AtlasFormat, width: i32, height: i32) -> Option<(usize, i32, i32)> {
+        for (i, page) in self.pages.iter_mut().enumerate() {
+            if page.format != format {
+                continue;
+            }
+            if let Some((x, y)) = page.allocate(width, height) {
+                return Some((i, x, y));
+            }
+        }
+
+        let page_count = self.pages.iter().filter(|page| page.format == format).count();
+        if page_count < self.max_pages {
+            debug!("Allocating {:?} glyph atlas page {}", format, self.pages.len());
+            self.pages.push(AtlasPage::new(format));
+            let i = self.pages.len() - 1;
+            return self.pages[i].allocate(width, height).map(|(x, y)| (i, x, y));
+        }
+
+        // 所有页都满了：回收最久未使用的一页（本帧用过的页不能动）
+        let victim = self
+            .pages
+            .iter()
+            .enumerate()
+            .filter(|(_, page)| page.format == format && page.last_used < self.frame)
+            .min_by_key(|(_, page)| page.last_used)
+            .map(|(i, _)| i)?;
+
+        debug!("Evicting glyph atlas page {}", victim);
+        self.entries.retain(|_, entry| entry.region.page != victim);
+        self.pages[victim].clear();
+        self.pages[victim].allocate(width, height).map(|(x, y)| (victim, x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(glyph: u32) -> GlyphKey {
+        GlyphKey { face: 0, glyph }
+    }
+
+    fn page_of(atlas: &mut GlyphAtlas, glyph: u32) -> Option<usize> {
+        atlas.get(&key(glyph)).map(|region| region.page)
+    }
+
+    // 一个字形占满一整页，方便数页
+    const WHOLE_PAGE: i32 = ATLAS_PAGE_SIZE - GLYPH_PADDING;
+
+    #[test]
+    fn shelves_fill_a_page() {
+        let mut page = AtlasPage::new(AtlasFormat::Alpha);
+        // 100x100 加上空隙每行放得下 10 个，一页 10 行
+        let step = 100 + GLYPH_PADDING;
+        for i in 0..100 {
+            assert_eq!(page.allocate(100, 100), Some((i % 10 * step, i / 10 * step)), "glyph {}", i);
+        }
+        assert_eq!(page.allocate(100, 100), None);
+        // 每行右边还剩一点，窄的还能放进第一行
+        assert_eq!(page.allocate(10, 100), Some((10 * step, 0)));
+
+        page.clear();
+        assert_eq!(page.allocate(10, 30), Some((0, 0)));
+        // 矮的放进已有的行，高的另开一行
+        assert_eq!(page.allocate(10, 10), Some((11, 0)));
+        assert_eq!(page.allocate(10, 50), Some((0, 31)));
+        assert_eq!(page.allocate(10, 20), Some((22, 0)));
+        assert_eq!(page.allocate(ATLAS_PAGE_SIZE, 10), None);
+    }
+
+    #[test]
+    fn eviction_skips_pages_used_this_frame() {
+        let mut atlas = GlyphAtlas::new(2);
+        let alpha = AtlasFormat::Alpha;
+        atlas.reserve(key(1), alpha, WHOLE_PAGE, WHOLE_PAGE).unwrap();
+        atlas.reserve(key(2), alpha, WHOLE_PAGE, WHOLE_PAGE).unwrap();
+        // 别的格式有自己的页，不占 Alpha 的页数
+        atlas.reserve(key(3), AtlasFormat::Rgba, WHOLE_PAGE, WHOLE_PAGE).unwrap();
+        assert_eq!((page_of(&mut atlas, 1), page_of(&mut atlas, 2), page_of(&mut atlas, 3)), (Some(0), Some(1), Some(2)));
+        // 同一帧里页都用过，一页也回收不了
+        assert!(atlas.reserve(key(4), alpha, 10, 10).is_none());
+
+        atlas.begin_frame();
+        page_of(&mut atlas, 1).unwrap();
+        // 第 0 页本帧用过，回收第 1 页，上面的字形跟着失效
+        assert_eq!(atlas.reserve(key(4), alpha, 10, 10).unwrap().0.page, 1);
+        assert_eq!(page_of(&mut atlas, 2), None);
+        assert_eq!(page_of(&mut atlas, 3), Some(2));
+        assert!(atlas.reserve(key(5), alpha, WHOLE_PAGE, WHOLE_PAGE).is_none());
+    }
+
+    #[test]
+    fn evicted_glyphs_can_come_back() {
+        let mut atlas = GlyphAtlas::new(1);
+        let alpha = AtlasFormat::Alpha;
+        let (first, x, y) = atlas.reserve(key(1), alpha, 20, 30).unwrap();
+        assert_eq!((first.page, x, y), (0, 0, 0));
+        atlas.begin_frame();
+        atlas.reserve(key(2), alpha, WHOLE_PAGE, WHOLE_PAGE).unwrap();
+        assert_eq!(page_of(&mut atlas, 1), None);
+
+        atlas.begin_frame();
+        let (again, x, y) = atlas.reserve(key(1), alpha, 20, 30).unwrap();
+        assert_eq!((again.page, x, y), (0, 0, 0));
+        assert_eq!(atlas.get(&key(1)).unwrap().uv, again.uv);
+        assert_eq!(page_of(&mut atlas, 2), None);
+    }
+}
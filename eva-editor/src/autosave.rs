@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use log::error;
+use crate::editor::Editor;
+use crate::watcher::{DiskState, DiskStatus};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum AutoSaveMode {
+    #[default]
+    Off,
+    // 窗口失去焦点时保存所有修改过的缓冲区
+    FocusLost,
+    // 停下编辑 delay 之后保存
+    AfterDelay,
+}
+
+// 自动保存，由配置文件的 [files] 段设置
+#[derive(Clone, Copy, Debug)]
+pub struct AutoSave {
+    pub mode: AutoSaveMode,
+    pub delay: Duration,
+}
+
+impl Default for AutoSave {
+    fn default() -> Self {
+        AutoSave { mode: AutoSaveMode::Off, delay: Duration::from_secs(1) }
+    }
+}
+
+// 记下每个修改过的缓冲区最后一次改动的版本和时间；None 表示这个版本已经试着保存过
+#[derive(Default)]
+pub struct AutoSaver {
+    edits: HashMap<u64, (u64, Option<Instant>)>,
+}
+
+impl AutoSaver {
+    // 每帧调用
+    pub fn tick(&mut self, editor: &mut Editor) {
+        if editor.autosave.mode != AutoSaveMode::AfterDelay {
+            return;
+        }
+        let now = Instant::now();
+        let delay = editor.autosave.delay;
+        self.edits.retain(|&id, _| editor.buffers.iter().any(|b| b.id == id && b.modified));
+
+        let mut due = Vec::new();
+        for (index, buffer) in editor.buffers.iter().enumerate().filter(|(_, b)| b.modified) {
+            let entry = self.edits.entry(buffer.id).or_insert((buffer.version, Some(now)));
+            if entry.0 != buffer.version {
+                *entry = (buffer.version, Some(now));
+            } else if entry.1.is_some_and(|edited| now.duration_since(edited) >= delay) {
+                // 保存失败时不每帧重试，等下次编辑
+                entry.1 = None;
+                due.push(index);
+            }
+        }
+        for index in due {
+            save(editor, index);
+        }
+    }
+
+    pub fn focus_lost(&mut self, editor: &mut Editor) {
+        if editor.autosave.mode != AutoSaveMode::FocusLost {
+            return;
+        }
+        for index in 0..editor.buffers.len() {
+            if editor.buffers[index].modified {
+                save(editor, index);
+            }
+        }
+    }
+}
+
+// 只保存能不声不响保存的：有路径、不是只读、磁盘上还是读入时的样子。
+// 外部改过或删掉的文件留给冲突提示和用户自己决定
+fn save(editor: &mut Editor, index: usize) {
+    let buffer = &editor.buffers[index];
+    let Some(path) = &buffer.file_path else {
+        return;
+    };
+    if buffer.readonly || buffer.disk_status != DiskStatus::Synced || DiskState::read(path) != buffer.disk_state {
+        return;
+    }
+    if let Err(e) = editor.save_buffer(index) {
+        error!("Failed to autosave: {:#}", e);
+    }
+}
@@ -0,0 +1,332 @@
+use std::ffi::{OsStr, OsString};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail, Context, Result};
+use log::error;
+use crate::editor::{Buffer, Editor};
+
+pub const USAGE: &str = "\
+Usage: eva-editor [OPTIONS] [FILE[:LINE[:COL]] | +LINE | DIR | -]...
+
+Open files in the EVA editor.
+
+Arguments:
+  FILE              Open FILE; it is created on first save if it does not exist
+  FILE:LINE[:COL]   Open FILE with the cursor at LINE and COL (both start at 1)
+  +LINE             Put the cursor at LINE in the next file (or the last one)
+  DIR               Use DIR as the workspace root
+  -                 Read a buffer from standard input
+
+Options:
+  -R, --readonly        Open every file read-only
+      --theme <NAME>    Color theme: eva-01 (default), eva-00 or eva-02
+      --config <FILE>   Read settings from FILE instead of
+                        $XDG_CONFIG_HOME/eva-editor/config
+      --set <KEY=VALUE> Override a config setting, e.g. --set font.size=15
+  -h, --help            Print this help and exit
+  -V, --version         Print the version and exit
+      --                Treat every following argument as a file name
+";
+
+// 命令行上的一个输入：文件或标准输入，以及要跳到的位置
+#[derive(Debug)]
+pub enum Source {
+    File(PathBuf),
+    Stdin,
+}
+
+#[derive(Debug)]
+pub struct Input {
+    pub source: Source,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub inputs: Vec<Input>,
+    pub workspace: Option<PathBuf>,
+    pub readonly: bool,
+    pub theme: Option<String>,
+    pub config: Option<PathBuf>,
+    // --set 给的配置，在配置文件之后按顺序应用
+    pub settings: Vec<(String, String)>,
+}
+
+pub enum Command {
+    Run(Args),
+    Help,
+    Version,
+}
+
+// 解析程序名之后的参数
+pub fn parse(args: impl IntoIterator<Item = OsString>) -> Result<Command> {
+    let mut parsed = Args::default();
+    let mut args = args.into_iter();
+    // +N 留给下一个文件
+    let mut pending_line = None;
+    let mut only_files = false;
+
+    while let Some(arg) = args.next() {
+        if !only_files {
+            match arg.to_str() {
+                Some("-h" | "--help") => return Ok(Command::Help),
+                Some("-V" | "--version") => return Ok(Command::Version),
+                Some("--") => {
+                    only_files = true;
+                    continue;
+                }
+                Some("-R" | "--readonly") => {
+                    parsed.readonly = true;
+                    continue;
+                }
+                Some("-") => {
+                    parsed.inputs.push(Input { source: Source::Stdin, line: pending_line.take(), column: None });
+                    continue;
+                }
+                // 只有一个 + 时跳到最后一行
+                Some(text) if text.starts_with('+') => {
+                    let line = match &text[1..] {
+                        "" => usize::MAX,
+                        digits => digits.parse().map_err(|_| anyhow!("invalid line number '{}'", text))?,
+                    };
+                    pending_line = Some(line);
+                    continue;
+                }
+                Some(text) if text.starts_with("--") => {
+                    let (name, inline) = match text.split_once('=') {
+                        Some((name, value)) => (name, Some(OsString::from(value))),
+                        None => (text, None),
+                    };
+                    if name != "--theme" && name != "--config" && name != "--set" {
+                        bail!("unknown option '{}'", name);
+                    }
+                    let name = name.to_string();
+                    let value = inline.or_else(|| args.next()).ok_or_else(|| anyhow!("'{}' needs a value", name))?;
+                    match name.as_str() {
+                        "--theme" => {
+                            let theme = value.into_string().map_err(|_| anyhow!("theme name must be valid UTF-8"))?;
+                            parsed.theme = Some(theme);
+                        }
+                        "--config" => parsed.config = Some(PathBuf::from(value)),
+                        _ => {
+                            let setting = value.to_str().and_then(|v| v.split_once('='));
+                            let (key, value) = setting.ok_or_else(|| anyhow!("'--set' expects KEY=VALUE"))?;
+                            parsed.settings.push((key.trim().to_string(), value.trim().to_string()));
+                        }
+                    }
+                    continue;
+                }
+                Some(text) if text.starts_with('-') => bail!("unknown option '{}'", text),
+                _ => {}
+            }
+        }
+
+        let (path, line, column) = split_position(&arg);
+        if path.is_dir() {
+            if parsed.workspace.is_some() {
+                bail!("only one directory can be opened as the workspace root");
+            }
+            parsed.workspace = Some(path);
+            continue;
+        }
+        let line = pending_line.take().or(line);
+        parsed.inputs.push(Input { source: Source::File(path), line, column });
+    }
+
+    if let (Some(line), Some(last)) = (pending_line, parsed.inputs.last_mut()) {
+        last.line = Some(line);
+    }
+    Ok(Command::Run(parsed))
+}
+
+// 打开命令行上的输入，出错的跳过；最后回到第一个打开的缓冲区
+pub fn open(editor: &mut Editor, args: &Args) {
+    editor.workspace_root = args.workspace.clone();
+    let mut first = None;
+    for input in &args.inputs {
+        let result = match &input.source {
+            Source::File(path) => editor.open_file(path.clone()),
+            Source::Stdin => read_stdin().map(|bytes| editor.open_buffer(Buffer::from_bytes(&bytes, None))),
+        };
+        if let Err(e) = result {
+            match &input.source {
+                Source::File(path) => error!("Failed to open {}: {:#}", path.display(), e),
+                Source::Stdin => error!("Failed to read standard input: {:#}", e),
+            }
+            continue;
+        }
+
+        if let Some(buffer) = editor.get_active_buffer_mut() {
+            buffer.readonly |= args.readonly;
+            first.get_or_insert(buffer.id);
+        }
+        if let Some(line) = input.line {
+            editor.goto(line, input.column.unwrap_or(1));
+        }
+    }
+    if let Some(index) = first.and_then(|id| editor.index_of(id)) {
+        editor.switch_to(index);
+    }
+}
+
+fn read_stdin() -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    std::io::stdin().read_to_end(&mut bytes).context("read failed")?;
+    Ok(bytes)
+}
+
+// "src/main.rs:10:5" 拆成路径、行和列；文件名本身就带冒号并且存在时不拆。
+// 编译器和 grep 输出里位置后面跟的冒号也认
+fn split_position(arg: &OsStr) -> (PathBuf, Option<usize>, Option<usize>) {
+    let whole = PathBuf::from(arg);
+    let Some(text) = arg.to_str() else {
+        return (whole, None, None);
+    };
+    if whole.exists() {
+        return (whole, None, None);
+    }
+
+    let mut rest = text.strip_suffix(':').unwrap_or(text);
+    let mut numbers = Vec::new();
+    while numbers.len() < 2 {
+        match rest.rsplit_once(':') {
+            Some((head, tail)) if !head.is_empty() && tail.bytes().all(|b| b.is_ascii_digit()) => match tail.parse() {
+                Ok(number) => {
+                    numbers.push(number);
+                    rest = head;
+                    // "notes:12:4" 里的 notes:12 存在时，4 是行号
+                    if Path::new(rest).exists() {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            },
+            _ => break,
+        }
+    }
+    match numbers[..] {
+        [line] => (PathBuf::from(rest), Some(line), None),
+        [column, line] => (PathBuf::from(rest), Some(line), Some(column)),
+        _ => (whole, None, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::theme::Theme;
+
+    fn run(args: &[&str]) -> Result<Args> {
+        match parse(args.iter().map(OsString::from))? {
+            Command::Run(args) => Ok(args),
+            Command::Help => bail!("help"),
+            Command::Version => bail!("version"),
+        }
+    }
+
+    fn files(args: &Args) -> Vec<(PathBuf, Option<usize>, Option<usize>)> {
+        args.inputs
+            .iter()
+            .map(|input| match &input.source {
+                Source::File(path) => (path.clone(), input.line, input.column),
+                Source::Stdin => (PathBuf::from("-"), input.line, input.column),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn positions_after_file_names() {
+        let args = run(&["no/such/a.rs:12", "no/such/b.rs:3:7", "no/such/c.rs:5:", "no/such/d.rs"]).unwrap();
+        assert_eq!(
+            files(&args),
+            [
+                (PathBuf::from("no/such/a.rs"), Some(12), None),
+                (PathBuf::from("no/such/b.rs"), Some(3), Some(7)),
+                (PathBuf::from("no/such/c.rs"), Some(5), None),
+                (PathBuf::from("no/such/d.rs"), None, None),
+            ]
+        );
+        // 冒号后面不是数字时整个当作文件名
+        let args = run(&["no/such/e.rs:main"]).unwrap();
+        assert_eq!(files(&args), [(PathBuf::from("no/such/e.rs:main"), None, None)]);
+    }
+
+    #[test]
+    fn plus_line_applies_to_the_next_file() {
+        let args = run(&["+20", "no/such/a.rs", "no/such/b.rs", "+"]).unwrap();
+        assert_eq!(
+            files(&args),
+            [(PathBuf::from("no/such/a.rs"), Some(20), None), (PathBuf::from("no/such/b.rs"), Some(usize::MAX), None)]
+        );
+        let args = run(&["+7", "-"]).unwrap();
+        assert_eq!(files(&args), [(PathBuf::from("-"), Some(7), None)]);
+        assert!(run(&["+x", "a.rs"]).is_err());
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let args = run(&["-R", "--", "-notes.txt", "--readonly", "+3"]).unwrap();
+        assert!(args.readonly);
+        assert_eq!(
+            files(&args),
+            [
+                (PathBuf::from("-notes.txt"), None, None),
+                (PathBuf::from("--readonly"), None, None),
+                (PathBuf::from("+3"), None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn existing_file_with_a_colon_is_not_split() {
+        let dir = std::env::temp_dir().join(format!("eva-editor-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("notes:12");
+        std::fs::write(&file, "text").unwrap();
+        let name = file.to_str().unwrap();
+
+        let args = run(&[name]).unwrap();
+        assert_eq!(files(&args), [(file.clone(), None, None)]);
+        let args = run(&[&format!("{}:4", name)]).unwrap();
+        assert_eq!(files(&args), [(file.clone(), Some(4), None)]);
+        // 目录当作工作区
+        let args = run(&[dir.to_str().unwrap()]).unwrap();
+        assert_eq!(args.workspace.as_deref(), Some(dir.as_path()));
+        assert!(args.inputs.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn options() {
+        assert!(matches!(parse(["--help"].map(OsString::from)), Ok(Command::Help)));
+        assert!(matches!(parse(["a.rs", "-V"].map(OsString::from)), Ok(Command::Version)));
+        let args = run(&["--theme", "eva-02", "--config=/tmp/eva.conf", "--set", "font.size = 15"]).unwrap();
+        assert_eq!(args.theme.as_deref(), Some("eva-02"));
+        assert_eq!(args.config.as_deref(), Some(Path::new("/tmp/eva.conf")));
+        assert_eq!(args.settings, [("font.size".to_string(), "15".to_string())]);
+
+        assert_eq!(run(&["--frobnicate"]).unwrap_err().to_string(), "unknown option '--frobnicate'");
+        assert_eq!(run(&["-x"]).unwrap_err().to_string(), "unknown option '-x'");
+        assert_eq!(run(&["--theme"]).unwrap_err().to_string(), "'--theme' needs a value");
+        assert_eq!(run(&["--set", "font.size"]).unwrap_err().to_string(), "'--set' expects KEY=VALUE");
+    }
+
+    #[test]
+    fn set_values_go_through_config() {
+        let mut theme = Theme::default();
+        let mut editor = Editor::new();
+        let args = run(&["--set=font.size=18", "--set", "cursor.blink=false"]).unwrap();
+        config::apply_overrides(&args.settings, &mut theme, &mut editor).unwrap();
+        assert_eq!(theme.font.size, 18.0);
+        assert!(!theme.cursor.blink);
+
+        for setting in ["font.size=big", "cursor.blink=maybe", "cursor.shape=circle", "files.autosave_delay=0"] {
+            let args = run(&["--set", setting]).unwrap();
+            let error = config::apply_overrides(&args.settings, &mut theme, &mut editor).unwrap_err();
+            let key = setting.split('=').next().unwrap();
+            assert_eq!(error.to_string(), format!("--set {}", key));
+        }
+    }
+}
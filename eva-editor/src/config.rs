@@ -0,0 +1,306 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::{anyhow, bail, Context, Result};
+use log::{info, warn};
+use crate::autosave::AutoSaveMode;
+use crate::editor::Editor;
+use crate::theme::{Antialiasing, CursorShape, GlyphRenderMode, Hinting, LineNumbers, Theme, THEME_PRESETS};
+
+// 配置文件是 `键 = 值` 的行，`[段]` 给后面的键加前缀，# 之后是注释：
+//
+//     theme = "eva-02"
+//
+//     [font]
+//     family = "JetBrains Mono"
+//     size = 15
+//     antialiasing = "subpixel_rgb"   # none、grayscale、subpixel_rgb 或 subpixel_bgr
+//     hinting = "slight"              # none、slight 或 full
+//     gamma = 1.8
+//     contrast = 0.25                 # 0 到 1
+//     render_mode = "sdf"             # bitmap 或 sdf，描边和辉光只在 sdf 下生效
+//     sdf_min_size = 20               # 小于这个字号仍用位图
+//     outline = 1.5                   # 描边宽度，逻辑像素
+//     outline_color = "#000000"
+//     glow = 4
+//     glow_color = "#ff800099"        # #rrggbb 或 #rrggbbaa
+//
+//     [cursor]
+//     shape = "beam"
+//     blink = false
+//
+//     [files]
+//     backup = true
+//     autosave = "delay"      # off、focus（窗口失去焦点时）或 delay
+//     autosave_delay = 1.5    # 秒
+
+// 没有 --config 时读的位置
+pub fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("eva-editor").join("config"))
+}
+
+// 读配置文件，外观写进 theme，文件相关的设置写进 editor。path 为 None 时读默认位置，那里没有文件不算错
+pub fn load(path: Option<&Path>, theme: &mut Theme, editor: &mut Editor) -> Result<()> {
+    let (path, required) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match default_path() {
+            Some(path) => (path, false),
+            None => return Ok(()),
+        },
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read config {}", path.display())),
+    };
+    apply(&text, theme, editor).with_context(|| format!("Invalid config {}", path.display()))?;
+    info!("Loaded config from {}", path.display());
+    Ok(())
+}
+
+fn apply(text: &str, theme: &mut Theme, editor: &mut Editor) -> Result<()> {
+    let mut section = String::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim().to_string();
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("line {}: expected `key = value`", index + 1))?;
+        let key = match section.as_str() {
+            "" => key.trim().to_string(),
+            section => format!("{}.{}", section, key.trim()),
+        };
+        set(theme, editor, &key, value.trim()).with_context(|| format!("line {}: {}", index + 1, key))?;
+    }
+    Ok(())
+}
+
+// 命令行上 --set 给的设置，值的写法和配置文件一样
+pub fn apply_overrides(settings: &[(String, String)], theme: &mut Theme, editor: &mut Editor) -> Result<()> {
+    for (key, value) in settings {
+        set(theme, editor, key, value).with_context(|| format!("--set {}", key))?;
+    }
+    Ok(())
+}
+
+fn set(theme: &mut Theme, editor: &mut Editor, key: &str, value: &str) -> Result<()> {
+    match key {
+        "theme" => {
+            let name = string(value);
+            if !theme.apply_preset(&name) {
+                bail!("unknown theme '{}', expected one of {}", name, THEME_PRESETS.join(", "));
+            }
+        }
+        "font.family" => theme.font.family = string(value),
+        "font.size" => theme.font.size = number(value)?,
+        "font.line_height" => theme.font.line_height = number(value)?,
+        "font.render_mode" => {
+            theme.font.render_mode = match string(value).as_str() {
+                "bitmap" => GlyphRenderMode::Bitmap,
+                "sdf" => GlyphRenderMode::Sdf,
+                other => bail!("unknown render mode '{}', expected bitmap or sdf", other),
+            }
+        }
+        "font.sdf_min_size" => theme.font.sdf_min_size = non_negative(value)?,
+        "font.outline" => theme.font.effects.outline_width = non_negative(value)?,
+        "font.outline_color" => theme.font.effects.outline_color = color(value)?,
+        "font.glow" => theme.font.effects.glow_radius = non_negative(value)?,
+        "font.glow_color" => theme.font.effects.glow_color = color(value)?,
+        "font.antialiasing" => {
+            theme.font.antialiasing = match string(value).as_str() {
+                "none" => Antialiasing::None,
+                "grayscale" => Antialiasing::Grayscale,
+                "subpixel_rgb" | "rgb" => Antialiasing::SubpixelRgb,
+                "subpixel_bgr" | "bgr" => Antialiasing::SubpixelBgr,
+                other => bail!("unknown antialiasing '{}', expected none, grayscale, subpixel_rgb or subpixel_bgr", other),
+            }
+        }
+        "font.hinting" => {
+            theme.font.hinting = match string(value).as_str() {
+                "none" => Hinting::None,
+                "slight" => Hinting::Slight,
+                "full" => Hinting::Full,
+                other => bail!("unknown hinting '{}', expected none, slight or full", other),
+            }
+        }
+        "font.gamma" => {
+            let gamma = number(value)?;
+            if !gamma.is_finite() || gamma <= 0.0 {
+                bail!("gamma must be positive, got {}", gamma);
+            }
+            theme.font.gamma = gamma;
+        }
+        "font.contrast" => {
+            let contrast = number(value)?;
+            if !(0.0..=1.0).contains(&contrast) {
+                bail!("contrast must be between 0 and 1, got {}", contrast);
+            }
+            theme.font.contrast = contrast;
+        }
+        "cursor.shape" => {
+            theme.cursor.shape = match string(value).as_str() {
+                "block" => CursorShape::Block,
+                "beam" => CursorShape::Beam,
+                "underline" => CursorShape::Underline,
+                other => bail!("unknown cursor shape '{}', expected block, beam or underline", other),
+            }
+        }
+        "cursor.blink" => theme.cursor.blink = boolean(value)?,
+        "cursor.smooth" => theme.cursor.smooth = boolean(value)?,
+        "gutter.line_numbers" => {
+            theme.gutter.line_numbers = match string(value).as_str() {
+                "off" => LineNumbers::Off,
+                "absolute" => LineNumbers::Absolute,
+                "relative" => LineNumbers::Relative,
+                "hybrid" => LineNumbers::Hybrid,
+                other => bail!("unknown line number mode '{}', expected off, absolute, relative or hybrid", other),
+            }
+        }
+        "gutter.markers" => theme.gutter.show_markers = boolean(value)?,
+        "minimap.enabled" => theme.minimap.enabled = boolean(value)?,
+        "minimap.width" => theme.minimap.width = number(value)?,
+        "files.backup" => editor.save_options.backup = boolean(value)?,
+        "files.autosave" => {
+            editor.autosave.mode = match string(value).as_str() {
+                "off" => AutoSaveMode::Off,
+                "focus" => AutoSaveMode::FocusLost,
+                "delay" => AutoSaveMode::AfterDelay,
+                other => bail!("unknown autosave mode '{}', expected off, focus or delay", other),
+            }
+        }
+        "files.autosave_delay" => {
+            let seconds = number(value)?;
+            if !seconds.is_finite() || seconds <= 0.0 {
+                bail!("autosave delay must be positive, got {}", seconds);
+            }
+            editor.autosave.delay = Duration::from_secs_f32(seconds);
+        }
+        _ => warn!("Unknown config key: {}", key),
+    }
+    Ok(())
+}
+
+// 引号里的 # 不算注释
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+// 字符串可以带引号也可以不带
+fn string(value: &str) -> String {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+        .to_string()
+}
+
+fn number(value: &str) -> Result<f32> {
+    value.parse().map_err(|_| anyhow!("expected a number, got '{}'", value))
+}
+
+fn non_negative(value: &str) -> Result<f32> {
+    let number = number(value)?;
+    if !number.is_finite() || number < 0.0 {
+        bail!("expected a non-negative number, got {}", number);
+    }
+    Ok(number)
+}
+
+// "#rrggbb" 或 "#rrggbbaa"
+fn color(value: &str) -> Result<[f32; 4]> {
+    let text = string(value);
+    let hex = text.strip_prefix('#').unwrap_or(&text);
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("expected a color like #rrggbb or #rrggbbaa, got '{}'", text);
+    }
+    let mut color = [1.0; 4];
+    for (i, channel) in color.iter_mut().enumerate().take(hex.len() / 2) {
+        *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)? as f32 / 255.0;
+    }
+    Ok(color)
+}
+
+fn boolean(value: &str) -> Result<bool> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => bail!("expected true or false, got '{}'", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn font_rasterization_keys() {
+        let mut theme = Theme::default();
+        let mut editor = Editor::new();
+        set(&mut theme, &mut editor, "font.antialiasing", "\"subpixel_bgr\"").unwrap();
+        set(&mut theme, &mut editor, "font.hinting", "full").unwrap();
+        set(&mut theme, &mut editor, "font.gamma", "2.2").unwrap();
+        set(&mut theme, &mut editor, "font.contrast", "0.5").unwrap();
+        assert_eq!(theme.font.antialiasing, Antialiasing::SubpixelBgr);
+        assert_eq!(theme.font.hinting, Hinting::Full);
+        assert_eq!(theme.font.gamma, 2.2);
+        assert_eq!(theme.font.contrast, 0.5);
+        set(&mut theme, &mut editor, "font.antialiasing", "rgb").unwrap();
+        assert_eq!(theme.font.antialiasing, Antialiasing::SubpixelRgb);
+
+        for (key, value) in [
+            ("font.antialiasing", "cleartype"),
+            ("font.hinting", "medium"),
+            ("font.gamma", "0"),
+            ("font.contrast", "1.5"),
+        ] {
+            assert!(set(&mut theme, &mut editor, key, value).is_err(), "{} = {}", key, value);
+        }
+        // 出错的值不改原来的设置
+        assert_eq!(theme.font.hinting, Hinting::Full);
+        assert_eq!(theme.font.contrast, 0.5);
+    }
+
+    #[test]
+    fn sdf_and_effect_keys() {
+        let mut theme = Theme::default();
+        let mut editor = Editor::new();
+        set(&mut theme, &mut editor, "font.render_mode", "\"sdf\"").unwrap();
+        set(&mut theme, &mut editor, "font.sdf_min_size", "16").unwrap();
+        set(&mut theme, &mut editor, "font.outline", "1.5").unwrap();
+        set(&mut theme, &mut editor, "font.outline_color", "\"#ff0000\"").unwrap();
+        set(&mut theme, &mut editor, "font.glow", "4").unwrap();
+        set(&mut theme, &mut editor, "font.glow_color", "#00ff0080").unwrap();
+        assert_eq!(theme.font.render_mode, GlyphRenderMode::Sdf);
+        assert_eq!(theme.font.sdf_min_size, 16.0);
+        assert_eq!(theme.font.effects.outline_width, 1.5);
+        assert_eq!(theme.font.effects.outline_color, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(theme.font.effects.glow_radius, 4.0);
+        assert_eq!(theme.font.effects.glow_color, [0.0, 1.0, 0.0, 128.0 / 255.0]);
+
+        for (key, value) in [
+            ("font.render_mode", "vector"),
+            ("font.outline", "-1"),
+            ("font.glow", "wide"),
+            ("font.glow_color", "#fff"),
+            ("font.outline_color", "#gg0000"),
+        ] {
+            assert!(set(&mut theme, &mut editor, key, value).is_err(), "{} = {}", key, value);
+        }
+    }
+}
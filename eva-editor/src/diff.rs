@@ -0,0 +1,316 @@
+use std::path::Path;
+use crate::editor::Buffer;
+use crate::highlight;
+use crate::markers::{Marker, MarkerKind};
+
+// 每段改动前后保留的上下文行数
+const CONTEXT_LINES: usize = 3;
+
+// 逐行比较的一步：两边相同、只在旧文本里、只在新文本里，带各自的行下标
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+// 统一格式（diff -u）的行差异，old_name 和 new_name 写在开头两行
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let edits = edits(&old_lines, &new_lines);
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+
+    let changes: Vec<usize> = (0..edits.len()).filter(|&i| !matches!(edits[i], Edit::Equal(..))).collect();
+    let mut next = 0;
+    while next < changes.len() {
+        // 两处改动之间的相同行不超过两倍上下文时并进同一段
+        let first = changes[next];
+        let mut last = first;
+        while next < changes.len() && changes[next] <= last + 2 * CONTEXT_LINES {
+            last = changes[next];
+            next += 1;
+        }
+        let start = first.saturating_sub(CONTEXT_LINES);
+        let end = (last + 1 + CONTEXT_LINES).min(edits.len());
+
+        let old_before = edits[..start].iter().filter(|e| !matches!(e, Edit::Insert(_))).count();
+        let new_before = edits[..start].iter().filter(|e| !matches!(e, Edit::Delete(_))).count();
+        let hunk = &edits[start..end];
+        let old_count = hunk.iter().filter(|e| !matches!(e, Edit::Insert(_))).count();
+        let new_count = hunk.iter().filter(|e| !matches!(e, Edit::Delete(_))).count();
+        // 一侧没有行时，起始行号指向它前面那一行
+        let old_start = if old_count == 0 { old_before } else { old_before + 1 };
+        let new_start = if new_count == 0 { new_before } else { new_before + 1 };
+        out.push_str(&format!("@@ -{},{} +{},{} @@\n", old_start, old_count, new_start, new_count));
+        for edit in hunk {
+            let (sign, line) = match *edit {
+                Edit::Equal(i, _) => (' ', old_lines[i]),
+                Edit::Delete(i) => ('-', old_lines[i]),
+                Edit::Insert(j) => ('+', new_lines[j]),
+            };
+            out.push(sign);
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+// 放差异的只读缓冲区，按 diff 语法高亮
+pub fn diff_buffer(old: &str, new: &str, old_name: &str, new_name: &str) -> Buffer {
+    let mut buffer = Buffer::from_content(unified(old, new, old_name, new_name), None);
+    buffer.syntax = highlight::detect_syntax(Path::new("changes.diff"), "");
+    buffer.readonly = true;
+    buffer
+}
+
+// 编辑距离超过这么多时不再找最短的编辑脚本，整个文件当作一段替换
+const MAX_EDIT_DISTANCE: usize = 10_000;
+// 改动标记每次编辑后都要重新比较，放弃得更早
+const MAX_MARKED_DISTANCE: usize = 1_000;
+
+// 行号栏上相对 old 的改动：new 里改过和新加的行，只删不加的标在删掉的地方后面那一行。
+// 按 \n 切行，和编辑器的行号一致
+pub fn changed_lines(old: &str, new: &str) -> Vec<Marker> {
+    let old_lines: Vec<&str> = old.split('\n').collect();
+    let new_lines: Vec<&str> = new.split('\n').collect();
+    let edits = edits_within(&old_lines, &new_lines, MAX_MARKED_DISTANCE);
+    let mut markers = Vec::new();
+    let mut next_line = 0;
+    for run in edits.chunk_by(|x, y| matches!(x, Edit::Equal(..)) == matches!(y, Edit::Equal(..))) {
+        if let Some(Edit::Equal(_, y)) = run.last() {
+            next_line = y + 1;
+            continue;
+        }
+        // 一段改动里新加的行先和删掉的行一一对上，对上的算改过，多出来的算新加
+        let deleted = run.iter().filter(|e| matches!(e, Edit::Delete(_))).count();
+        let inserted: Vec<usize> = run.iter().filter_map(|e| if let Edit::Insert(y) = e { Some(*y) } else { None }).collect();
+        for (i, &line) in inserted.iter().enumerate() {
+            let kind = if i < deleted { MarkerKind::Modified } else { MarkerKind::Added };
+            markers.push(Marker { line, kind });
+            next_line = line + 1;
+        }
+        if inserted.is_empty() {
+            markers.push(Marker { line: next_line.min(new_lines.len() - 1), kind: MarkerKind::Deleted });
+        }
+    }
+    markers
+}
+
+fn edits(a: &[&str], b: &[&str]) -> Vec<Edit> {
+    edits_within(a, b, MAX_EDIT_DISTANCE)
+}
+
+fn edits_within(a: &[&str], b: &[&str], limit: usize) -> Vec<Edit> {
+    let mut edits = Vec::with_capacity(a.len().max(b.len()));
+    if !diff_range(a, b, 0, 0, limit, &mut edits) {
+        edits.clear();
+        edits.extend((0..a.len()).map(Edit::Delete));
+        edits.extend((0..b.len()).map(Edit::Insert));
+    }
+    edits
+}
+
+// Myers 线性空间的分治：找出最短编辑路径正中间的那段相同的行（middle snake），两边各自递归。
+// a_start、b_start 是 a、b 在整个文本里的起始行；编辑距离超过 limit 时返回 false
+fn diff_range(a: &[&str], b: &[&str], a_start: usize, b_start: usize, limit: usize, edits: &mut Vec<Edit>) -> bool {
+    // 先去掉相同的开头和结尾
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| x == y).count();
+    edits.extend((0..prefix).map(|i| Edit::Equal(a_start + i, b_start + i)));
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    let (a_mid_start, b_mid_start) = (a_start + prefix, b_start + prefix);
+
+    if a_mid.is_empty() {
+        edits.extend((0..b_mid.len()).map(|j| Edit::Insert(b_mid_start + j)));
+    } else if b_mid.is_empty() {
+        edits.extend((0..a_mid.len()).map(|i| Edit::Delete(a_mid_start + i)));
+    } else {
+        // 去掉首尾后两边都不空，编辑距离至少是 2，两半都比整体小
+        let Some((x, y, u, v)) = middle_snake(a_mid, b_mid, limit) else {
+            return false;
+        };
+        if !diff_range(&a_mid[..x], &b_mid[..y], a_mid_start, b_mid_start, limit, edits) {
+            return false;
+        }
+        edits.extend((0..u - x).map(|i| Edit::Equal(a_mid_start + x + i, b_mid_start + y + i)));
+        if !diff_range(&a_mid[u..], &b_mid[v..], a_mid_start + u, b_mid_start + v, limit, edits) {
+            return false;
+        }
+    }
+
+    let (a_end, b_end) = (a_start + a.len() - suffix, b_start + b.len() - suffix);
+    edits.extend((0..suffix).map(|i| Edit::Equal(a_end + i, b_end + i)));
+    true
+}
+
+// 从两头同时按编辑距离一轮轮往里走，相遇时返回那段相同的行在 a、b 里的起止 (x, y, u, v)。
+// 只记当前一轮每条对角线走到的最远位置，空间是 O(n + m)
+fn middle_snake(a: &[&str], b: &[&str], limit: usize) -> Option<(usize, usize, usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    // 两头各走编辑距离的一半
+    let max = ((n + m + 1) / 2).min(limit.div_ceil(2) as isize);
+    let offset = max + 1;
+    // forward[k] 是从起点在对角线 k = x - y 上走到的最远 x；backward 同样，但从终点倒着数
+    let mut forward = vec![0isize; 2 * max as usize + 3];
+    let mut backward = vec![0isize; 2 * max as usize + 3];
+
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let index = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && forward[index - 1] < forward[index + 1]) {
+                forward[index + 1]
+            } else {
+                forward[index - 1] + 1
+            };
+            let mut y = x - k;
+            let (start_x, start_y) = (x, y);
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[index] = x;
+            // 总编辑距离是奇数时在正向这一步相遇，倒着的对角线是 delta - k
+            let reverse_k = delta - k;
+            if odd && reverse_k.abs() < d && x + backward[(reverse_k + offset) as usize] >= n {
+                return Some((start_x as usize, start_y as usize, x as usize, y as usize));
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let index = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && backward[index - 1] < backward[index + 1]) {
+                backward[index + 1]
+            } else {
+                backward[index - 1] + 1
+            };
+            let mut y = x - k;
+            let (start_x, start_y) = (x, y);
+            while x < n && y < m && a[(n - 1 - x) as usize] == b[(m - 1 - y) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[index] = x;
+            let forward_k = delta - k;
+            if !odd && forward_k.abs() <= d && x + forward[(forward_k + offset) as usize] >= n {
+                return Some(((n - x) as usize, (m - y) as usize, (n - start_x) as usize, (m - start_y) as usize));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 按编辑脚本从旧文本拼出新文本，顺带检查下标是按顺序一行行走过去的
+    fn apply(a: &[&str], b: &[&str], edits: &[Edit]) -> Vec<String> {
+        let (mut i, mut j) = (0, 0);
+        let mut out = Vec::new();
+        for edit in edits {
+            match *edit {
+                Edit::Equal(x, y) => {
+                    assert_eq!((x, y), (i, j));
+                    assert_eq!(a[x], b[y]);
+                    out.push(a[x].to_string());
+                    i += 1;
+                    j += 1;
+                }
+                Edit::Delete(x) => {
+                    assert_eq!(x, i);
+                    i += 1;
+                }
+                Edit::Insert(y) => {
+                    assert_eq!(y, j);
+                    out.push(b[y].to_string());
+                    j += 1;
+                }
+            }
+        }
+        assert_eq!((i, j), (a.len(), b.len()));
+        out
+    }
+
+    fn distance(edits: &[Edit]) -> usize {
+        edits.iter().filter(|e| !matches!(e, Edit::Equal(..))).count()
+    }
+
+    // 动态规划算出的最短编辑距离，用来对照
+    fn shortest(a: &[&str], b: &[&str]) -> usize {
+        let mut row: Vec<usize> = (0..=b.len()).collect();
+        for i in 1..=a.len() {
+            let mut previous = row[0];
+            row[0] = i;
+            for j in 1..=b.len() {
+                let current = row[j];
+                row[j] = if a[i - 1] == b[j - 1] { previous } else { row[j].min(row[j - 1]) + 1 };
+                previous = current;
+            }
+        }
+        row[b.len()]
+    }
+
+    #[test]
+    fn changed_lines_against_the_saved_text() {
+        let changes = |old: &str, new: &str| -> Vec<(usize, MarkerKind)> {
+            changed_lines(old, new).iter().map(|m| (m.line, m.kind)).collect()
+        };
+        assert!(changes("a\nb\n", "a\nb\n").is_empty());
+        assert_eq!(
+            changes("a\nb\nc\nd\ne\n", "a\nB\nc\ne\nf\n"),
+            [(1, MarkerKind::Modified), (3, MarkerKind::Deleted), (4, MarkerKind::Added)]
+        );
+        // 改一行再接着加一行：第一行算改过，第二行算新加
+        assert_eq!(changes("a\nb", "a\nx\ny"), [(1, MarkerKind::Modified), (2, MarkerKind::Added)]);
+        // 删掉最后几行时标在剩下的最后一行
+        assert_eq!(changes("a\nb\nc", "a"), [(0, MarkerKind::Deleted)]);
+        assert_eq!(changes("", "a\n"), [(0, MarkerKind::Added)]);
+    }
+
+    #[test]
+    fn finds_shortest_edit_scripts() {
+        let mut seed: u64 = 7;
+        let mut next = |n: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % n
+        };
+        let words = ["a", "b", "c", "d"];
+        for _ in 0..2000 {
+            let a: Vec<&str> = (0..next(12)).map(|_| words[next(4) as usize]).collect();
+            let b: Vec<&str> = (0..next(12)).map(|_| words[next(4) as usize]).collect();
+            let edits = edits(&a, &b);
+            assert_eq!(apply(&a, &b, &edits), b);
+            assert_eq!(distance(&edits), shortest(&a, &b), "{:?} -> {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn large_rewrite_falls_back_to_one_replacement() {
+        let a: Vec<String> = (0..200).map(|i| format!("old {}", i)).collect();
+        let b: Vec<String> = (0..200).map(|i| format!("new {}", i)).collect();
+        let a: Vec<&str> = a.iter().map(String::as_str).collect();
+        let b: Vec<&str> = b.iter().map(String::as_str).collect();
+        let edits = edits_within(&a, &b, 100);
+        assert_eq!(apply(&a, &b, &edits), b);
+        assert!(edits[..200].iter().all(|e| matches!(e, Edit::Delete(_))));
+
+        // 超过上限时即使有相同的行也整个替换
+        let mut c = b.clone();
+        c[100] = a[100];
+        let edits = edits_within(&a, &c, 100);
+        assert_eq!(distance(&edits), 400);
+        assert_eq!(distance(&edits_within(&a, &c, 1000)), 398);
+    }
+
+    #[test]
+    fn unified_output() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        let new = "1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n";
+        let expected = "--- old\n+++ new\n@@ -1,6 +1,6 @@\n 1\n 2\n-3\n+three\n 4\n 5\n 6\n@@ -8,3 +8,4 @@\n 8\n 9\n 10\n+11\n";
+        assert_eq!(unified(old, new, "old", "new"), expected);
+        assert_eq!(unified(old, old, "old", "new"), "--- old\n+++ new\n");
+    }
+}
@@ -1,58 +1,263 @@
-use std::path::PathBuf;
-use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use anyhow::{bail, Result};
+use log::info;
 use syntect::parsing::{SyntaxSet, SyntaxReference};
 use syntect::highlighting::ThemeSet;
+use crate::autosave::AutoSave;
+use crate::diff;
+use crate::encoding::{self, LineEnding, TextEncoding};
+use crate::highlight;
+use crate::markers::{MarkerLane, Markers};
+use crate::save::{self, SaveOptions};
+use crate::view::{Direction, PaneTree, SplitDirection, View};
+use crate::watcher::{DiskState, DiskStatus};
+
+// 缓冲区的身份，下标会随关闭、拖动重排变化，它不会
+static NEXT_BUFFER_ID: AtomicU64 = AtomicU64::new(1);
+
+// 全局递增的内容版本号，不同缓冲区之间也不会重复，缓存可以直接拿它判断是否失效
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(1);
+
+fn next_version() -> u64 {
+    NEXT_VERSION.fetch_add(1, Ordering::Relaxed)
+}
+
+// 跳到某一行时，让它上面留出这么多行
+const GOTO_CONTEXT_LINES: usize = 5;
+// 缓冲区记住最近这么多次修改涉及的行，更早的缓存只能整个作废
+const EDIT_LOG_LEN: usize = 64;
+
+// 一次或几次修改涉及的行：first 到 last（修改后的行号，含两端），delta 是增加的行数
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LineChange {
+    pub first: usize,
+    pub last: usize,
+    pub delta: isize,
+}
+
+impl LineChange {
+    // 合并先后两次修改。先改的那段可能被后面的修改挪动，last 按挪动的总行数放宽
+    fn merge(self, later: LineChange) -> LineChange {
+        LineChange {
+            first: self.first.min(later.first),
+            last: self.last.max(later.last).saturating_add(later.delta.unsigned_abs()),
+            delta: self.delta + later.delta,
+        }
+    }
+}
+
+// 缩进设置，打开文件时从内容推断
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Indentation {
+    pub use_tabs: bool,
+    pub width: usize,
+}
+
+impl Default for Indentation {
+    fn default() -> Self {
+        Indentation { use_tabs: false, width: 4 }
+    }
+}
+
+impl Indentation {
+    // 统计以制表符和空格开头的行；空格缩进取最常见的相邻缩进差
+    pub fn detect(content: &str) -> Self {
+        let mut tab_lines = 0;
+        let mut space_lines = 0;
+        let mut widths = [0usize; 9];
+        let mut previous = 0;
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            if line.starts_with('\t') {
+                tab_lines += 1;
+                continue;
+            }
+            let indent = line.len() - line.trim_start_matches(' ').len();
+            if indent > 0 {
+                space_lines += 1;
+            }
+            let delta = indent.abs_diff(previous);
+            if (2..=8).contains(&delta) {
+                widths[delta] += 1;
+            }
+            previous = indent;
+        }
+
+        let width = (2..=8).max_by_key(|&w| (widths[w], w == 4)).filter(|&w| widths[w] > 0).unwrap_or(4);
+        Indentation { use_tabs: tab_lines > space_lines, width }
+    }
+
+    // 按一次 Tab 插入的文本
+    pub fn unit(&self) -> String {
+        if self.use_tabs {
+            "\t".to_string()
+        } else {
+            " ".repeat(self.width)
+        }
+    }
+}
 
 pub struct Buffer {
+    pub id: u64,
     pub content: String,
     pub file_path: Option<PathBuf>,
     pub syntax: Option<&'static SyntaxReference>,
     pub modified: bool,
+    // 内容每次修改后更新
+    pub version: u64,
+    pub markers: Markers,
+    pub indentation: Indentation,
+    // 固定的标签页排在最前面，不随标签栏滚动
+    pub pinned: bool,
+    // 只读时所有编辑操作都不生效
+    pub readonly: bool,
+    // 打开时识别出的编码和换行符，保存时照原样写回
+    pub encoding: TextEncoding,
+    pub line_ending: LineEnding,
+    // 上次读入或保存时文件的状态，用来分辨外部修改
+    pub disk_state: Option<DiskState>,
+    pub disk_status: DiskStatus,
+    // 上次读入或保存时的内容，和它比较得出行号栏上的改动标记；不是从文件读入的缓冲区没有
+    saved_content: Option<String>,
+    // 改动标记是按哪个版本算的，内容或者 saved_content 变了之后不一致
+    changes_version: Option<u64>,
+    // 最近的修改：版本和涉及的行，高亮缓存据此只丢掉改动之后的部分
+    edits: VecDeque<(u64, LineChange)>,
+    // 从记录里挤掉的最新版本，比它旧的版本查不到修改了哪里
+    forgotten: u64,
 }
 
 impl Buffer {
     pub fn new() -> Self {
         Buffer {
+            id: NEXT_BUFFER_ID.fetch_add(1, Ordering::Relaxed),
             content: String::new(),
             file_path: None,
             syntax: None,
             modified: false,
+            version: next_version(),
+            markers: Markers::default(),
+            indentation: Indentation::default(),
+            pinned: false,
+            readonly: false,
+            encoding: TextEncoding::default(),
+            line_ending: LineEnding::default(),
+            disk_state: None,
+            disk_status: DiskStatus::default(),
+            saved_content: None,
+            changes_version: None,
+            edits: VecDeque::new(),
+            forgotten: 0,
         }
     }
 
+    // 状态先于内容读：中间文件又被改了的话，下一个事件会让它再读一次
     pub fn from_file(path: PathBuf) -> Result<Self> {
-        let content = std::fs::read_to_string(&path)?;
-        Ok(Buffer {
-            content,
-            file_path: Some(path),
-            syntax: None,
-            modified: false,
-        })
+        let disk_state = DiskState::read(&path);
+        let bytes = std::fs::read(&path)?;
+        let mut buffer = Buffer { disk_state, ..Buffer::from_bytes(&bytes, Some(path)) };
+        buffer.mark_saved();
+        Ok(buffer)
+    }
+
+    // 识别编码和换行符，内容里的换行统一成 \n
+    pub fn from_bytes(bytes: &[u8], file_path: Option<PathBuf>) -> Self {
+        let (text_encoding, content) = TextEncoding::decode(bytes);
+        let line_ending = LineEnding::detect(&content);
+        let content = encoding::normalize_line_endings(content);
+        Buffer { encoding: text_encoding, line_ending, ..Buffer::from_content(content, file_path) }
+    }
+
+    // 按缓冲区的换行符和编码得到要写进文件的字节
+    pub fn encoded(&self) -> Result<Vec<u8>> {
+        self.encoding.encode(&self.line_ending.apply(&self.content))
     }
 
-    pub fn save(&mut self) -> Result<()> {
+    // 语法和缩进从路径和内容推断；没有路径时（比如从标准输入读入）只看首行
+    pub fn from_content(content: String, file_path: Option<PathBuf>) -> Self {
+        let syntax = highlight::detect_syntax(file_path.as_deref().unwrap_or(Path::new("")), &content);
+        let indentation = Indentation::detect(&content);
+        Buffer { content, file_path, syntax, indentation, ..Buffer::new() }
+    }
+
+    pub fn save(&mut self, options: &SaveOptions) -> Result<()> {
         if let Some(path) = &self.file_path {
-            std::fs::write(path, &self.content)?;
+            let target = save::write_atomic(path, &self.encoded()?, options)?;
+            self.disk_state = DiskState::read(&target);
+            self.disk_status = DiskStatus::Synced;
             self.modified = false;
+            info!("Saved {}", path.display());
+            self.mark_saved();
         }
         Ok(())
     }
 
     pub fn insert(&mut self, position: usize, text: &str) {
         if position <= self.content.len() {
+            let line = self.content[..position].matches('\n').count();
+            let count = text.matches('\n').count();
+            self.markers.insert_lines(line, count);
             self.content.insert_str(position, text);
             self.modified = true;
+            self.record_edit(LineChange { first: line, last: line + count, delta: count as isize });
         }
     }
 
     pub fn delete(&mut self, start: usize, end: usize) {
         if start < end && end <= self.content.len() {
+            let line = self.content[..start].matches('\n').count();
+            let count = self.content[start..end].matches('\n').count();
+            // 删的是整行时这几行连同标记一起消失，否则后面的行并入 line
+            let whole_lines = (start == 0 || self.content[..start].ends_with('\n')) && self.content[..end].ends_with('\n');
+            self.markers.remove_lines(if whole_lines { line } else { line + 1 }, count);
             self.content.replace_range(start..end, "");
             self.modified = true;
+            self.record_edit(LineChange { first: line, last: line, delta: -(count as isize) });
+        }
+    }
+
+    // 现在的内容就是磁盘上的内容，改动标记从这里重新算
+    fn mark_saved(&mut self) {
+        self.saved_content = Some(self.content.clone());
+        self.changes_version = None;
+    }
+
+    // 内容变过的话重新和上次保存的内容比较，换掉行号栏上的改动标记
+    pub fn update_changes(&mut self) {
+        if self.changes_version == Some(self.version) {
+            return;
+        }
+        self.changes_version = Some(self.version);
+        let changes = match &self.saved_content {
+            Some(saved) => diff::changed_lines(saved, &self.content),
+            None => Vec::new(),
+        };
+        self.markers.replace_lane(MarkerLane::Vcs, changes);
+    }
+
+    // 换一个新版本号，记下这次修改涉及的行
+    fn record_edit(&mut self, change: LineChange) {
+        self.version = next_version();
+        if self.edits.len() == EDIT_LOG_LEN {
+            if let Some((version, _)) = self.edits.pop_front() {
+                self.forgotten = version;
+            }
         }
+        self.edits.push_back((self.version, change));
+    }
+
+    // 版本 since 之后的修改合在一起涉及的行；记录不够早或者没有修改时返回 None
+    pub fn changes_since(&self, since: u64) -> Option<LineChange> {
+        if since < self.forgotten {
+            return None;
+        }
+        self.edits.iter().filter(|(version, _)| *version > since).map(|(_, change)| *change).reduce(LineChange::merge)
     }
 }
 
+#[derive(Clone, Copy)]
 pub struct Cursor {
     pub position: usize,
     pub line: usize,
@@ -69,32 +274,325 @@ impl Default for Cursor {
     }
 }
 
+// cursor、selection_anchor、scroll_line 和 active_buffer 是获得焦点的窗格的实时状态，
+// 其他窗格的状态存在 panes 里
 pub struct Editor {
     pub buffers: Vec<Buffer>,
     pub active_buffer: usize,
     pub cursor: Cursor,
-    pub syntax_set: SyntaxSet,
+    // 选区的另一端（字节偏移），与光标位置相同或为 None 时没有选区
+    pub selection_anchor: Option<usize>,
+    // 改写模式：输入的字符覆盖光标后的字符
+    pub overwrite: bool,
+    // 视口顶部所在的行，可以是小数（平滑滚动、缩放时保持光标位置）
+    pub scroll_line: f32,
+    // 当前搜索的匹配（活动缓冲区里的字节范围），由搜索填写，缩略图上标出
+    pub search_matches: Vec<Range<usize>>,
+    pub syntax_set: &'static SyntaxSet,
     pub theme_set: ThemeSet,
+    // 命令行上给出的目录
+    pub workspace_root: Option<PathBuf>,
+    pub save_options: SaveOptions,
+    pub autosave: AutoSave,
+    // 缓冲区 id，最近使用的在前
+    mru: Vec<u64>,
+    // Ctrl+Tab 轮换中走到的 mru 下标，松开 Ctrl 后才更新顺序
+    mru_cycle: Option<usize>,
+    // 切走的缓冲区的光标、选区和滚动位置，切回来时恢复
+    parked: HashMap<u64, View>,
+    pub panes: PaneTree,
 }
 
 impl Editor {
     pub fn new() -> Self {
+        let buffer = Buffer::new();
         Editor {
-            buffers: vec![Buffer::new()],
+            mru: vec![buffer.id],
+            mru_cycle: None,
+            parked: HashMap::new(),
+            panes: PaneTree::new(View::new(buffer.id)),
+            buffers: vec![buffer],
             active_buffer: 0,
             cursor: Cursor::default(),
-            syntax_set: SyntaxSet::load_defaults_newlines(),
+            selection_anchor: None,
+            overwrite: false,
+            scroll_line: 0.0,
+            search_matches: Vec::new(),
+            syntax_set: highlight::syntax_set(),
             theme_set: ThemeSet::load_defaults(),
+            workspace_root: None,
+            save_options: SaveOptions::default(),
+            autosave: AutoSave::default(),
         }
     }
 
+    // 已经打开的文件直接切过去；不存在的文件打开成空缓冲区，保存时创建
     pub fn open_file(&mut self, path: PathBuf) -> Result<()> {
-        let buffer = Buffer::from_file(path)?;
-        self.buffers.push(buffer);
-        self.active_buffer = self.buffers.len() - 1;
+        if let Some(index) = self.buffers.iter().position(|b| b.file_path.as_ref() == Some(&path)) {
+            self.switch_to(index);
+            return Ok(());
+        }
+        let buffer = if path.exists() {
+            Buffer::from_file(path)?
+        } else {
+            info!("New file: {}", path.display());
+            Buffer::from_content(String::new(), Some(path))
+        };
+        self.open_buffer(buffer);
         Ok(())
     }
 
+    // 打开并切到新缓冲区；启动时那个没动过的空白缓冲区会被它替换掉
+    pub fn open_buffer(&mut self, buffer: Buffer) {
+        let scratch = match self.buffers.as_slice() {
+            [only] => only.file_path.is_none() && !only.modified && only.content.is_empty(),
+            _ => false,
+        };
+        self.mru.push(buffer.id);
+        self.buffers.push(buffer);
+        self.switch_to(self.buffers.len() - 1);
+        if scratch {
+            self.close_buffer(0);
+        }
+    }
+
+    pub fn index_of(&self, id: u64) -> Option<usize> {
+        self.buffers.iter().position(|b| b.id == id)
+    }
+
+    // 点击标签页等直接切换
+    pub fn switch_to(&mut self, index: usize) {
+        self.mru_cycle = None;
+        self.activate(index, true);
+    }
+
+    // touch_mru 为假时不改最近使用顺序（Ctrl+Tab 轮换途中）
+    fn activate(&mut self, index: usize, touch_mru: bool) {
+        if index >= self.buffers.len() {
+            return;
+        }
+        if index != self.active_buffer {
+            self.park_view();
+            self.active_buffer = index;
+            self.restore_view();
+        }
+        if touch_mru {
+            let id = self.buffers[index].id;
+            self.mru.retain(|&other| other != id);
+            self.mru.insert(0, id);
+        }
+    }
+
+    fn park_view(&mut self) {
+        if let Some(view) = self.live_view() {
+            self.parked.insert(view.buffer_id, view);
+        }
+    }
+
+    fn restore_view(&mut self) {
+        let Some(id) = self.buffers.get(self.active_buffer).map(|b| b.id) else {
+            return;
+        };
+        let view = self.parked.remove(&id).unwrap_or_else(|| View::new(id));
+        // 搜索结果属于切走的缓冲区
+        self.search_matches.clear();
+        self.load_view(view);
+    }
+
+    // 获得焦点的窗格的实时状态
+    fn live_view(&self) -> Option<View> {
+        let buffer = self.buffers.get(self.active_buffer)?;
+        Some(View {
+            buffer_id: buffer.id,
+            cursor: self.cursor,
+            selection_anchor: self.selection_anchor,
+            scroll_line: self.scroll_line,
+        })
+    }
+
+    // 把视图装进实时状态；缓冲区可能在别的窗格里改过，位置要夹回有效范围
+    fn load_view(&mut self, view: View) {
+        let index = self.index_of(view.buffer_id).unwrap_or(0);
+        if index != self.active_buffer {
+            // 搜索结果属于切走的缓冲区
+            self.search_matches.clear();
+        }
+        self.active_buffer = index;
+        let content = self.buffers.get(index).map_or("", |b| b.content.as_str());
+        let clamp = |position: usize| {
+            let mut position = position.min(content.len());
+            while !content.is_char_boundary(position) {
+                position -= 1;
+            }
+            position
+        };
+        self.cursor = cursor_at(content, clamp(view.cursor.position));
+        self.selection_anchor = view.selection_anchor.map(clamp);
+        self.scroll_line = view.scroll_line;
+    }
+
+    // 窗格的视图，获得焦点的那个取实时状态
+    pub fn view(&self, id: usize) -> Option<View> {
+        if id == self.panes.focused() {
+            self.live_view()
+        } else {
+            self.panes.view(id).copied()
+        }
+    }
+
+    pub fn focus_view(&mut self, id: usize) {
+        let focused = self.panes.focused();
+        if id == focused || self.panes.view(id).is_none() {
+            return;
+        }
+        self.mru_cycle = None;
+        if let (Some(live), Some(slot)) = (self.live_view(), self.panes.view_mut(focused)) {
+            *slot = live;
+        }
+        self.panes.set_focused(id);
+        if let Some(view) = self.panes.view(id).copied() {
+            self.load_view(view);
+            self.activate(self.active_buffer, true);
+        }
+    }
+
+    // 把获得焦点的窗格一分为二，新窗格显示同一个缓冲区的同一位置并获得焦点
+    pub fn split_view(&mut self, direction: SplitDirection) {
+        let Some(live) = self.live_view() else {
+            return;
+        };
+        if let Some(id) = self.panes.split(self.panes.focused(), direction, live) {
+            self.focus_view(id);
+        }
+    }
+
+    // 关掉获得焦点的窗格，只剩一个时不关
+    pub fn close_view(&mut self) {
+        if let Some(next) = self.panes.close(self.panes.focused()) {
+            if let Some(view) = self.panes.view(next).copied() {
+                self.load_view(view);
+            }
+        }
+    }
+
+    pub fn focus_direction(&mut self, direction: Direction) {
+        if let Some(id) = self.panes.neighbor(self.panes.focused(), direction) {
+            self.focus_view(id);
+        }
+    }
+
+    // 和相邻窗格交换位置，焦点跟着视图走
+    pub fn swap_view(&mut self, direction: Direction) {
+        let focused = self.panes.focused();
+        if let Some(id) = self.panes.neighbor(focused, direction) {
+            self.panes.swap(focused, id);
+        }
+    }
+
+    pub fn resize_view(&mut self, direction: Direction) {
+        self.panes.resize(self.panes.focused(), direction);
+    }
+
+    // 滚动任一窗格，不改变焦点
+    pub fn scroll_view(&mut self, id: usize, lines: f32) {
+        if id == self.panes.focused() {
+            return self.scroll_by(lines);
+        }
+        let buffers = &self.buffers;
+        if let Some(view) = self.panes.view_mut(id) {
+            let line_count = buffers.iter().find(|b| b.id == view.buffer_id).map_or(1, |b| b.content.matches('\n').count() + 1);
+            view.scroll_line = (view.scroll_line + lines).clamp(0.0, line_count.saturating_sub(1) as f32);
+        }
+    }
+
+    // 关掉活动缓冲区时切到最近使用的那个；最后一个关掉后留一个空白缓冲区
+    pub fn close_buffer(&mut self, index: usize) {
+        if index >= self.buffers.len() {
+            return;
+        }
+        let closing_active = index == self.active_buffer;
+        let buffer = self.buffers.remove(index);
+        self.mru.retain(|&id| id != buffer.id);
+        self.parked.remove(&buffer.id);
+        self.mru_cycle = None;
+        if self.buffers.is_empty() {
+            let buffer = Buffer::new();
+            self.mru.push(buffer.id);
+            self.buffers.push(buffer);
+        }
+
+        // 其他窗格里显示着这个缓冲区的，换成最近使用的那个
+        let fallback = self.mru.first().copied().unwrap_or(0);
+        let focused = self.panes.focused();
+        for (id, view) in self.panes.views_mut() {
+            if id != focused && view.buffer_id == buffer.id {
+                *view = View::new(fallback);
+            }
+        }
+
+        if closing_active {
+            self.active_buffer = self.index_of(fallback).unwrap_or(0);
+            self.restore_view();
+        } else if index < self.active_buffer {
+            self.active_buffer -= 1;
+        }
+    }
+
+    // 拖动重排，固定的和未固定的各自留在自己的区段里；返回实际落到的下标
+    pub fn move_buffer(&mut self, from: usize, to: usize) -> usize {
+        if from >= self.buffers.len() {
+            return from;
+        }
+        let pinned_count = self.buffers.iter().filter(|b| b.pinned).count();
+        let to = if self.buffers[from].pinned {
+            to.min(pinned_count - 1)
+        } else {
+            to.clamp(pinned_count, self.buffers.len() - 1)
+        };
+        if to == from {
+            return from;
+        }
+
+        let active_id = self.buffers[self.active_buffer].id;
+        let buffer = self.buffers.remove(from);
+        self.buffers.insert(to, buffer);
+        self.active_buffer = self.index_of(active_id).unwrap_or(0);
+        to
+    }
+
+    // 固定后移到固定区段的末尾，取消固定后移到未固定区段的开头
+    pub fn toggle_pin(&mut self, index: usize) {
+        let Some(buffer) = self.buffers.get_mut(index) else {
+            return;
+        };
+        buffer.pinned = !buffer.pinned;
+        let pinned = buffer.pinned;
+        let pinned_count = self.buffers.iter().filter(|b| b.pinned).count();
+        let target = if pinned { pinned_count - 1 } else { pinned_count };
+        self.move_buffer(index, target);
+    }
+
+    // Ctrl+Tab：按最近使用顺序往后（Shift 时往前）走一步
+    pub fn cycle_mru(&mut self, backward: bool) {
+        let count = self.mru.len();
+        if count < 2 {
+            return;
+        }
+        let current = self.mru_cycle.unwrap_or(0);
+        let next = if backward { (current + count - 1) % count } else { (current + 1) % count };
+        self.mru_cycle = Some(next);
+        if let Some(index) = self.index_of(self.mru[next]) {
+            self.activate(index, false);
+        }
+    }
+
+    // 松开 Ctrl：轮换停下的缓冲区成为最近使用的
+    pub fn finish_mru_cycle(&mut self) {
+        if self.mru_cycle.take().is_some() {
+            self.activate(self.active_buffer, true);
+        }
+    }
+
     pub fn get_active_buffer(&self) -> Option<&Buffer> {
         self.buffers.get(self.active_buffer)
     }
@@ -103,58 +601,459 @@ impl Editor {
         self.buffers.get_mut(self.active_buffer)
     }
 
-    pub fn move_cursor(&mut self, position: usize) {
-        // 首先获取必要的信息
-        let (content_len, content) = if let Some(buffer) = self.get_active_buffer() {
-            (buffer.content.len(), buffer.content.clone())
-        } else {
+    // 活动缓冲区的行数，末尾换行后的空行也算
+    pub fn line_count(&self) -> usize {
+        self.get_active_buffer().map_or(1, |b| b.content.matches('\n').count() + 1)
+    }
+
+    // 滚动 lines 行，最多滚到最后一行停在视口顶部
+    pub fn scroll_by(&mut self, lines: f32) {
+        self.set_scroll(self.scroll_line + lines);
+    }
+
+    pub fn set_scroll(&mut self, line: f32) {
+        let max = self.line_count().saturating_sub(1) as f32;
+        self.scroll_line = line.clamp(0.0, max);
+    }
+
+    // 跳到第 line 行第 column 列（都从 1 开始，按字符数），超出范围时停在最近的位置
+    pub fn goto(&mut self, line: usize, column: usize) {
+        let Some(buffer) = self.get_active_buffer() else {
             return;
         };
+        let position = position_at(&buffer.content, line.saturating_sub(1), column.saturating_sub(1));
+        self.set_cursor(position, false);
+        self.set_scroll(self.cursor.line.saturating_sub(GOTO_CONTEXT_LINES) as f32);
+    }
 
-        if position > content_len {
+    // 活动缓冲区是否只读
+    fn readonly(&self) -> bool {
+        self.get_active_buffer().is_some_and(|b| b.readonly)
+    }
+
+    pub fn move_cursor(&mut self, position: usize) {
+        let Some(buffer) = self.get_active_buffer() else {
+            return;
+        };
+        if position > buffer.content.len() {
             return;
         }
+        self.cursor = cursor_at(&buffer.content, position);
+    }
 
-        // 计算行和列
-        let content_before_cursor = &content[..position];
-        let line_count = content_before_cursor.matches('\n').count();
-        let column = if let Some(last_newline) = content_before_cursor.rfind('\n') {
-            content_before_cursor.len() - last_newline - 1
-        } else {
-            content_before_cursor.len()
+    // 移动光标；extend 为真时保留（没有则建立）选区锚点，否则取消选区
+    pub fn set_cursor(&mut self, position: usize, extend: bool) {
+        if !extend {
+            self.selection_anchor = None;
+        } else if self.selection_anchor.is_none() {
+            self.selection_anchor = Some(self.cursor.position);
+        }
+        self.move_cursor(position);
+    }
+
+    // 选中的字节范围
+    pub fn selection(&self) -> Option<Range<usize>> {
+        self.live_view()?.selection()
+    }
+
+    // 删除选区，返回是否删除了内容
+    pub fn delete_selection(&mut self) -> bool {
+        if self.readonly() {
+            return false;
+        }
+        let selection = match self.selection() {
+            Some(selection) => selection,
+            None => return false,
         };
+        self.selection_anchor = None;
+        self.buffer_delete(selection.start, selection.end);
+        self.move_cursor(selection.start);
+        true
+    }
 
-        // 更新光标位置
-        self.cursor.position = position;
-        self.cursor.line = line_count;
-        self.cursor.column = column;
+    // 键盘输入：有选区时先替换掉；改写模式下覆盖光标后的字符（不跨行）
+    pub fn type_text(&mut self, text: &str) {
+        if self.readonly() {
+            return;
+        }
+        if !self.delete_selection() && self.overwrite && text != "\n" {
+            let position = self.cursor.position;
+            let next = self
+                .get_active_buffer()
+                .and_then(|b| b.content[position..].chars().next())
+                .filter(|&c| c != '\n')
+                .map(|c| position + c.len_utf8());
+            if let Some(next) = next {
+                self.buffer_delete(position, next);
+            }
+        }
+        self.insert_text(text);
     }
 
-    pub fn insert_text(&mut self, text: &str) {
+    // Tab 键：按缓冲区的缩进设置插入
+    pub fn indent(&mut self) {
+        let unit = self.get_active_buffer().map(|b| b.indentation.unit()).unwrap_or_default();
+        self.type_text(&unit);
+    }
+
+    pub fn backspace(&mut self) {
+        if self.readonly() || self.delete_selection() {
+            return;
+        }
         let position = self.cursor.position;
-        if let Some(buffer) = self.get_active_buffer_mut() {
-            let old_len = buffer.content.len();
-            buffer.insert(position, text);
-            // 只有在实际插入了文本时才移动光标
-            if buffer.content.len() > old_len {
-                self.move_cursor(position + text.len());
+        if let Some(previous) = self.get_active_buffer().and_then(|b| prev_char_boundary(&b.content, position)) {
+            self.delete_text(position - previous);
+        }
+    }
+
+    pub fn delete_forward(&mut self) {
+        if self.readonly() || self.delete_selection() {
+            return;
+        }
+        let position = self.cursor.position;
+        if let Some(next) = self.get_active_buffer().and_then(|b| next_char_boundary(&b.content, position)) {
+            self.buffer_delete(position, next);
+        }
+    }
+
+    pub fn move_left(&mut self, extend: bool) {
+        if let Some(previous) = self.get_active_buffer().and_then(|b| prev_char_boundary(&b.content, self.cursor.position)) {
+            self.set_cursor(previous, extend);
+        }
+    }
+
+    pub fn move_right(&mut self, extend: bool) {
+        if let Some(next) = self.get_active_buffer().and_then(|b| next_char_boundary(&b.content, self.cursor.position)) {
+            self.set_cursor(next, extend);
+        }
+    }
+
+    pub fn move_line_start(&mut self, extend: bool) {
+        if let Some(start) = self.get_active_buffer().map(|b| line_start(&b.content, self.cursor.position)) {
+            self.set_cursor(start, extend);
+        }
+    }
+
+    pub fn move_line_end(&mut self, extend: bool) {
+        if let Some(end) = self.get_active_buffer().map(|b| line_end(&b.content, self.cursor.position)) {
+            self.set_cursor(end, extend);
+        }
+    }
+
+    // 上下移动一行，按字符数保持列
+    pub fn move_vertical(&mut self, down: bool, extend: bool) {
+        let target = match self.get_active_buffer() {
+            Some(buffer) => {
+                let content = &buffer.content;
+                let position = self.cursor.position;
+                let start = line_start(content, position);
+                let column = content[start..position].chars().count();
+                let target_start = if down {
+                    let end = line_end(content, position);
+                    if end == content.len() {
+                        return self.set_cursor(content.len(), extend);
+                    }
+                    end + 1
+                } else {
+                    if start == 0 {
+                        return self.set_cursor(0, extend);
+                    }
+                    line_start(content, start - 1)
+                };
+                let line = &content[target_start..line_end(content, target_start)];
+                target_start + line.char_indices().nth(column).map_or(line.len(), |(i, _)| i)
             }
+            None => return,
+        };
+        self.set_cursor(target, extend);
+    }
+
+    pub fn insert_text(&mut self, text: &str) {
+        if self.readonly() {
+            return;
+        }
+        let position = self.cursor.position;
+        self.selection_anchor = None;
+        // 只有在实际插入了文本时才移动光标
+        if self.buffer_insert(position, text) {
+            self.move_cursor(position + text.len());
         }
     }
 
     pub fn delete_text(&mut self, count: usize) {
+        if self.readonly() {
+            return;
+        }
         let position = self.cursor.position;
         let start = position.saturating_sub(count);
-        if let Some(buffer) = self.get_active_buffer_mut() {
-            buffer.delete(start, position);
+        if self.get_active_buffer().is_some() {
+            self.buffer_delete(start, position);
             self.move_cursor(start);
         }
     }
 
-    pub fn save_active_buffer(&mut self) -> Result<()> {
-        if let Some(buffer) = self.get_active_buffer_mut() {
-            buffer.save()?;
+    // 活动缓冲区上的插入和删除都走这里，显示同一缓冲区的其他窗格的光标跟着移动
+    fn buffer_insert(&mut self, position: usize, text: &str) -> bool {
+        let Some(buffer) = self.buffers.get_mut(self.active_buffer) else {
+            return false;
+        };
+        let old_len = buffer.content.len();
+        buffer.insert(position, text);
+        let inserted = buffer.content.len() > old_len;
+        if inserted {
+            self.shift_other_views(position, 0, text.len());
+        }
+        inserted
+    }
+
+    fn buffer_delete(&mut self, start: usize, end: usize) {
+        let Some(buffer) = self.buffers.get_mut(self.active_buffer) else {
+            return;
+        };
+        let old_len = buffer.content.len();
+        buffer.delete(start, end);
+        if buffer.content.len() < old_len {
+            self.shift_other_views(start, end - start, 0);
+        }
+    }
+
+    // 在 at 处删掉 removed 字节、插入 inserted 字节之后，修正其他窗格里和切走时存下的位置
+    fn shift_other_views(&mut self, at: usize, removed: usize, inserted: usize) {
+        let Some(buffer) = self.buffers.get(self.active_buffer) else {
+            return;
+        };
+        let shift = |position: usize| {
+            if position <= at {
+                position
+            } else if position < at + removed {
+                at
+            } else {
+                position - removed + inserted
+            }
+        };
+        let focused = self.panes.focused();
+        let mut views: Vec<&mut View> =
+            self.panes.views_mut().filter(|(id, _)| *id != focused).map(|(_, view)| view).collect();
+        views.extend(self.parked.values_mut());
+        for view in views.into_iter().filter(|view| view.buffer_id == buffer.id) {
+            view.cursor = cursor_at(&buffer.content, shift(view.cursor.position));
+            view.selection_anchor = view.selection_anchor.map(shift);
+        }
+    }
+
+    // 换行符和编码的转换在下次保存时写进文件；只读的缓冲区存不了，不转换
+    pub fn set_line_ending(&mut self, index: usize, line_ending: LineEnding) -> Result<()> {
+        let Some(buffer) = self.buffers.get_mut(index) else {
+            return Ok(());
+        };
+        if buffer.readonly {
+            bail!("Buffer is read-only");
+        }
+        if buffer.line_ending != line_ending {
+            buffer.line_ending = line_ending;
+            buffer.modified = true;
+            info!("Line endings set to {}", line_ending.name());
+        }
+        Ok(())
+    }
+
+    // 内容里有这个编码表示不了的字符时不转换
+    pub fn set_encoding(&mut self, index: usize, encoding: TextEncoding) -> Result<()> {
+        let Some(buffer) = self.buffers.get_mut(index) else {
+            return Ok(());
+        };
+        if buffer.readonly {
+            bail!("Buffer is read-only");
+        }
+        if buffer.encoding != encoding {
+            encoding.encode(&buffer.content)?;
+            buffer.encoding = encoding;
+            buffer.modified = true;
+            info!("Encoding set to {}", encoding.name());
+        }
+        Ok(())
+    }
+
+    // 状态栏上点换行符
+    pub fn toggle_line_ending(&mut self) -> Result<()> {
+        let Some(buffer) = self.get_active_buffer() else {
+            return Ok(());
+        };
+        self.set_line_ending(self.active_buffer, buffer.line_ending.toggled())
+    }
+
+    // 状态栏上点编码：跳过表示不了当前内容的编码
+    pub fn cycle_encoding(&mut self) -> Result<()> {
+        let Some(buffer) = self.get_active_buffer() else {
+            return Ok(());
+        };
+        let mut encoding = buffer.encoding.next();
+        while encoding != buffer.encoding && encoding.encode(&buffer.content).is_err() {
+            encoding = encoding.next();
+        }
+        self.set_encoding(self.active_buffer, encoding)
+    }
+
+    // 从磁盘重新读入，各窗格的光标、选区按行列留在原处，滚动位置不变
+    pub fn reload_buffer(&mut self, index: usize) -> Result<()> {
+        let Some(path) = self.buffers.get(index).and_then(|b| b.file_path.clone()) else {
+            return Ok(());
+        };
+        let fresh = Buffer::from_file(path.clone())?;
+        self.replace_content(index, fresh.content);
+        let buffer = &mut self.buffers[index];
+        buffer.encoding = fresh.encoding;
+        buffer.line_ending = fresh.line_ending;
+        buffer.disk_state = fresh.disk_state;
+        buffer.disk_status = DiskStatus::Synced;
+        buffer.modified = false;
+        buffer.mark_saved();
+        info!("Reloaded {}", path.display());
+        Ok(())
+    }
+
+    // 换上恢复出来的内容，算作未保存的修改
+    pub fn restore_buffer(&mut self, index: usize, content: String) {
+        if index < self.buffers.len() {
+            self.replace_content(index, content);
+            self.buffers[index].modified = true;
+        }
+    }
+
+    // 整个换掉缓冲区的内容，光标和选区按行列保留
+    fn replace_content(&mut self, index: usize, content: String) {
+        let buffer = &mut self.buffers[index];
+        let old = std::mem::replace(&mut buffer.content, content);
+        let delta = buffer.content.matches('\n').count() as isize - old.matches('\n').count() as isize;
+        buffer.record_edit(LineChange { first: 0, last: usize::MAX, delta });
+
+        let content = &buffer.content;
+        let last_line = content.matches('\n').count() as f32;
+        let remap = |position: usize| {
+            let cursor = cursor_at(&old, position.min(old.len()));
+            let column = old[cursor.position - cursor.column..cursor.position].chars().count();
+            position_at(content, cursor.line, column)
+        };
+        let mut views: Vec<&mut View> = self.panes.views_mut().map(|(_, view)| view).collect();
+        views.extend(self.parked.values_mut());
+        for view in views.into_iter().filter(|view| view.buffer_id == buffer.id) {
+            view.cursor = cursor_at(content, remap(view.cursor.position));
+            view.selection_anchor = view.selection_anchor.map(remap);
+            view.scroll_line = view.scroll_line.min(last_line);
+        }
+        if index == self.active_buffer {
+            self.cursor = cursor_at(content, remap(self.cursor.position));
+            self.selection_anchor = self.selection_anchor.map(remap);
+            self.scroll_line = self.scroll_line.min(last_line);
+            self.search_matches.clear();
+        }
+    }
+
+    pub fn save_buffer(&mut self, index: usize) -> Result<()> {
+        let options = self.save_options;
+        if let Some(buffer) = self.buffers.get_mut(index) {
+            if buffer.readonly {
+                bail!("Buffer is read-only");
+            }
+            buffer.save(&options)?;
         }
         Ok(())
     }
-} 
\ No newline at end of file
+}
+
+// 字节偏移处的光标，行和列从内容里数出来
+fn cursor_at(content: &str, position: usize) -> Cursor {
+    let before = &content[..position];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Cursor {
+        position,
+        line: before.matches('\n').count(),
+        column: position - line_start,
+    }
+}
+
+// 第 line 行第 column 个字符处（都从 0 开始）的字节偏移，超出范围时取最近的位置
+fn position_at(content: &str, line: usize, column: usize) -> usize {
+    let start = content.split_inclusive('\n').take(line).map(str::len).sum::<usize>();
+    // 最后一行以换行结尾时 start 落在末尾，正是末尾那个空行
+    let text = &content[start..line_end(content, start)];
+    start + text.char_indices().nth(column).map_or(text.len(), |(i, _)| i)
+}
+
+fn prev_char_boundary(content: &str, position: usize) -> Option<usize> {
+    content[..position].chars().next_back().map(|c| position - c.len_utf8())
+}
+
+fn next_char_boundary(content: &str, position: usize) -> Option<usize> {
+    content[position..].chars().next().map(|c| position + c.len_utf8())
+}
+
+fn line_start(content: &str, position: usize) -> usize {
+    content[..position].rfind('\n').map_or(0, |i| i + 1)
+}
+
+fn line_end(content: &str, position: usize) -> usize {
+    content[position..].find('\n').map_or(content.len(), |i| position + i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markers::MarkerKind;
+
+    #[test]
+    fn changes_follow_edits_until_saved() {
+        let changes = |buffer: &Buffer| -> Vec<(usize, MarkerKind)> { buffer.markers.iter().map(|m| (m.line, m.kind)).collect() };
+        let mut buffer = Buffer::from_content("a\nb\nc\n".to_string(), None);
+        // 没有读入或保存过的内容可比
+        buffer.insert(0, "x");
+        buffer.update_changes();
+        assert!(changes(&buffer).is_empty());
+
+        buffer.mark_saved();
+        buffer.markers.toggle(2, MarkerKind::Bookmark);
+        buffer.insert(3, "y\n");
+        buffer.update_changes();
+        assert_eq!(changes(&buffer), [(1, MarkerKind::Added), (3, MarkerKind::Bookmark)]);
+        let start = buffer.content.find('c').unwrap();
+        // 删掉带书签的整行，书签跟着没了
+        buffer.delete(start, start + 2);
+        buffer.update_changes();
+        assert_eq!(changes(&buffer), [(1, MarkerKind::Added), (3, MarkerKind::Deleted)]);
+
+        // 保存之后改动标记清掉，书签留着
+        buffer.mark_saved();
+        buffer.update_changes();
+        buffer.delete(0, 1);
+        buffer.insert(0, "z");
+        buffer.update_changes();
+        assert_eq!(changes(&buffer), [(0, MarkerKind::Modified)]);
+    }
+
+    #[test]
+    fn edits_move_views_parked_in_other_panes() {
+        let mut editor = Editor::new();
+        editor.open_buffer(Buffer::from_content("héllo wörld".to_string(), None));
+        editor.open_buffer(Buffer::from_content("other".to_string(), None));
+        editor.switch_to(0);
+        let first = editor.panes.focused();
+
+        // 第二个窗格把光标放在 w 上，然后切到别的缓冲区，这个位置存了起来
+        editor.split_view(SplitDirection::Horizontal);
+        editor.set_cursor("héllo ".len(), false);
+        editor.switch_to(1);
+
+        // 在第一个窗格里删掉 é
+        editor.focus_view(first);
+        editor.set_cursor(1, false);
+        editor.set_cursor(3, true);
+        assert!(editor.delete_selection());
+        assert_eq!(editor.buffers[0].content, "hllo wörld");
+
+        let second = editor.panes.views_mut().map(|(id, _)| id).find(|&id| id != first).unwrap();
+        editor.focus_view(second);
+        editor.switch_to(0);
+        assert_eq!(editor.cursor.position, "hllo ".len());
+        assert_eq!(editor.cursor.column, "hllo ".len());
+    }
+}
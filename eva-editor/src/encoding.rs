@@ -0,0 +1,334 @@
+use anyhow::{anyhow, bail, Result};
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, BIG5, EUC_KR, GBK, REPLACEMENT, SHIFT_JIS, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use log::warn;
+use crate::editor::Buffer;
+use crate::prompt::{Prompt, PromptAction};
+
+// 没有 BOM 时，偶数（或奇数）位置上零字节占到这个比例就当作 UTF-16
+const UTF16_ZERO_RATIO: f32 = 0.3;
+
+// 换行符。缓冲区里统一存成 \n，保存时再换回来
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    // 混用时取多数
+    pub fn detect(text: &str) -> Self {
+        let crlf = text.matches("\r\n").count();
+        let lf = text.matches('\n').count() - crlf;
+        if crlf > lf {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            LineEnding::Lf => LineEnding::CrLf,
+            LineEnding::CrLf => LineEnding::Lf,
+        }
+    }
+
+    // 把 \n 换成这种换行符
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            LineEnding::Lf => text.to_string(),
+            LineEnding::CrLf => text.replace('\n', "\r\n"),
+        }
+    }
+}
+
+// 去掉 \r\n 里的 \r，单独的 \r 保留
+pub fn normalize_line_endings(text: String) -> String {
+    if text.contains("\r\n") {
+        text.replace("\r\n", "\n")
+    } else {
+        text
+    }
+}
+
+// 文件的编码，以及保存时要不要写 BOM
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TextEncoding {
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+}
+
+impl Default for TextEncoding {
+    fn default() -> Self {
+        TextEncoding { encoding: UTF_8, bom: false }
+    }
+}
+
+// 在状态栏上点编码时按这个顺序轮换
+pub const ENCODING_CYCLE: [TextEncoding; 9] = [
+    TextEncoding { encoding: UTF_8, bom: false },
+    TextEncoding { encoding: UTF_8, bom: true },
+    TextEncoding { encoding: UTF_16LE, bom: true },
+    TextEncoding { encoding: UTF_16BE, bom: true },
+    TextEncoding { encoding: GBK, bom: false },
+    TextEncoding { encoding: BIG5, bom: false },
+    TextEncoding { encoding: SHIFT_JIS, bom: false },
+    TextEncoding { encoding: EUC_KR, bom: false },
+    TextEncoding { encoding: WINDOWS_1252, bom: false },
+];
+
+impl TextEncoding {
+    pub fn name(&self) -> String {
+        let name = match self.encoding.name() {
+            "UTF-16LE" => "UTF-16 LE",
+            "UTF-16BE" => "UTF-16 BE",
+            name => name,
+        };
+        if self.bom && self.encoding == UTF_8 {
+            format!("{} with BOM", name)
+        } else {
+            name.to_string()
+        }
+    }
+
+    // 按 WHATWG 的标签（utf-8、gbk、shift_jis、latin1 等，不分大小写）找编码，后面加上 bom 表示写 BOM
+    pub fn from_label(label: &str) -> Result<Self> {
+        let label = label.trim().to_ascii_lowercase();
+        let (name, bom) = match label.strip_suffix("bom") {
+            Some(name) => (name.trim_end_matches([' ', '-', '_']).trim_end_matches(" with"), true),
+            None => (label.as_str(), false),
+        };
+        match Encoding::for_label(name.trim().as_bytes()) {
+            Some(encoding) if encoding != REPLACEMENT => Ok(TextEncoding { encoding, bom }),
+            _ => Err(anyhow!("Unknown encoding '{}'", label)),
+        }
+    }
+
+    // 轮换到下一个编码，当前编码不在列表里时从头开始
+    pub fn next(&self) -> Self {
+        let index = ENCODING_CYCLE.iter().position(|e| e == self).map_or(0, |i| i + 1);
+        ENCODING_CYCLE[index % ENCODING_CYCLE.len()]
+    }
+
+    // 识别编码并解码：先看 BOM，再试没有 BOM 的 UTF-16 和 UTF-8，都不是时按统计猜旧式编码
+    pub fn decode(bytes: &[u8]) -> (Self, String) {
+        if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+            let encoding = TextEncoding { encoding, bom: true };
+            return (encoding, encoding.decode_body(&bytes[bom_length..]));
+        }
+        // 纯 ASCII 的 UTF-16 也是合法的 UTF-8（全是 NUL），所以先看零字节
+        let encoding = match utf16_without_bom(bytes) {
+            Some(encoding) => encoding,
+            None => {
+                if let Ok(text) = std::str::from_utf8(bytes) {
+                    return (TextEncoding::default(), text.to_string());
+                }
+                let mut detector = EncodingDetector::new();
+                detector.feed(bytes, true);
+                detector.guess(None, true)
+            }
+        };
+        let encoding = TextEncoding { encoding, bom: false };
+        (encoding, encoding.decode_body(bytes))
+    }
+
+    fn decode_body(&self, bytes: &[u8]) -> String {
+        let (text, had_errors) = self.encoding.decode_without_bom_handling(bytes);
+        if had_errors {
+            warn!("Some bytes are not valid {} and were replaced", self.name());
+        }
+        text.into_owned()
+    }
+
+    // 编码成字节，有这个编码表示不了的字符时报错，不会悄悄写坏文件
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(text.len() + 3);
+        // encoding_rs 不会往 UTF-16 编码，自己来
+        if self.encoding == UTF_16LE || self.encoding == UTF_16BE {
+            let little = self.encoding == UTF_16LE;
+            let units = (self.bom.then_some('\u{feff}').into_iter().map(|c| c as u16)).chain(text.encode_utf16());
+            for unit in units {
+                bytes.extend_from_slice(&if little { unit.to_le_bytes() } else { unit.to_be_bytes() });
+            }
+            return Ok(bytes);
+        }
+
+        if self.bom && self.encoding == UTF_8 {
+            bytes.extend_from_slice(b"\xef\xbb\xbf");
+        }
+        let (encoded, _, unmappable) = self.encoding.encode(text);
+        if unmappable {
+            let example = text.chars().find(|c| self.encoding.encode(c.encode_utf8(&mut [0; 4])).2);
+            bail!(
+                "The text contains characters that cannot be encoded in {}{}",
+                self.name(),
+                example.map(|c| format!(" (first: {:?})", c)).unwrap_or_default()
+            );
+        }
+        bytes.extend_from_slice(&encoded);
+        Ok(bytes)
+    }
+}
+
+// ASCII 为主的 UTF-16 文本每两个字节就有一个零
+fn utf16_without_bom(bytes: &[u8]) -> Option<&'static Encoding> {
+    if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let pairs = bytes.len() / 2;
+    let even = bytes.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd = bytes.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+    let threshold = (pairs as f32 * UTF16_ZERO_RATIO).max(1.0) as usize;
+    // 另一侧也可能有少量零字节（比如 U+0100），只要明显少得多就行
+    if odd >= threshold && even * 4 < odd {
+        Some(UTF_16LE)
+    } else if even >= threshold && odd * 4 < even {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+// 设置换行符的命令
+pub fn line_ending_prompt(buffer: &Buffer) -> Prompt {
+    let id = buffer.id;
+    Prompt::new(
+        format!("line-ending:{}", id),
+        format!("Line endings are {}. Convert to:", buffer.line_ending.name()),
+        PromptAction::Cancel,
+    )
+    .focused()
+    .choice('l', "LF", PromptAction::SetLineEnding(id, LineEnding::Lf))
+    .choice('r', "CRLF", PromptAction::SetLineEnding(id, LineEnding::CrLf))
+    .choice('c', "Cancel", PromptAction::Cancel)
+}
+
+// 设置编码的命令，在输入框里填编码的名字
+pub fn encoding_prompt(buffer: &Buffer) -> Prompt {
+    let id = buffer.id;
+    Prompt::new(
+        format!("encoding:{}", id),
+        format!("Convert from {} to (e.g. utf-8, gbk, utf-16le bom):", buffer.encoding.name()),
+        PromptAction::Cancel,
+    )
+    .input("")
+    .focused()
+    .choice('s', "Convert", PromptAction::SetEncoding(id))
+    .choice('c', "Cancel", PromptAction::Cancel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str, little: bool, bom: bool) -> Vec<u8> {
+        let units = bom.then_some(0xfeff).into_iter().chain(text.encode_utf16());
+        units.flat_map(|u| if little { u.to_le_bytes() } else { u.to_be_bytes() }).collect()
+    }
+
+    #[test]
+    fn utf8_with_and_without_bom() {
+        let (encoding, text) = TextEncoding::decode("héllo\n".as_bytes());
+        assert_eq!(encoding, TextEncoding { encoding: UTF_8, bom: false });
+        assert_eq!(text, "héllo\n");
+
+        let (encoding, text) = TextEncoding::decode(b"\xef\xbb\xbfh\xc3\xa9llo\n");
+        assert_eq!(encoding, TextEncoding { encoding: UTF_8, bom: true });
+        assert_eq!(text, "héllo\n");
+        assert_eq!(encoding.encode(&text).unwrap(), b"\xef\xbb\xbfh\xc3\xa9llo\n");
+    }
+
+    #[test]
+    fn utf16_with_bom() {
+        for little in [true, false] {
+            let bytes = utf16("héllo, 世界\n", little, true);
+            let (encoding, text) = TextEncoding::decode(&bytes);
+            assert_eq!(encoding.encoding, if little { UTF_16LE } else { UTF_16BE });
+            assert!(encoding.bom);
+            assert_eq!(text, "héllo, 世界\n");
+            assert_eq!(encoding.encode(&text).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn utf16_without_bom_is_detected_from_zero_bytes() {
+        for little in [true, false] {
+            let bytes = utf16("fn main() {}\nĀ\n", little, false);
+            let (encoding, text) = TextEncoding::decode(&bytes);
+            assert_eq!(encoding, TextEncoding { encoding: if little { UTF_16LE } else { UTF_16BE }, bom: false });
+            assert_eq!(text, "fn main() {}\nĀ\n");
+            assert_eq!(encoding.encode(&text).unwrap(), bytes);
+        }
+        // 奇数长度、零字节太少的都不是
+        assert_eq!(utf16_without_bom(b"a\0b"), None);
+        assert_eq!(utf16_without_bom(b"abcdefgh\0\0"), None);
+    }
+
+    #[test]
+    fn legacy_encodings_are_guessed() {
+        let chinese = "这是一个用简体中文写的文本文件，编辑器应该能够识别出它的编码并正确显示内容。\n";
+        let (bytes, _, _) = GBK.encode(chinese);
+        let (encoding, text) = TextEncoding::decode(&bytes);
+        assert_eq!(encoding.encoding, GBK);
+        assert_eq!(text, chinese);
+
+        let japanese = "これは日本語で書かれたテキストファイルです。エディタは文字コードを正しく判別できるはずです。\n";
+        let (bytes, _, _) = SHIFT_JIS.encode(japanese);
+        let (encoding, text) = TextEncoding::decode(&bytes);
+        assert_eq!(encoding.encoding, SHIFT_JIS);
+        assert_eq!(text, japanese);
+        assert_eq!(encoding.encode(&text).unwrap(), bytes.into_owned());
+    }
+
+    #[test]
+    fn unencodable_characters_are_an_error() {
+        let encoding = TextEncoding { encoding: SHIFT_JIS, bom: false };
+        let error = encoding.encode("abc 한국어").unwrap_err().to_string();
+        assert!(error.contains("Shift_JIS"), "{}", error);
+        assert!(error.contains("'한'"), "{}", error);
+        assert!(TextEncoding { encoding: WINDOWS_1252, bom: false }.encode("€uro").is_ok());
+    }
+
+    #[test]
+    fn line_ending_detection() {
+        assert_eq!(LineEnding::detect("a\nb\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\r\nb\r\n"), LineEnding::CrLf);
+        assert_eq!(LineEnding::detect("no newline"), LineEnding::Lf);
+        // 混用时取多数，一样多时用 LF
+        assert_eq!(LineEnding::detect("a\r\nb\r\nc\n"), LineEnding::CrLf);
+        assert_eq!(LineEnding::detect("a\r\nb\nc\n"), LineEnding::Lf);
+        assert_eq!(LineEnding::detect("a\r\nb\n"), LineEnding::Lf);
+        assert_eq!(normalize_line_endings("a\r\nb\rc\n".to_string()), "a\nb\rc\n");
+    }
+
+    #[test]
+    fn crlf_round_trips_byte_for_byte() {
+        let bytes = "first\r\nsecond, 中文\r\n\r\nlast".as_bytes();
+        let buffer = Buffer::from_bytes(bytes, None);
+        assert_eq!(buffer.line_ending, LineEnding::CrLf);
+        assert_eq!(buffer.content, "first\nsecond, 中文\n\nlast");
+        assert_eq!(buffer.encoded().unwrap(), bytes);
+
+        let bytes = utf16("a\r\nb\r\n", true, true);
+        assert_eq!(Buffer::from_bytes(&bytes, None).encoded().unwrap(), bytes);
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(TextEncoding::from_label("GBK").unwrap(), TextEncoding { encoding: GBK, bom: false });
+        assert_eq!(TextEncoding::from_label(" utf-8 with BOM ").unwrap(), TextEncoding { encoding: UTF_8, bom: true });
+        assert_eq!(TextEncoding::from_label("utf-16le bom").unwrap(), TextEncoding { encoding: UTF_16LE, bom: true });
+        assert_eq!(TextEncoding::from_label("latin1").unwrap().encoding, WINDOWS_1252);
+        assert!(TextEncoding::from_label("klingon").is_err());
+        assert!(TextEncoding::from_label("iso-2022-kr").is_err());
+    }
+}
@@ -1,167 +1,1188 @@
 use std::collections::HashMap;
-use freetype::Library;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::rc::Rc;
+use freetype::{Face, FtResult, LcdFilter, Library, RenderMode};
 use anyhow::Result;
-use log::error;
-use freetype::face::LoadFlag;
+use log::{info, warn};
+use freetype::bitmap::PixelMode;
+use freetype::face::{LoadFlag, StyleFlag};
+use crate::atlas::{AtlasFormat, AtlasRegion, GlyphAtlas, GlyphKey};
+use crate::fontconfig::{self, FontMatch};
+use crate::sdf;
+use crate::shader::Shader;
+use crate::shaping::{self, ShapedLine};
+use crate::theme::{Antialiasing, FontConfig, GlyphRenderMode, Hinting, TextEffects};
+
+// 图集最多占用的纹理页数
+const MAX_ATLAS_PAGES: usize = 4;
+// fontconfig 不可用时使用的主字体
+const DEFAULT_FONT_PATH: &str = "/System/Library/Fonts/Menlo.ttc";
+// 整形结果缓存的行数上限，超过后整体清空
+const MAX_SHAPED_LINES: usize = 4096;
+// 缩放时保留的其他字号缓存份数（每份有自己的图集）
+const MAX_CACHED_SIZES: usize = 3;
+// 距离场按这个像素字号光栅化一次，任意字号共用
+const SDF_BASE_SIZE: u32 = 48;
+// 距离场向字形外扩展的像素数（基准字号下），也是描边和辉光的最大宽度
+const SDF_SPREAD: usize = 8;
+// faces 的前四项是主字体的常规、粗体、斜体、粗斜体，下标即 TextStyle::variant()
+const STYLE_VARIANTS: usize = 4;
+// 图集里的一小块实心像素，下划线等装饰用它画成矩形
+const SOLID_KEY: GlyphKey = GlyphKey { face: usize::MAX, glyph: 0 };
+
+// 文本样式：粗体和斜体决定字体，下划线和删除线作为装饰绘制
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct TextStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+}
+
+impl TextStyle {
+    fn variant(self) -> usize {
+        self.bold as usize | (self.italic as usize) << 1
+    }
+}
+
+// 同一颜色和样式的一段文本，range 是行内的字节范围
+#[derive(Clone, Debug)]
+pub struct TextSpan {
+    pub range: Range<usize>,
+    pub color: [f32; 4],
+    pub style: TextStyle,
+}
 
 // 字形信息
+#[derive(Clone, Copy)]
 pub struct Character {
-    pub texture_id: u32,
     pub size: (i32, i32),     // 宽度和高度
     pub bearing: (i32, i32),  // 基准点到字形左边和顶边的距离
-    pub advance: u32,         // 到下一个字形的水平距离
+    pub scale: f32,           // 位图到字号的缩放，只有固定尺寸的 emoji 位图不为 1
+}
+
+// 回退链中的一个字体
+struct FontFace {
+    face: Face,
+    // 字体文件内容，FreeType 光栅化和 rustybuzz 整形共用
+    data: Rc<Vec<u8>>,
+    source: FontMatch,
+    units_per_em: f32,
+    // 只有固定尺寸位图的字体（如 Noto Color Emoji）需要缩放到当前字号
+    scale: f32,
+    // 缺少对应样式的字体时，由常规体合成粗体和斜体
+    embolden: bool,
+    oblique: bool,
+}
+
+impl FontFace {
+    fn load(library: &Library, source: FontMatch, font_size: u32) -> Result<Self> {
+        let data = Rc::new(std::fs::read(&source.path)?);
+        let font = FontFace::from_data(library, data, source, font_size)?;
+        info!("Loaded font {} ({})", font.source.path.display(), font.face.family_name().unwrap_or_default());
+        Ok(font)
+    }
+
+    fn from_data(library: &Library, data: Rc<Vec<u8>>, source: FontMatch, font_size: u32) -> Result<Self> {
+        let face = library.new_memory_face(data.clone(), source.index)?;
+        let units_per_em = face.em_size().max(1) as f32;
+        let mut font = FontFace { face, data, source, units_per_em, scale: 1.0, embolden: false, oblique: false };
+        font.set_size(font_size)?;
+        Ok(font)
+    }
+
+    // 同一份字体数据在另一个字号下的新字体，原来的不动
+    fn resized(&self, library: &Library, font_size: u32) -> Result<Self> {
+        let mut font = FontFace::from_data(library, self.data.clone(), self.source.clone(), font_size)?;
+        font.embolden = self.embolden;
+        font.oblique = self.oblique;
+        Ok(font)
+    }
+
+    // 加载并光栅化字形，合成样式在光栅化前变换轮廓
+    fn render_glyph(&self, glyph: u32, flags: LoadFlag, render_mode: RenderMode) -> FtResult<()> {
+        self.face.load_glyph(glyph, flags)?;
+        let slot = self.face.glyph();
+        let raw = slot.raw() as *const freetype::ffi::FT_GlyphSlotRec as freetype::ffi::FT_GlyphSlot;
+        unsafe {
+            if self.embolden {
+                freetype::ffi::FT_GlyphSlot_Embolden(raw);
+            }
+            if self.oblique {
+                freetype::ffi::FT_GlyphSlot_Oblique(raw);
+            }
+        }
+        slot.render_glyph(render_mode)
+    }
+
+    // 按物理像素字号设置光栅化尺寸
+    fn set_size(&mut self, font_size: u32) -> Result<()> {
+        if self.face.is_scalable() {
+            self.face.set_pixel_sizes(0, font_size)?;
+            self.scale = 1.0;
+        } else if self.face.has_fixed_sizes() {
+            // 选一个最接近字号的位图尺寸，绘制时再缩放
+            let raw = self.face.raw();
+            let sizes = unsafe {
+                std::slice::from_raw_parts(raw.available_sizes, raw.num_fixed_sizes as usize)
+            };
+            let (index, strike) = sizes
+                .iter()
+                .enumerate()
+                .map(|(i, s)| (i, (s.y_ppem >> 6) as u32))
+                .min_by_key(|&(_, px)| (px < font_size, px.abs_diff(font_size)))
+                .ok_or_else(|| anyhow::anyhow!("Font has no bitmap strikes"))?;
+            let err = unsafe { freetype::ffi::FT_Select_Size(self.face.raw_mut(), index as i32) };
+            if err != 0 {
+                return Err(anyhow::anyhow!("FT_Select_Size failed with error {}", err));
+            }
+            self.scale = font_size as f32 / strike.max(1) as f32;
+        }
+        Ok(())
+    }
+}
+
+// 实例化路径：每个实例一个字形，顶点只携带单位四边形的角
+const TEXT_INSTANCED_VERTEX_SHADER: &str = r#"
+#version 120
+attribute vec2 a_corner;
+attribute vec4 a_rect;
+attribute vec4 a_uv;
+attribute vec4 a_color;
+uniform mat4 u_projection;
+varying vec2 v_uv;
+varying vec4 v_color;
+
+void main() {
+    v_uv = mix(a_uv.xy, a_uv.zw, a_corner);
+    v_color = a_color;
+    gl_Position = u_projection * vec4(mix(a_rect.xy, a_rect.zw, a_corner), 0.0, 1.0);
+}
+"#;
+
+// GL 2.1 回退路径：每个字形展开成 6 个顶点
+const TEXT_VERTEX_SHADER: &str = r#"
+#version 120
+attribute vec2 a_position;
+attribute vec2 a_uv;
+attribute vec4 a_color;
+uniform mat4 u_projection;
+varying vec2 v_uv;
+varying vec4 v_color;
+
+void main() {
+    v_uv = a_uv;
+    v_color = a_color;
+    gl_Position = u_projection * vec4(a_position, 0.0, 1.0);
+}
+"#;
+
+// u_glyph_mode 对应 GLYPH_MODE_*：灰度字形只用红色通道的覆盖率；彩色 emoji 直接用纹理颜色；
+// 次像素字形每个通道各有覆盖率，分两遍混合（先按覆盖率压暗背景，再叠加文字颜色）。
+// 覆盖率按文字亮度做 gamma 校正：亮字在暗背景上加粗，暗字在亮背景上减细
+const TEXT_FRAGMENT_SHADER: &str = r#"
+#version 120
+uniform sampler2D u_atlas;
+uniform int u_glyph_mode;
+uniform float u_gamma;
+uniform float u_contrast;
+varying vec2 v_uv;
+varying vec4 v_color;
+
+vec3 adjust_coverage(vec3 coverage) {
+    coverage = mix(coverage, smoothstep(0.0, 1.0, coverage), u_contrast);
+    float luma = dot(v_color.rgb, vec3(0.2126, 0.7152, 0.0722));
+    return pow(coverage, vec3(mix(u_gamma, 1.0 / u_gamma, luma)));
+}
+
+void main() {
+    vec4 texel = texture2D(u_atlas, v_uv);
+    if (u_glyph_mode == 1) {
+        gl_FragColor = vec4(texel.rgb, texel.a * v_color.a);
+    } else if (u_glyph_mode == 2) {
+        gl_FragColor = vec4(adjust_coverage(texel.rgb) * v_color.a, 1.0);
+    } else if (u_glyph_mode == 3) {
+        gl_FragColor = vec4(v_color.rgb * adjust_coverage(texel.rgb) * v_color.a, 1.0);
+    } else {
+        gl_FragColor = vec4(v_color.rgb, v_color.a * adjust_coverage(texel.rrr).r);
+    }
+}
+"#;
+
+const GLYPH_MODE_GRAY: i32 = 0;
+const GLYPH_MODE_COLOR: i32 = 1;
+const GLYPH_MODE_LCD_MASK: i32 = 2;
+const GLYPH_MODE_LCD_COLOR: i32 = 3;
+
+// 距离场字形：同一张纹理在任意缩放下都能得到清晰边缘，并支持描边和辉光。
+// 距离单位是基准字号下的像素，正值在字形内部
+const SDF_FRAGMENT_SHADER: &str = r#"
+#version 120
+uniform sampler2D u_atlas;
+uniform float u_spread;
+uniform float u_outline_width;
+uniform vec4 u_outline_color;
+uniform float u_glow_radius;
+uniform vec4 u_glow_color;
+varying vec2 v_uv;
+varying vec4 v_color;
+
+void main() {
+    float dist = (texture2D(u_atlas, v_uv).r - 0.5) * 2.0 * u_spread;
+    float aa = max(fwidth(dist) * 0.5, 0.0001);
+
+    float fill = smoothstep(-aa, aa, dist);
+    vec4 color = vec4(v_color.rgb, v_color.a * fill);
+
+    if (u_outline_width > 0.0) {
+        float outline = smoothstep(-aa, aa, dist + u_outline_width);
+        color = mix(vec4(u_outline_color.rgb, u_outline_color.a * outline), vec4(v_color.rgb, v_color.a), fill);
+    }
+
+    if (u_glow_radius > 0.0) {
+        float glow = clamp(1.0 + dist / u_glow_radius, 0.0, 1.0);
+        float glow_alpha = u_glow_color.a * glow * glow;
+        float alpha = color.a + glow_alpha * (1.0 - color.a);
+        vec3 rgb = (color.rgb * color.a + u_glow_color.rgb * glow_alpha * (1.0 - color.a)) / max(alpha, 0.0001);
+        color = vec4(rgb, alpha);
+    }
+
+    gl_FragColor = color;
+}
+"#;
+
+// 一个字形四边形：rect 的 (x0, y0)/(x1, y1) 分别对应 uv 的 (u0, v0)/(u1, v1)
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GlyphInstance {
+    rect: [f32; 4],
+    uv: [f32; 4],
+    color: [f32; 4],
+}
+
+// GL 2.1 回退路径用的展开顶点
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GlyphVertex {
+    pos: [f32; 2],
+    uv: [f32; 2],
+    color: [f32; 4],
+}
+
+// 顶点属性位置
+const ATTRIB_CORNER: u32 = 0;
+const ATTRIB_RECT: u32 = 1;
+const ATTRIB_UV: u32 = 2;
+const ATTRIB_COLOR: u32 = 3;
+const ATTRIB_POSITION: u32 = 0;
+
+// 整形缓存的键：文本和样式变体的切换点 (字节偏移, 变体)，见 shape_styled
+type ShapeKey = (String, Vec<(usize, usize)>);
+
+// 某一物理像素字号下的字形缓存：度量、图集和整形结果都只对这个字号有效
+struct SizedGlyphs {
+    font_size: u32,
+    characters: HashMap<GlyphKey, Character>,
+    atlas: GlyphAtlas,
+    shaped_lines: HashMap<ShapeKey, Rc<ShapedLine>>,
+}
+
+impl SizedGlyphs {
+    fn new(font_size: u32) -> Self {
+        SizedGlyphs {
+            font_size,
+            characters: HashMap::new(),
+            atlas: GlyphAtlas::new(MAX_ATLAS_PAGES),
+            shaped_lines: HashMap::new(),
+        }
+    }
+}
+
+// 距离场字形缓存，与字号无关
+struct SdfGlyphs {
+    characters: HashMap<GlyphKey, Character>,
+    atlas: GlyphAtlas,
 }
 
 pub struct FontRenderer {
-    characters: HashMap<char, Character>,
+    glyphs: SizedGlyphs,
+    cached_sizes: Vec<SizedGlyphs>,
+    sdf_glyphs: Option<SdfGlyphs>,
+    render_mode: GlyphRenderMode,
+    sdf_min_size: f32,
+    effects: TextEffects,
+    antialiasing: Antialiasing,
+    hinting: Hinting,
+    gamma: f32,
+    contrast: f32,
+    // 回退链：主字体在最前，后面依次是 FontConfig.fallback 和按需找到的字体
+    faces: Vec<FontFace>,
+    // (字符, 样式变体) 对应的字体
+    char_faces: HashMap<(char, usize), usize>,
+    features: Vec<rustybuzz::Feature>,
+    // 逻辑字号乘以显示器内容缩放得到的物理像素字号
+    base_size: f32,
+    content_scale: f32,
+    font_size: u32,
+    library: Library,
+    // 按图集页分组的待绘制字形，flush 时每页一次绘制
+    batches: Vec<Vec<GlyphInstance>>,
+    sdf_batches: Vec<Vec<GlyphInstance>>,
+    // 下划线、删除线这些实心矩形单独一批，在所有字形之前画，不随图集页的顺序跑到文字上面
+    rects: Vec<GlyphInstance>,
+    vertices: Vec<GlyphVertex>,
+    instanced: bool,
+    shader: Shader,
+    sdf_shader: Shader,
+    // 左上角为原点、单位为像素的正交投影
+    projection: [f32; 16],
+    // FontConfig.line_height 是字号的倍数
+    line_height_factor: f32,
+    line_height: f32,
+    // 行顶到基线的距离（含上下均分的行距），render_spans 的 y 是行顶
+    baseline_offset: f32,
+    // 下划线和删除线：相对基线向下的偏移和粗细，单位像素
+    underline: (f32, f32),
+    strikeout: (f32, f32),
+    quad_vbo: u32,
     vbo: u32,
-    vao: u32,
 }
 
 impl FontRenderer {
-    pub fn new(font_path: &str, font_size: u32) -> Result<Self> {
-        let ft = Library::init()?;
-        let face = ft.new_face(font_path, 0)?;
-        face.set_pixel_sizes(0, font_size)?;
+    pub fn new(config: &FontConfig, content_scale: f32) -> Result<Self> {
+        let library = Library::init()?;
+        let font_size = physical_size(config.size, content_scale);
+
+        // 主字体必须能加载；找不到时退回系统自带的等宽字体
+        let primary = fontconfig::match_pattern(&config.family).unwrap_or_else(|| {
+            warn!("Font family '{}' not found, using {}", config.family, DEFAULT_FONT_PATH);
+            FontMatch { path: PathBuf::from(DEFAULT_FONT_PATH), index: 0 }
+        });
+        let mut faces = vec![FontFace::load(&library, primary, font_size)?];
+        for variant in 1..STYLE_VARIANTS {
+            let font = load_style_variant(&library, &config.family, &faces[0], variant, font_size)?;
+            faces.push(font);
+        }
 
-        let mut characters = HashMap::new();
-        let mut vao = 0;
+        for family in &config.fallback {
+            let source = match fontconfig::match_pattern(family) {
+                Some(source) => source,
+                None => {
+                    warn!("Fallback font '{}' not found", family);
+                    continue;
+                }
+            };
+            if faces.iter().any(|f| f.source == source) {
+                continue;
+            }
+            match FontFace::load(&library, source, font_size) {
+                Ok(face) => faces.push(face),
+                Err(e) => warn!("Failed to load fallback font '{}': {}", family, e),
+            }
+        }
+
+        // ARB_instanced_arrays / ARB_draw_instanced 不可用时退回逐顶点绘制
+        let instanced = gl::VertexAttribDivisor::is_loaded() && gl::DrawArraysInstanced::is_loaded();
+        info!("Text rendering path: {}", if instanced { "instanced" } else { "vertex arrays" });
+        let shader = text_shader(instanced, TEXT_FRAGMENT_SHADER)?;
+        let sdf_shader = text_shader(instanced, SDF_FRAGMENT_SHADER)?;
+
+        let mut quad_vbo = 0;
         let mut vbo = 0;
 
         unsafe {
-            gl::GenVertexArrays(1, &mut vao);
             gl::GenBuffers(1, &mut vbo);
-            gl::BindVertexArray(vao);
-            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-            gl::BufferData(
-                gl::ARRAY_BUFFER,
-                (6 * 4 * std::mem::size_of::<f32>()) as isize,
-                std::ptr::null(),
-                gl::DYNAMIC_DRAW,
-            );
-            gl::EnableVertexAttribArray(0);
-            gl::VertexAttribPointer(
-                0,
-                2,
-                gl::FLOAT,
-                gl::FALSE as u8,
-                (4 * std::mem::size_of::<f32>()) as i32,
-                std::ptr::null(),
-            );
-            gl::EnableVertexAttribArray(1);
-            gl::VertexAttribPointer(
-                1,
-                2,
-                gl::FLOAT,
-                gl::FALSE as u8,
-                (4 * std::mem::size_of::<f32>()) as i32,
-                (2 * std::mem::size_of::<f32>()) as *const _,
-            );
-            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-            gl::BindVertexArray(0);
+            if instanced {
+                // 单位四边形的四个角，按三角形带排列
+                let corners: [f32; 8] = [0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+                gl::GenBuffers(1, &mut quad_vbo);
+                gl::BindBuffer(gl::ARRAY_BUFFER, quad_vbo);
+                gl::BufferData(
+                    gl::ARRAY_BUFFER,
+                    std::mem::size_of_val(&corners) as isize,
+                    corners.as_ptr() as *const _,
+                    gl::STATIC_DRAW,
+                );
+                gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            }
+        }
+
+        let mut renderer = FontRenderer {
+            glyphs: SizedGlyphs::new(font_size),
+            cached_sizes: Vec::new(),
+            sdf_glyphs: None,
+            render_mode: config.render_mode,
+            sdf_min_size: config.sdf_min_size,
+            effects: config.effects,
+            antialiasing: config.antialiasing,
+            hinting: config.hinting,
+            gamma: config.gamma.max(0.1),
+            contrast: config.contrast.clamp(0.0, 1.0),
+            faces,
+            char_faces: HashMap::new(),
+            features: shaping::parse_features(&config.features),
+            base_size: config.size,
+            content_scale,
+            font_size,
+            library,
+            batches: Vec::new(),
+            sdf_batches: Vec::new(),
+            rects: Vec::new(),
+            vertices: Vec::new(),
+            instanced,
+            shader,
+            sdf_shader,
+            projection: ortho_projection(1.0, 1.0),
+            line_height_factor: config.line_height,
+            line_height: 0.0,
+            baseline_offset: 0.0,
+            underline: (0.0, 1.0),
+            strikeout: (0.0, 1.0),
+            quad_vbo,
+            vbo,
+        };
+        renderer.update_line_metrics();
+        renderer.set_render_options(config);
+        Ok(renderer)
+    }
+
+    // 渲染方式、特效、抗锯齿、微调、gamma 和对比度；光栅化方式变了就丢掉所有字号的字形缓存，下次用到时重新光栅化。
+    // 距离场字形按基准字号单独缓存，和位图字形互不影响，切换渲染方式不用清缓存
+    pub fn set_render_options(&mut self, config: &FontConfig) {
+        let subpixel = matches!(config.antialiasing, Antialiasing::SubpixelRgb | Antialiasing::SubpixelBgr);
+        if subpixel {
+            // 较新的 FreeType 默认用 Harmony 次像素渲染，不支持滤波器设置也不影响效果
+            if let Err(e) = self.library.set_lcd_filter(LcdFilter::LcdFilterDefault) {
+                info!("LCD filter unavailable: {}", e);
+            }
+        }
+        self.render_mode = config.render_mode;
+        self.sdf_min_size = config.sdf_min_size;
+        self.effects = config.effects;
+        self.gamma = config.gamma.max(0.1);
+        self.contrast = config.contrast.clamp(0.0, 1.0);
+        if config.antialiasing == self.antialiasing && config.hinting == self.hinting {
+            return;
+        }
+        info!("Glyph rasterization changed, clearing glyph caches");
+        self.antialiasing = config.antialiasing;
+        self.hinting = config.hinting;
+        self.glyphs = SizedGlyphs::new(self.font_size);
+        self.cached_sizes.clear();
+        self.sdf_glyphs = None;
+    }
+
+    // 物理像素行高
+    pub fn line_height(&self) -> f32 {
+        self.line_height
+    }
+
+    // 运行时缩放：size 是逻辑像素字号
+    pub fn set_font_size(&mut self, size: f32) -> Result<()> {
+        self.base_size = size;
+        self.switch_size(physical_size(size, self.content_scale))
+    }
+
+    // 窗口移到缩放比例不同的显示器上时，按新的物理像素字号重新光栅化
+    pub fn set_content_scale(&mut self, content_scale: f32) -> Result<()> {
+        if (content_scale - self.content_scale).abs() < f32::EPSILON {
+            return Ok(());
+        }
+        info!("Content scale changed: {} -> {}", self.content_scale, content_scale);
+        self.content_scale = content_scale;
+        self.switch_size(physical_size(self.base_size, content_scale))
+    }
+
+    // 切换物理像素字号：字体重设尺寸，字形缓存换成该字号的那一份（没有就新建）。
+    // 所有字体都换好了才替换，中途失败时仍全部停在原来的字号
+    fn switch_size(&mut self, font_size: u32) -> Result<()> {
+        if font_size == self.font_size {
+            return Ok(());
+        }
+        self.faces = self.faces.iter().map(|font| font.resized(&self.library, font_size)).collect::<Result<_>>()?;
+
+        let glyphs = match self.cached_sizes.iter().position(|g| g.font_size == font_size) {
+            Some(i) => self.cached_sizes.remove(i),
+            None => SizedGlyphs::new(font_size),
+        };
+        let previous = std::mem::replace(&mut self.glyphs, glyphs);
+        self.cached_sizes.insert(0, previous);
+        self.cached_sizes.truncate(MAX_CACHED_SIZES);
+
+        self.font_size = font_size;
+        self.update_line_metrics();
+        Ok(())
+    }
+
+    fn update_line_metrics(&mut self) {
+        let (ascender, descender) = match self.faces[0].face.size_metrics() {
+            Some(m) => ((m.ascender >> 6) as f32, (m.descender >> 6) as f32),
+            None => (self.font_size as f32 * 0.8, -(self.font_size as f32) * 0.2),
+        };
+        self.line_height = (self.font_size as f32 * self.line_height_factor).round().max(1.0);
+        let half_leading = ((self.line_height - (ascender - descender)) / 2.0).max(0.0);
+        self.baseline_offset = (half_leading + ascender).round();
+
+        // 装饰线位置取自主字体的 post / OS/2 表，字体没写时按字号估算
+        let font = &self.faces[0];
+        let px_per_unit = self.font_size as f32 / font.units_per_em;
+        let size = self.font_size as f32;
+        let (underline, strikeout) = match rustybuzz::Face::from_slice(&font.data, font.source.index as u32) {
+            Some(face) => (face.underline_metrics(), face.strikeout_metrics()),
+            None => (None, None),
+        };
+        let to_px = |metrics: Option<rustybuzz::ttf_parser::LineMetrics>, position: f32, thickness: f32| {
+            let (position, thickness) = metrics
+                .map(|m| (m.position as f32 * px_per_unit, m.thickness as f32 * px_per_unit))
+                .unwrap_or((position * size, thickness * size));
+            // 字体坐标向上为正，这里换成向下为正；粗细至少一个像素
+            (-position.round(), thickness.round().max(1.0))
+        };
+        self.underline = to_px(underline, -0.1, 0.05);
+        self.strikeout = to_px(strikeout, 0.25, 0.05);
+    }
+
+    // 窗口（帧缓冲）尺寸变化时更新投影
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.projection = ortho_projection(width.max(1) as f32, height.max(1) as f32);
+    }
+
+    // 每帧开始时调用，推进图集的 LRU 时钟
+    pub fn begin_frame(&mut self) {
+        self.glyphs.atlas.begin_frame();
+        if let Some(cache) = &mut self.sdf_glyphs {
+            cache.atlas.begin_frame();
+        }
+    }
+
+    // 先看该样式的主字体，再沿回退链找覆盖该字符的字体；链上都没有时问 fontconfig
+    fn face_for(&mut self, c: char, variant: usize) -> usize {
+        if let Some(&index) = self.char_faces.get(&(c, variant)) {
+            return index;
+        }
+
+        let covers = |font: &FontFace| font.face.get_char_index(c as usize) != 0;
+        let mut found = if covers(&self.faces[variant]) {
+            Some(variant)
+        } else {
+            (STYLE_VARIANTS..self.faces.len()).find(|&i| covers(&self.faces[i]))
+        };
+        if found.is_none() && !c.is_control() {
+            if let Some(source) = fontconfig::match_char(c) {
+                if !self.faces.iter().any(|f| f.source == source) {
+                    match FontFace::load(&self.library, source, self.font_size) {
+                        Ok(face) if face.face.get_char_index(c as usize) != 0 => {
+                            self.faces.push(face);
+                            found = Some(self.faces.len() - 1);
+                        }
+                        Ok(_) => {}
+                        Err(e) => warn!("Failed to load fallback font for {:?}: {}", c, e),
+                    }
+                }
+            }
+        }
+
+        // 谁都不覆盖时用主字体的 .notdef 方框
+        let index = found.unwrap_or(variant);
+        self.char_faces.insert((c, variant), index);
+        index
+    }
+
+    // 把一行文本按字体切成若干段分别整形，结果按行缓存。
+    // variants 是按字节偏移升序的 (起点, 样式变体) 切换点，第一个切换点之前是常规体
+    fn shape_styled(&mut self, text: &str, variants: Vec<(usize, usize)>) -> Rc<ShapedLine> {
+        let key: ShapeKey = (text.to_string(), variants);
+        if let Some(line) = self.glyphs.shaped_lines.get(&key) {
+            return line.clone();
+        }
+        let variants = &key.1;
+
+        let mut glyphs = Vec::new();
+        let mut pen_x = 0.0;
+        let mut run: Option<(usize, usize)> = None; // (字体, 起始字节)
+        let mut variant = 0;
+        let mut next_variant = 0;
+
+        for (offset, c) in text.char_indices() {
+            while next_variant < variants.len() && variants[next_variant].0 <= offset {
+                variant = variants[next_variant].1;
+                next_variant += 1;
+            }
+            let face = match run {
+                // 附加符号等优先留在当前段的字体里
+                Some((face, _))
+                    if shaping::is_cluster_continuation(c)
+                        && self.faces[face].face.get_char_index(c as usize) != 0 =>
+                {
+                    face
+                }
+                _ => self.face_for(c, variant),
+            };
+            match run {
+                Some((run_face, _)) if run_face == face => {}
+                Some((run_face, start)) => {
+                    pen_x = self.shape_run(run_face, text, start..offset, pen_x, &mut glyphs);
+                    run = Some((face, offset));
+                }
+                None => run = Some((face, offset)),
+            }
+        }
+        if let Some((face, start)) = run {
+            pen_x = self.shape_run(face, text, start..text.len(), pen_x, &mut glyphs);
+        }
+
+        let carets = shaping::compute_carets(text, &glyphs, pen_x);
+        let line = Rc::new(ShapedLine { glyphs, carets, width: pen_x });
+        if self.glyphs.shaped_lines.len() >= MAX_SHAPED_LINES {
+            self.glyphs.shaped_lines.clear();
+        }
+        self.glyphs.shaped_lines.insert(key, line.clone());
+        line
+    }
+
+    fn shape_run(
+        &self,
+        face: usize,
+        text: &str,
+        range: std::ops::Range<usize>,
+        pen_x: f32,
+        out: &mut Vec<shaping::ShapedGlyph>,
+    ) -> f32 {
+        let font = &self.faces[face];
+        // rustybuzz 的坐标是字体单位，按字号换算成像素
+        let px_per_unit = self.font_size as f32 / font.units_per_em;
+        shaping::shape_run(
+            &font.data,
+            font.source.index as u32,
+            face,
+            px_per_unit,
+            &self.features,
+            text,
+            range,
+            pen_x,
+            out,
+        )
+    }
+
+    // 取字形度量和图集位置，首次使用（或被回收后）才光栅化
+    fn glyph(&mut self, key: GlyphKey) -> Option<(Character, Option<AtlasRegion>)> {
+        if let Some(ch) = self.glyphs.characters.get(&key).copied() {
+            if ch.size.0 == 0 || ch.size.1 == 0 {
+                return Some((ch, None));
+            }
+            if let Some(region) = self.glyphs.atlas.get(&key) {
+                return Some((ch, Some(region)));
+            }
+        }
+
+        let font = &self.faces[key.face];
+        let loaded = if font.face.has_color() {
+            font.face.load_glyph(key.glyph, LoadFlag::RENDER | LoadFlag::COLOR)
+        } else {
+            let (flags, render_mode) = self.raster_flags();
+            font.render_glyph(key.glyph, flags, render_mode)
+        };
+        if let Err(e) = loaded {
+            warn!("Failed to load glyph {} from face {}: {}", key.glyph, key.face, e);
+            return None;
         }
+        let glyph = font.face.glyph();
+        let bitmap = glyph.bitmap();
+        // 次像素位图每个像素占 3 列
+        let width = match bitmap.pixel_mode() {
+            Ok(PixelMode::Lcd) => bitmap.width() / 3,
+            _ => bitmap.width(),
+        };
+        let ch = Character {
+            size: (width, bitmap.rows()),
+            bearing: (glyph.bitmap_left(), glyph.bitmap_top()),
+            scale: font.scale,
+        };
+        self.glyphs.characters.insert(key, ch);
 
-        for c in 32u32..128 {
-            face.load_char(c as usize, LoadFlag::RENDER)?;
-            let glyph = face.glyph();
+        let bgr = self.antialiasing == Antialiasing::SubpixelBgr;
+        let (format, pixels) = match bitmap_pixels(&bitmap, bgr) {
+            Some(converted) => converted,
+            None => {
+                warn!("Unsupported bitmap format for glyph {}", key.glyph);
+                return Some((ch, None));
+            }
+        };
+        let region = self.glyphs.atlas.insert(key, format, ch.size.0, ch.size.1, &pixels);
+        Some((ch, region))
+    }
+
+    // 按抗锯齿和微调设置选择加载标志与渲染模式
+    fn raster_flags(&self) -> (LoadFlag, RenderMode) {
+        let (target, render_mode) = match (self.antialiasing, self.hinting) {
+            (Antialiasing::None, _) => (LoadFlag::TARGET_MONO, RenderMode::Mono),
+            (Antialiasing::Grayscale, Hinting::Full) => (LoadFlag::TARGET_NORMAL, RenderMode::Normal),
+            (Antialiasing::Grayscale, _) => (LoadFlag::TARGET_LIGHT, RenderMode::Normal),
+            // 轻微微调只在竖直方向对齐，横向精度交给次像素
+            (_, Hinting::Full) => (LoadFlag::TARGET_LCD, RenderMode::Lcd),
+            (_, _) => (LoadFlag::TARGET_LIGHT, RenderMode::Lcd),
+        };
+        let flags = match self.hinting {
+            Hinting::None => LoadFlag::DEFAULT | LoadFlag::NO_HINTING,
+            _ => LoadFlag::DEFAULT | target,
+        };
+        (flags, render_mode)
+    }
+
+    // 距离场模式只用于足够大的字号，小字号位图更清晰
+    fn use_sdf(&self) -> bool {
+        self.render_mode == GlyphRenderMode::Sdf
+            && self.font_size as f32 >= self.sdf_min_size * self.content_scale
+    }
+
+    // 取距离场字形，缓存未命中时按基准字号光栅化再做距离变换
+    fn sdf_glyph(&mut self, key: GlyphKey) -> Option<(Character, Option<AtlasRegion>)> {
+        let cache = self.sdf_glyphs.get_or_insert_with(|| SdfGlyphs {
+            characters: HashMap::new(),
+            atlas: GlyphAtlas::new(MAX_ATLAS_PAGES),
+        });
+        if let Some(ch) = cache.characters.get(&key).copied() {
+            if ch.size.0 == 0 || ch.size.1 == 0 {
+                return Some((ch, None));
+            }
+            if let Some(region) = cache.atlas.get(&key) {
+                return Some((ch, Some(region)));
+            }
+        }
+
+        let font = &mut self.faces[key.face];
+        if let Err(e) = font.face.set_pixel_sizes(0, SDF_BASE_SIZE) {
+            warn!("Failed to set SDF base size: {}", e);
+            return None;
+        }
+        let loaded = font.render_glyph(key.glyph, LoadFlag::NO_HINTING, RenderMode::Normal);
+        let result = loaded.ok().and_then(|_| {
+            let glyph = font.face.glyph();
             let bitmap = glyph.bitmap();
+            let (_, coverage) = bitmap_pixels(&bitmap, false)?;
+            let (width, rows) = (bitmap.width() as usize, bitmap.rows() as usize);
+            let spread = SDF_SPREAD as i32;
+            if width == 0 || rows == 0 {
+                let ch = Character { size: (0, 0), bearing: (0, 0), scale: 1.0 };
+                return Some((ch, Vec::new()));
+            }
+            let (pixels, out_w, out_h) = sdf::coverage_to_sdf(&coverage, width, rows, SDF_SPREAD);
+            let ch = Character {
+                size: (out_w as i32, out_h as i32),
+                bearing: (glyph.bitmap_left() - spread, glyph.bitmap_top() + spread),
+                scale: 1.0,
+            };
+            Some((ch, pixels))
+        });
+        // 恢复当前字号，位图路径和整形还要用
+        if let Err(e) = font.set_size(self.font_size) {
+            warn!("Failed to restore font size: {}", e);
+        }
 
-            let mut texture_id = 0;
-            unsafe {
-                gl::GenTextures(1, &mut texture_id);
-                gl::BindTexture(gl::TEXTURE_2D, texture_id);
-                gl::TexImage2D(
-                    gl::TEXTURE_2D,
-                    0,
-                    gl::RED as i32,
-                    bitmap.width(),
-                    bitmap.rows(),
-                    0,
-                    gl::RED,
-                    gl::UNSIGNED_BYTE,
-                    bitmap.buffer().as_ptr() as *const _,
-                );
+        let (ch, pixels) = match result {
+            Some(result) => result,
+            None => {
+                warn!("Failed to build SDF for glyph {} from face {}", key.glyph, key.face);
+                return None;
+            }
+        };
+        let cache = self.sdf_glyphs.as_mut()?;
+        cache.characters.insert(key, ch);
+        if pixels.is_empty() {
+            return Some((ch, None));
+        }
+        let region = cache.atlas.insert(key, AtlasFormat::Alpha, ch.size.0, ch.size.1, &pixels);
+        Some((ch, region))
+    }
 
-                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
-                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
-                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
-                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-            }
-
-            if let Some(ch) = char::from_u32(c) {
-                characters.insert(
-                    ch,
-                    Character {
-                        texture_id,
-                        size: (bitmap.width(), bitmap.rows()),
-                        bearing: (glyph.bitmap_left(), glyph.bitmap_top()),
-                        advance: (glyph.advance().x >> 6) as u32,
-                    },
-                );
+    // 测量一行：和 render_spans 用同样的字体整形，carets 给出每个字符左边缘相对行首的 x。
+    // 比例字体下光标、选区和点击都要用它，不能按固定字宽推算
+    pub fn measure_line(&mut self, text: &str, spans: &[TextSpan]) -> Rc<ShapedLine> {
+        let mut variants = Vec::new();
+        let mut current = 0;
+        for span in spans {
+            if span.style.variant() != current {
+                current = span.style.variant();
+                variants.push((span.range.start, current));
             }
         }
+        self.shape_styled(text, variants)
+    }
+
+    // 按高亮片段把一行加入本帧的批次，(x, y) 是行框左上角，实际绘制在 flush 中进行；
+    // spans 按字节偏移升序且覆盖整行
+    pub fn render_spans(&mut self, text: &str, spans: &[TextSpan], x: f32, y: f32, scale: f32) {
+        let line = self.measure_line(text, spans);
+        let baseline = y + self.baseline_offset * scale;
+        let use_sdf = self.use_sdf();
+        let default_color = spans.first().map_or([1.0; 4], |span| span.color);
+
+        for shaped in &line.glyphs {
+            let key = GlyphKey { face: shaped.face, glyph: shaped.glyph_id };
+            // 彩色位图字体没有轮廓，只能走位图路径
+            let sdf = use_sdf && self.faces[key.face].face.is_scalable();
+            let glyph = if sdf { self.sdf_glyph(key) } else { self.glyph(key) };
+            let (ch, region) = match glyph {
+                Some((ch, Some(region))) => (ch, region),
+                _ => continue,
+            };
 
-        Ok(FontRenderer { characters, vbo, vao })
+            let glyph_scale = if sdf {
+                self.font_size as f32 / SDF_BASE_SIZE as f32 * scale
+            } else {
+                ch.scale * scale
+            };
+            // 字形贴到整像素上，避免线性采样把笔画糊开
+            let x0 = (x + shaped.x * scale).round() + ch.bearing.0 as f32 * glyph_scale;
+            // y 轴向下，rustybuzz 的 y_offset 向上为正
+            let y_top = baseline - shaped.y * scale - ch.bearing.1 as f32 * glyph_scale;
+            let w = ch.size.0 as f32 * glyph_scale;
+            let h = ch.size.1 as f32 * glyph_scale;
+            let span = spans.partition_point(|span| span.range.end <= shaped.cluster);
+            let color = spans.get(span).map_or(default_color, |span| span.color);
+
+            let batches = if sdf { &mut self.sdf_batches } else { &mut self.batches };
+            if batches.len() <= region.page {
+                batches.resize_with(region.page + 1, Vec::new);
+            }
+            batches[region.page].push(GlyphInstance {
+                rect: [x0, y_top, x0 + w, y_top + h],
+                uv: region.uv,
+                color,
+            });
+        }
+
+        for span in spans {
+            let decorations = [(span.style.underline, self.underline), (span.style.strikethrough, self.strikeout)];
+            if !decorations.iter().any(|&(enabled, _)| enabled) {
+                continue;
+            }
+            let start = line.caret_x(text[..span.range.start].chars().count());
+            let end = line.caret_x(text[..span.range.end].chars().count());
+            for (enabled, (offset, thickness)) in decorations {
+                if enabled {
+                    let top = (baseline + offset * scale).round();
+                    let rect = [(x + start * scale).round(), top, (x + end * scale).round(), top + thickness * scale];
+                    self.fill_rect(rect, span.color);
+                }
+            }
+        }
+    }
+
+    // 实心矩形用字形的着色器画，uv 指向图集里那块实心像素的中心；总在本帧的文字下面
+    pub fn fill_rect(&mut self, rect: [f32; 4], color: [f32; 4]) {
+        let region = match self.glyphs.atlas.get(&SOLID_KEY) {
+            Some(region) => region,
+            None => match self.glyphs.atlas.insert(SOLID_KEY, AtlasFormat::Alpha, 3, 3, &[255; 9]) {
+                Some(region) => region,
+                None => return,
+            },
+        };
+        let [u0, v0, u1, v1] = region.uv;
+        let (u, v) = ((u0 + u1) / 2.0, (v0 + v1) / 2.0);
+        self.rects.push(GlyphInstance { rect, uv: [u, v, u, v], color });
     }
 
-    pub fn render_text(&mut self, text: &str, x: f32, y: f32, scale: f32, color: &[f32; 4]) {
+    // 绘制本帧累积的全部文本：先画实心矩形，再每个图集页一次绘制调用
+    pub fn flush(&mut self) {
         unsafe {
             gl::Enable(gl::BLEND);
             gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::ActiveTexture(gl::TEXTURE0);
 
-            let mut x_pos = x;
+            self.shader.use_program();
+            self.shader.set_mat4("u_projection", &self.projection);
+            self.shader.set_int("u_atlas", 0);
+            self.shader.set_float("u_gamma", self.gamma);
+            self.shader.set_float("u_contrast", self.contrast);
+            self.draw_rects();
+            self.draw_pages(false);
 
-            for c in text.chars() {
-                if let Some(ch) = self.characters.get(&c) {
-                    let x_pos_f = x_pos;
-                    let y_pos_f = y + (ch.bearing.1 as f32 * scale);
+            if self.sdf_batches.iter().any(|batch| !batch.is_empty()) {
+                // 特效宽度换算成基准字号下的像素，并限制在距离场范围内
+                let to_base = SDF_BASE_SIZE as f32 / self.font_size as f32 * self.content_scale;
+                let max_width = SDF_SPREAD as f32 - 1.0;
+                self.sdf_shader.use_program();
+                self.sdf_shader.set_mat4("u_projection", &self.projection);
+                self.sdf_shader.set_int("u_atlas", 0);
+                self.sdf_shader.set_float("u_spread", SDF_SPREAD as f32);
+                self.sdf_shader.set_float("u_outline_width", (self.effects.outline_width * to_base).min(max_width));
+                self.sdf_shader.set_vec4("u_outline_color", &self.effects.outline_color);
+                self.sdf_shader.set_float("u_glow_radius", (self.effects.glow_radius * to_base).min(max_width));
+                self.sdf_shader.set_vec4("u_glow_color", &self.effects.glow_color);
+                self.draw_pages(true);
+            }
 
-                    let w = ch.size.0 as f32 * scale;
-                    let h = ch.size.1 as f32 * scale;
+            gl::UseProgram(0);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::Disable(gl::BLEND);
+        }
+    }
 
-                    let vertices: [f32; 24] = [
-                        // pos      // tex
-                        x_pos_f,     y_pos_f - h, 0.0, 1.0,
-                        x_pos_f + w, y_pos_f - h, 1.0, 1.0,
-                        x_pos_f + w, y_pos_f,     1.0, 0.0,
-                        x_pos_f,     y_pos_f - h, 0.0, 1.0,
-                        x_pos_f + w, y_pos_f,     1.0, 0.0,
-                        x_pos_f,     y_pos_f,     0.0, 0.0,
-                    ];
+    // 实心像素在本帧用过的页上，不会被换出，按它现在所在的页一次画完
+    unsafe fn draw_rects(&mut self) {
+        if self.rects.is_empty() {
+            return;
+        }
+        let rects = std::mem::take(&mut self.rects);
+        if let Some(region) = self.glyphs.atlas.get(&SOLID_KEY) {
+            gl::BindTexture(gl::TEXTURE_2D, self.glyphs.atlas.page_texture(region.page));
+            self.shader.set_int("u_glyph_mode", GLYPH_MODE_GRAY);
+            self.draw_batch(&rects);
+        }
+        self.rects = rects;
+        self.rects.clear();
+    }
 
-                    gl::BindTexture(gl::TEXTURE_2D, ch.texture_id);
-                    gl::BindVertexArray(self.vao);
+    unsafe fn draw_pages(&mut self, sdf: bool) {
+        let mut batches = std::mem::take(if sdf { &mut self.sdf_batches } else { &mut self.batches });
 
-                    gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
-                    gl::BufferSubData(
-                        gl::ARRAY_BUFFER,
-                        0,
-                        (vertices.len() * std::mem::size_of::<f32>()) as isize,
-                        vertices.as_ptr() as *const _,
-                    );
+        for (page, instances) in batches.iter_mut().enumerate() {
+            if instances.is_empty() {
+                continue;
+            }
+            if sdf {
+                let texture = self.sdf_glyphs.as_ref().map_or(0, |cache| cache.atlas.page_texture(page));
+                gl::BindTexture(gl::TEXTURE_2D, texture);
+                self.draw_batch(instances);
+                instances.clear();
+                continue;
+            }
 
-                    gl::DrawArrays(gl::TRIANGLES, 0, 6);
+            gl::BindTexture(gl::TEXTURE_2D, self.glyphs.atlas.page_texture(page));
+            match self.glyphs.atlas.page_format(page) {
+                AtlasFormat::Alpha => {
+                    self.shader.set_int("u_glyph_mode", GLYPH_MODE_GRAY);
+                    self.draw_batch(instances);
+                }
+                AtlasFormat::Rgba => {
+                    self.shader.set_int("u_glyph_mode", GLYPH_MODE_COLOR);
+                    self.draw_batch(instances);
+                }
+                AtlasFormat::Lcd => {
+                    // 逐通道混合：dst = dst * (1 - coverage) + color * coverage
+                    self.shader.set_int("u_glyph_mode", GLYPH_MODE_LCD_MASK);
+                    gl::BlendFunc(gl::ZERO, gl::ONE_MINUS_SRC_COLOR);
+                    self.draw_batch(instances);
+                    self.shader.set_int("u_glyph_mode", GLYPH_MODE_LCD_COLOR);
+                    gl::BlendFunc(gl::ONE, gl::ONE);
+                    self.draw_batch(instances);
+                    gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+                }
+            }
+            instances.clear();
+        }
 
-                    gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-                    gl::BindVertexArray(0);
+        if sdf {
+            self.sdf_batches = batches;
+        } else {
+            self.batches = batches;
+        }
+    }
 
-                    x_pos += ch.advance as f32 * scale;
-                }
+    unsafe fn draw_batch(&mut self, instances: &[GlyphInstance]) {
+        if self.instanced {
+            self.draw_instanced(instances);
+        } else {
+            self.draw_vertices(instances);
+        }
+    }
+
+    unsafe fn draw_instanced(&self, instances: &[GlyphInstance]) {
+        let stride = std::mem::size_of::<GlyphInstance>() as i32;
+        let float = std::mem::size_of::<f32>();
+
+        gl::BufferData(
+            gl::ARRAY_BUFFER,
+            (instances.len() * stride as usize) as isize,
+            instances.as_ptr() as *const _,
+            gl::STREAM_DRAW,
+        );
+        for (attrib, offset) in [(ATTRIB_RECT, 0), (ATTRIB_UV, 4), (ATTRIB_COLOR, 8)] {
+            gl::EnableVertexAttribArray(attrib);
+            gl::VertexAttribPointer(attrib, 4, gl::FLOAT, gl::FALSE, stride, (offset * float) as *const _);
+            gl::VertexAttribDivisor(attrib, 1);
+        }
+
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.quad_vbo);
+        gl::EnableVertexAttribArray(ATTRIB_CORNER);
+        gl::VertexAttribPointer(ATTRIB_CORNER, 2, gl::FLOAT, gl::FALSE, 0, std::ptr::null());
+
+        gl::DrawArraysInstanced(gl::TRIANGLE_STRIP, 0, 4, instances.len() as i32);
+
+        for attrib in [ATTRIB_CORNER, ATTRIB_RECT, ATTRIB_UV, ATTRIB_COLOR] {
+            gl::VertexAttribDivisor(attrib, 0);
+            gl::DisableVertexAttribArray(attrib);
+        }
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+    }
+
+    unsafe fn draw_vertices(&mut self, instances: &[GlyphInstance]) {
+        self.vertices.clear();
+        for g in instances {
+            let [x0, y0, x1, y1] = g.rect;
+            let [u0, v0, u1, v1] = g.uv;
+            let corners = [
+                ([x0, y0], [u0, v0]),
+                ([x1, y0], [u1, v0]),
+                ([x1, y1], [u1, v1]),
+                ([x0, y0], [u0, v0]),
+                ([x1, y1], [u1, v1]),
+                ([x0, y1], [u0, v1]),
+            ];
+            for (pos, uv) in corners {
+                self.vertices.push(GlyphVertex { pos, uv, color: g.color });
             }
+        }
 
-            gl::Disable(gl::BLEND);
+        let stride = std::mem::size_of::<GlyphVertex>() as i32;
+        let float = std::mem::size_of::<f32>();
+        gl::BufferData(
+            gl::ARRAY_BUFFER,
+            (self.vertices.len() * stride as usize) as isize,
+            self.vertices.as_ptr() as *const _,
+            gl::STREAM_DRAW,
+        );
+        for (attrib, size, offset) in [(ATTRIB_POSITION, 2, 0), (ATTRIB_UV, 2, 2), (ATTRIB_COLOR, 4, 4)] {
+            gl::EnableVertexAttribArray(attrib);
+            gl::VertexAttribPointer(attrib, size, gl::FLOAT, gl::FALSE, stride, (offset * float) as *const _);
+        }
+
+        gl::DrawArrays(gl::TRIANGLES, 0, self.vertices.len() as i32);
+
+        for attrib in [ATTRIB_POSITION, ATTRIB_UV, ATTRIB_COLOR] {
+            gl::DisableVertexAttribArray(attrib);
         }
     }
 }
 
+// 按绘制路径选顶点着色器，片段着色器决定位图还是距离场
+fn text_shader(instanced: bool, fragment_source: &str) -> Result<Shader> {
+    if instanced {
+        Shader::with_attributes(
+            TEXT_INSTANCED_VERTEX_SHADER,
+            fragment_source,
+            &[(ATTRIB_CORNER, "a_corner"), (ATTRIB_RECT, "a_rect"), (ATTRIB_UV, "a_uv"), (ATTRIB_COLOR, "a_color")],
+        )
+    } else {
+        Shader::with_attributes(
+            TEXT_VERTEX_SHADER,
+            fragment_source,
+            &[(ATTRIB_POSITION, "a_position"), (ATTRIB_UV, "a_uv"), (ATTRIB_COLOR, "a_color")],
+        )
+    }
+}
+
+// 按 fontconfig 找主字体的粗体/斜体；找不到同族的对应字体时由常规体合成
+fn load_style_variant(
+    library: &Library,
+    family: &str,
+    regular: &FontFace,
+    variant: usize,
+    font_size: u32,
+) -> Result<FontFace> {
+    let (bold, italic) = (variant & 1 != 0, variant & 2 != 0);
+    let mut pattern = family.to_string();
+    if bold {
+        pattern.push_str(":weight=bold");
+    }
+    if italic {
+        pattern.push_str(":slant=italic");
+    }
+
+    if let Some(source) = fontconfig::match_pattern(&pattern).filter(|source| *source != regular.source) {
+        match FontFace::load(library, source, font_size) {
+            // fontconfig 总会返回点什么，族名不同说明这一族没有该样式
+            Ok(mut font) if font.face.family_name() == regular.face.family_name() => {
+                let flags = font.face.style_flags();
+                font.embolden = bold && !flags.contains(StyleFlag::BOLD);
+                font.oblique = italic && !flags.contains(StyleFlag::ITALIC);
+                return Ok(font);
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to load '{}': {}", pattern, e),
+        }
+    }
+
+    info!("No '{}' face found, synthesizing it", pattern);
+    let mut font = FontFace::from_data(library, regular.data.clone(), regular.source.clone(), font_size)?;
+    font.embolden = bold;
+    font.oblique = italic;
+    Ok(font)
+}
+
+fn physical_size(size: f32, content_scale: f32) -> u32 {
+    (size * content_scale).round().max(1.0) as u32
+}
+
+// 像素坐标到 NDC 的正交投影，原点在左上角，y 轴向下
+pub fn ortho_projection(width: f32, height: f32) -> [f32; 16] {
+    let matrix = cgmath::ortho(0.0, width, height, 0.0, -1.0, 1.0);
+    *matrix.as_ref()
+}
+
+// 把 FreeType 位图转成图集需要的紧密排列格式，bgr 时交换次像素的红蓝通道
+fn bitmap_pixels(bitmap: &freetype::Bitmap, bgr: bool) -> Option<(AtlasFormat, Vec<u8>)> {
+    let (width, rows) = (bitmap.width() as usize, bitmap.rows() as usize);
+    let pitch = bitmap.pitch().unsigned_abs() as usize;
+    let buffer = bitmap.buffer();
+
+    match bitmap.pixel_mode().ok()? {
+        PixelMode::Gray => {
+            let mut pixels = Vec::with_capacity(width * rows);
+            for row in 0..rows {
+                pixels.extend_from_slice(&buffer[row * pitch..row * pitch + width]);
+            }
+            Some((AtlasFormat::Alpha, pixels))
+        }
+        PixelMode::Mono => {
+            let mut pixels = Vec::with_capacity(width * rows);
+            for row in 0..rows {
+                for col in 0..width {
+                    let bit = buffer[row * pitch + col / 8] & (0x80 >> (col % 8));
+                    pixels.push(if bit != 0 { 255 } else { 0 });
+                }
+            }
+            Some((AtlasFormat::Alpha, pixels))
+        }
+        PixelMode::Lcd => {
+            let mut pixels = Vec::with_capacity(width * rows);
+            for row in 0..rows {
+                for px in buffer[row * pitch..row * pitch + width].chunks_exact(3) {
+                    if bgr {
+                        pixels.extend_from_slice(&[px[2], px[1], px[0]]);
+                    } else {
+                        pixels.extend_from_slice(px);
+                    }
+                }
+            }
+            Some((AtlasFormat::Lcd, pixels))
+        }
+        PixelMode::Bgra => {
+            // FreeType 给出预乘的 BGRA，图集按非预乘 RGBA 存放
+            let mut pixels = Vec::with_capacity(width * rows * 4);
+            for row in 0..rows {
+                for px in buffer[row * pitch..row * pitch + width * 4].chunks_exact(4) {
+                    let (b, g, r, a) = (px[0] as u32, px[1] as u32, px[2] as u32, px[3] as u32);
+                    let unpremultiply = |c: u32| (c * 255).checked_div(a).map_or(0, |v| v.min(255) as u8);
+                    pixels.extend_from_slice(&[unpremultiply(r), unpremultiply(g), unpremultiply(b), a as u8]);
+                }
+            }
+            Some((AtlasFormat::Rgba, pixels))
+        }
+        _ => None,
+    }
+}
+
 impl Drop for FontRenderer {
     fn drop(&mut self) {
         unsafe {
-            for character in self.characters.values() {
-                gl::DeleteTextures(1, &character.texture_id);
-            }
             gl::DeleteBuffers(1, &self.vbo);
-            gl::DeleteVertexArrays(1, &self.vao);
+            if self.quad_vbo != 0 {
+                gl::DeleteBuffers(1, &self.quad_vbo);
+            }
         }
     }
-} 
\ No newline at end of file
+}
@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+use std::process::Command;
+use log::debug;
+
+// fontconfig 返回的字体文件及其在集合（.ttc）中的索引
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FontMatch {
+    pub path: PathBuf,
+    pub index: isize,
+}
+
+// 按 fontconfig 模式查找字体文件，例如 "JetBrains Mono" 或 "Noto Sans CJK SC:style=Bold"
+pub fn match_pattern(pattern: &str) -> Option<FontMatch> {
+    let output = Command::new("fc-match")
+        .args(["-f", "%{file}\t%{index}", pattern])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (file, index) = stdout.trim().split_once('\t')?;
+    if file.is_empty() {
+        return None;
+    }
+    debug!("fc-match '{}' -> {} ({})", pattern, file, index);
+
+    Some(FontMatch {
+        path: PathBuf::from(file),
+        index: index.parse().unwrap_or(0),
+    })
+}
+
+// 按字符覆盖查找字体，用于回退链中没有列出的字符
+pub fn match_char(c: char) -> Option<FontMatch> {
+    match_pattern(&format!(":charset={:x}", c as u32))
+}
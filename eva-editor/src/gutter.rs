@@ -0,0 +1,159 @@
+use std::ops::Range;
+use crate::editor::Buffer;
+use crate::font::{FontRenderer, TextSpan, TextStyle};
+use crate::markers::MarkerKind;
+use crate::primitives::{PrimitiveRenderer, RectStyle};
+use crate::theme::{LineNumbers, Metrics, Theme};
+
+// 至少按这么多位数留宽度，行数跨过 99、999 时栏宽不会来回跳
+const MIN_DIGITS: usize = 3;
+
+// 行号栏各部分的横向范围（物理像素），每帧按行数、字号和设置重新计算
+pub struct Gutter {
+    pub width: f32,
+    // 断点和书签
+    marker_lane: Range<f32>,
+    numbers_right: f32,
+    // 相对上次保存的改动条，紧贴文字区
+    vcs_lane: Range<f32>,
+}
+
+impl Gutter {
+    pub fn layout(buffer: &Buffer, theme: &Theme, metrics: &Metrics, font_renderer: &mut FontRenderer, scale: f32) -> Self {
+        let config = &theme.gutter;
+        let line_height = font_renderer.line_height();
+        let gap = (4.0 * scale).round();
+
+        let lane = if config.show_markers { (line_height * 0.6).round() } else { 0.0 };
+        let vcs = if config.show_markers { (3.0 * scale).round() } else { 0.0 };
+        let numbers = if config.line_numbers == LineNumbers::Off {
+            0.0
+        } else {
+            let line_count = buffer.content.matches('\n').count() + 1;
+            let digits = line_count.to_string().len().max(MIN_DIGITS);
+            let digit = font_renderer.measure_line("0", &[plain_span(1, [0.0; 4])]).width;
+            digits as f32 * digit + gap * 2.0
+        };
+
+        let needed = lane + numbers + vcs;
+        if needed <= 0.0 {
+            return Gutter { width: 0.0, marker_lane: 0.0..0.0, numbers_right: 0.0, vcs_lane: 0.0..0.0 };
+        }
+
+        // 宽度不够 Metrics 给的最小宽度时，多出的空间留给行号
+        let width = needed.max(metrics.line_numbers_width).round();
+        let vcs_lane = width - vcs..width;
+        Gutter {
+            width,
+            marker_lane: 0.0..lane,
+            numbers_right: vcs_lane.start - gap,
+            vcs_lane,
+        }
+    }
+
+    // 点在断点道上，x 相对于行号栏左边
+    pub fn in_marker_lane(&self, x: f32) -> bool {
+        self.marker_lane.contains(&x)
+    }
+
+    // 画 lines 范围内的行号和标记，first_y 是 lines.start 那一行的行顶；行号栏贴着 area 的左边
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &self,
+        buffer: &Buffer,
+        cursor_line: usize,
+        lines: Range<usize>,
+        first_y: f32,
+        area: [f32; 4],
+        theme: &Theme,
+        primitives: &mut PrimitiveRenderer,
+        font_renderer: &mut FontRenderer,
+    ) {
+        if self.width <= 0.0 {
+            return;
+        }
+        let colors = &theme.colors;
+        let [x0, top, _, bottom] = area;
+        let background = colors.background;
+        let shade = [background[0] * 0.7, background[1] * 0.7, background[2] * 0.7, 1.0];
+        primitives.fill_rect([x0, top, x0 + self.width, bottom], shade);
+        primitives.fill_rect([x0 + self.width - 1.0, top, x0 + self.width, bottom], colors.grid);
+
+        let line_height = font_renderer.line_height();
+        let mut y = first_y;
+        for line in lines {
+            let current = line == cursor_line;
+            if current {
+                let mut highlight = colors.accent;
+                highlight[3] = 0.25;
+                primitives.fill_rect([x0, y, x0 + self.width - 1.0, y + line_height], highlight);
+            }
+            self.render_number(line, cursor_line, x0, y, theme, font_renderer);
+            self.render_markers(buffer, line, x0, y, line_height, theme, primitives);
+            y += line_height;
+        }
+    }
+
+    fn render_number(&self, line: usize, cursor_line: usize, x0: f32, y: f32, theme: &Theme, font_renderer: &mut FontRenderer) {
+        let current = line == cursor_line;
+        let number = match theme.gutter.line_numbers {
+            LineNumbers::Off => return,
+            LineNumbers::Absolute => line + 1,
+            LineNumbers::Relative => line.abs_diff(cursor_line),
+            LineNumbers::Hybrid if current => line + 1,
+            LineNumbers::Hybrid => line.abs_diff(cursor_line),
+        };
+        let text = number.to_string();
+        let color = if current { theme.colors.warning } else { theme.syntax.comments };
+        let mut span = plain_span(text.len(), color);
+        span.style.bold = current;
+        let spans = [span];
+        let width = font_renderer.measure_line(&text, &spans).width;
+        font_renderer.render_spans(&text, &spans, (x0 + self.numbers_right - width).round(), y, 1.0);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn render_markers(
+        &self,
+        buffer: &Buffer,
+        line: usize,
+        x0: f32,
+        y: f32,
+        line_height: f32,
+        theme: &Theme,
+        primitives: &mut PrimitiveRenderer,
+    ) {
+        let colors = &theme.colors;
+        let offset = |range: &Range<f32>| x0 + range.start..x0 + range.end;
+        let marker_lane = offset(&self.marker_lane);
+        let vcs_lane = offset(&self.vcs_lane);
+        let center_y = y + line_height / 2.0;
+        for kind in buffer.markers.shown_on_line(line) {
+            match kind {
+                MarkerKind::Breakpoint | MarkerKind::Bookmark => {
+                    let size = (marker_lane.end - marker_lane.start) * 0.7;
+                    let center_x = (marker_lane.start + marker_lane.end) / 2.0;
+                    let rect = [center_x - size / 2.0, center_y - size / 2.0, center_x + size / 2.0, center_y + size / 2.0];
+                    let (color, radius) = match kind {
+                        MarkerKind::Breakpoint => (colors.error, size / 2.0),
+                        _ => (colors.accent, size / 5.0),
+                    };
+                    primitives.draw_rect(rect, &RectStyle::solid(color).rounded(radius));
+                }
+                MarkerKind::Added | MarkerKind::Modified => {
+                    let color = if kind == MarkerKind::Added { colors.success } else { colors.warning };
+                    primitives.fill_rect([vcs_lane.start, y, vcs_lane.end, y + line_height], color);
+                }
+                MarkerKind::Deleted => {
+                    // 被删掉的行夹在这一行和上一行之间
+                    let height = vcs_lane.end - vcs_lane.start;
+                    primitives.fill_rect([vcs_lane.start - height, y - height, vcs_lane.end, y + height], colors.error);
+                }
+            }
+        }
+    }
+}
+
+fn plain_span(len: usize, color: [f32; 4]) -> TextSpan {
+    TextSpan { range: 0..len, color, style: TextStyle::default() }
+}
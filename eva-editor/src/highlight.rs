@@ -0,0 +1,356 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use log::warn;
+use syntect::highlighting::{
+    Color, FontStyle, Highlighter as ThemeHighlighter, ScopeSelectors, StyleModifier, Theme as SyntectTheme, ThemeItem,
+    ThemeSettings,
+};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+use crate::editor::Buffer;
+use crate::font::{TextSpan, TextStyle};
+use crate::theme::Theme;
+
+// 每隔多少行保存一次解析状态，滚动时从最近的检查点继续解析
+const CHECKPOINT_INTERVAL: usize = 64;
+
+// 语法定义只加载一次，Buffer 可以直接持有 &'static SyntaxReference
+pub fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+// 先按扩展名找语法，找不到再看首行（#!/bin/sh、<?xml 等）
+pub fn detect_syntax(path: &Path, content: &str) -> Option<&'static SyntaxReference> {
+    let syntax_set = syntax_set();
+    syntax_set
+        .find_syntax_for_file(path)
+        .ok()
+        .flatten()
+        .or_else(|| syntax_set.find_syntax_by_first_line(content.lines().next().unwrap_or("")))
+}
+
+// 一行开头的解析状态
+type Checkpoint = (ParseState, ScopeStack);
+
+// 把缓冲区内容切成带颜色和字体样式的片段，解析状态按检查点缓存
+pub struct Highlighter {
+    theme: SyntectTheme,
+    // syntect 的字体样式里没有删除线，按作用域单独判断
+    strikethrough: ScopeSelectors,
+    // 检查点对应的缓冲区、版本和语法。换了缓冲区或语法要从头解析，
+    // 内容改了只丢掉修改的第一行之后的检查点
+    buffer_id: u64,
+    version: u64,
+    syntax_name: String,
+    // checkpoints[i] 是第 i * CHECKPOINT_INTERVAL 行开头的解析状态
+    checkpoints: Vec<Checkpoint>,
+    // 修改前的检查点，第一个的下标是 stale_from。修改没有增减行数时，重新解析到
+    // stale_after 行之后的检查点、状态和修改前一样，后面的就还能接着用
+    stale: Vec<Checkpoint>,
+    stale_from: usize,
+    stale_after: usize,
+}
+
+impl Highlighter {
+    pub fn new(theme: &Theme) -> Self {
+        Highlighter {
+            theme: build_theme(theme),
+            strikethrough: ScopeSelectors::from_str("markup.strikethrough, invalid.deprecated").unwrap_or_default(),
+            buffer_id: 0,
+            version: 0,
+            syntax_name: String::new(),
+            checkpoints: Vec::new(),
+            stale: Vec::new(),
+            stale_from: 0,
+            stale_after: 0,
+        }
+    }
+
+    // 高亮 [first, first + count) 行，缓冲区没有识别出语法时返回 None
+    pub fn highlight(&mut self, buffer: &Buffer, first: usize, count: usize) -> Option<Vec<Vec<TextSpan>>> {
+        let syntax = buffer.syntax?;
+        if self.checkpoints.is_empty() || self.buffer_id != buffer.id || self.syntax_name != syntax.name {
+            self.buffer_id = buffer.id;
+            self.version = buffer.version;
+            self.syntax_name = syntax.name.clone();
+            self.checkpoints = vec![(ParseState::new(syntax), ScopeStack::new())];
+            self.stale.clear();
+        } else if self.version != buffer.version {
+            self.invalidate(buffer);
+        }
+
+        let highlighter = ThemeHighlighter::new(&self.theme);
+        let checkpoint = (first / CHECKPOINT_INTERVAL).min(self.checkpoints.len() - 1);
+        let (mut state, mut stack) = self.checkpoints[checkpoint].clone();
+        let mut lines = Vec::with_capacity(count);
+
+        let mut index = checkpoint * CHECKPOINT_INTERVAL;
+        let mut rest = LinesWithEndings::from(&buffer.content).skip(index);
+        while index < first + count {
+            if index.is_multiple_of(CHECKPOINT_INTERVAL) && index / CHECKPOINT_INTERVAL == self.checkpoints.len() {
+                self.checkpoints.push((state.clone(), stack.clone()));
+                // 接上了修改前的检查点，直接跳到离 first 最近的那个
+                if reuse_stale(&mut self.checkpoints, &mut self.stale, self.stale_from, self.stale_after) {
+                    let target = (first / CHECKPOINT_INTERVAL).min(self.checkpoints.len() - 1) * CHECKPOINT_INTERVAL;
+                    if target > index {
+                        (state, stack) = self.checkpoints[target / CHECKPOINT_INTERVAL].clone();
+                        rest.nth(target - index - 1);
+                        index = target;
+                        continue;
+                    }
+                }
+            }
+            let Some(line) = rest.next() else {
+                break;
+            };
+            let spans = highlight_line(&highlighter, &self.strikethrough, line, &mut state, &mut stack);
+            if index >= first {
+                lines.push(spans);
+            }
+            index += 1;
+        }
+        Some(lines)
+    }
+
+    // 内容改了：第 i 个检查点只取决于第 i * CHECKPOINT_INTERVAL 行之前的内容，修改的第一行之前的都留着
+    fn invalidate(&mut self, buffer: &Buffer) {
+        let change = buffer.changes_since(self.version);
+        self.version = buffer.version;
+        let Some(change) = change else {
+            self.checkpoints.truncate(1);
+            self.stale.clear();
+            return;
+        };
+
+        let keep = (change.first / CHECKPOINT_INTERVAL + 1).min(self.checkpoints.len());
+        let mut tail = self.checkpoints.split_off(keep);
+        // 上次修改后还没追上的旧检查点接在后面，一起当作修改前的状态
+        let skip = (keep + tail.len()).saturating_sub(self.stale_from);
+        if skip < self.stale.len() {
+            tail.extend(self.stale.drain(skip..));
+        } else {
+            self.stale_after = 0;
+        }
+        self.stale.clear();
+        if change.delta == 0 {
+            self.stale = tail;
+            self.stale_from = keep;
+            self.stale_after = self.stale_after.max(change.last);
+        }
+    }
+
+}
+
+// 刚加上的检查点和修改前一样时，把后面的旧检查点接上，返回是否接上了
+fn reuse_stale(checkpoints: &mut Vec<Checkpoint>, stale: &mut Vec<Checkpoint>, stale_from: usize, stale_after: usize) -> bool {
+    let index = checkpoints.len() - 1;
+    if stale.is_empty() || index < stale_from || index * CHECKPOINT_INTERVAL <= stale_after {
+        return false;
+    }
+    let offset = index - stale_from;
+    if offset >= stale.len() {
+        stale.clear();
+        return false;
+    }
+    if stale[offset] != checkpoints[index] {
+        return false;
+    }
+    let rest = stale.split_off(offset + 1);
+    stale.clear();
+    checkpoints.extend(rest);
+    true
+}
+
+// 解析一行并按作用域栈切分，相邻同样式的片段合并
+fn highlight_line(
+    highlighter: &ThemeHighlighter,
+    strikethrough: &ScopeSelectors,
+    line: &str,
+    state: &mut ParseState,
+    stack: &mut ScopeStack,
+) -> Vec<TextSpan> {
+    let ops = match state.parse_line(line, syntax_set()) {
+        Ok(ops) => ops,
+        Err(e) => {
+            warn!("Syntax parsing failed: {}", e);
+            Vec::new()
+        }
+    };
+    let text_len = line.trim_end_matches(['\n', '\r']).len();
+
+    let mut spans: Vec<TextSpan> = Vec::new();
+    let mut push = |start: usize, end: usize, stack: &ScopeStack| {
+        let end = end.min(text_len);
+        if start >= end {
+            return;
+        }
+        let style = highlighter.style_for_stack(stack.as_slice());
+        let color = to_rgba(style.foreground);
+        let style = TextStyle {
+            bold: style.font_style.contains(FontStyle::BOLD),
+            italic: style.font_style.contains(FontStyle::ITALIC),
+            underline: style.font_style.contains(FontStyle::UNDERLINE),
+            strikethrough: strikethrough.does_match(stack.as_slice()).is_some(),
+        };
+        match spans.last_mut() {
+            Some(last) if last.range.end == start && last.color == color && last.style == style => {
+                last.range.end = end;
+            }
+            _ => spans.push(TextSpan { range: start..end, color, style }),
+        }
+    };
+
+    let mut start = 0;
+    for (offset, op) in ops {
+        push(start, offset, stack);
+        start = offset;
+        if let Err(e) = stack.apply(&op) {
+            warn!("Invalid scope operation: {}", e);
+        }
+    }
+    push(start, text_len, stack);
+    spans
+}
+
+// 用 EVA 语法配色生成 syntect 主题：关键字加粗，注释斜体
+fn build_theme(theme: &Theme) -> SyntectTheme {
+    let syntax = &theme.syntax;
+    let (r, g, b) = theme.foreground;
+    let rules: [(&str, Option<[f32; 4]>, FontStyle); 10] = [
+        ("comment", Some(syntax.comments), FontStyle::ITALIC),
+        ("string", Some(syntax.strings), FontStyle::empty()),
+        ("constant.numeric, constant.language", Some(syntax.numbers), FontStyle::empty()),
+        ("keyword, storage.modifier", Some(syntax.keywords), FontStyle::BOLD),
+        ("storage.type, entity.name.type, support.type", Some(syntax.types), FontStyle::empty()),
+        ("entity.name.function, support.function", Some(syntax.functions), FontStyle::empty()),
+        ("markup.heading", Some(syntax.keywords), FontStyle::BOLD),
+        ("markup.bold", None, FontStyle::BOLD),
+        ("markup.italic", None, FontStyle::ITALIC),
+        ("markup.underline, markup.underline.link", None, FontStyle::UNDERLINE),
+    ];
+
+    let scopes = rules
+        .iter()
+        .filter_map(|&(selector, color, font_style)| {
+            Some(ThemeItem {
+                scope: ScopeSelectors::from_str(selector).ok()?,
+                style: StyleModifier {
+                    foreground: color.map(to_color),
+                    background: None,
+                    font_style: Some(font_style),
+                },
+            })
+        })
+        .collect();
+
+    SyntectTheme {
+        name: Some("EVA".to_string()),
+        settings: ThemeSettings {
+            foreground: Some(Color { r, g, b, a: 255 }),
+            ..ThemeSettings::default()
+        },
+        scopes,
+        ..SyntectTheme::default()
+    }
+}
+
+fn to_color(c: [f32; 4]) -> Color {
+    let channel = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Color { r: channel(c[0]), g: channel(c[1]), b: channel(c[2]), a: channel(c[3]) }
+}
+
+fn to_rgba(c: Color) -> [f32; 4] {
+    [c.r as f32 / 255.0, c.g as f32 / 255.0, c.b as f32 / 255.0, c.a as f32 / 255.0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rust_buffer(content: &str) -> Buffer {
+        let mut buffer = Buffer::from_content(content.to_string(), None);
+        buffer.syntax = syntax_set().find_syntax_by_extension("rs");
+        buffer
+    }
+
+    // 增量高亮的结果要和新建的高亮器从头解析的一样
+    fn assert_matches_fresh(highlighter: &mut Highlighter, buffer: &Buffer, first: usize, count: usize) {
+        let theme = Theme::default();
+        let expected = Highlighter::new(&theme).highlight(buffer, first, count).unwrap();
+        let actual = highlighter.highlight(buffer, first, count).unwrap();
+        assert_eq!(actual.len(), expected.len());
+        for (line, (a, e)) in actual.iter().zip(&expected).enumerate() {
+            let a: Vec<_> = a.iter().map(|s| (s.range.clone(), s.color)).collect();
+            let e: Vec<_> = e.iter().map(|s| (s.range.clone(), s.color)).collect();
+            assert_eq!(a, e, "line {}", first + line);
+        }
+    }
+
+    fn sample(lines: usize) -> String {
+        (0..lines).map(|i| format!("fn f{}() {{ let s = \"{}\"; }} // {}\n", i, i, i)).collect()
+    }
+
+    #[test]
+    fn edit_keeps_checkpoints_above_it() {
+        let mut buffer = rust_buffer(&sample(1000));
+        let mut highlighter = Highlighter::new(&Theme::default());
+        highlighter.highlight(&buffer, 900, 40);
+        let before = highlighter.checkpoints.len();
+
+        let position = buffer.content.match_indices('\n').nth(899).unwrap().0;
+        buffer.insert(position, "x");
+        highlighter.invalidate(&buffer);
+        assert_eq!(highlighter.checkpoints.len(), 899 / CHECKPOINT_INTERVAL + 1);
+        assert_eq!(highlighter.stale.len(), before - highlighter.checkpoints.len());
+        assert_matches_fresh(&mut highlighter, &buffer, 900, 40);
+    }
+
+    #[test]
+    fn reparse_stops_when_state_matches_again() {
+        let mut buffer = rust_buffer(&sample(1000));
+        let mut highlighter = Highlighter::new(&Theme::default());
+        highlighter.highlight(&buffer, 960, 40);
+
+        buffer.insert(10, "x");
+        highlighter.highlight(&buffer, 0, CHECKPOINT_INTERVAL + 1);
+        // 第 64 行的状态和修改前一样，后面的检查点都接上了
+        assert!(highlighter.stale.is_empty());
+        assert_eq!(highlighter.checkpoints.len(), 999 / CHECKPOINT_INTERVAL + 1);
+        assert_matches_fresh(&mut highlighter, &buffer, 960, 40);
+    }
+
+    #[test]
+    fn unterminated_comment_changes_everything_below() {
+        let mut buffer = rust_buffer(&sample(300));
+        let mut highlighter = Highlighter::new(&Theme::default());
+        highlighter.highlight(&buffer, 250, 40);
+
+        buffer.insert(0, "/*");
+        assert_matches_fresh(&mut highlighter, &buffer, 250, 40);
+        buffer.delete(0, 2);
+        assert_matches_fresh(&mut highlighter, &buffer, 250, 40);
+        // 增减行数的修改
+        buffer.insert(0, "/*\n");
+        assert_matches_fresh(&mut highlighter, &buffer, 200, 100);
+        let end = buffer.content.len();
+        buffer.insert(end, "*/\n");
+        assert_matches_fresh(&mut highlighter, &buffer, 0, 400);
+    }
+
+    #[test]
+    fn several_edits_between_frames() {
+        let mut buffer = rust_buffer(&sample(500));
+        let mut highlighter = Highlighter::new(&Theme::default());
+        highlighter.highlight(&buffer, 400, 50);
+        for (i, text) in ["\"", "x", "\"", "/*", "*/", "y\n"].iter().enumerate() {
+            let position = buffer.content.match_indices('\n').nth(100 + i * 70).unwrap().0;
+            buffer.insert(position, text);
+            if i % 2 == 1 {
+                assert_matches_fresh(&mut highlighter, &buffer, 0, 30);
+            }
+        }
+        assert_matches_fresh(&mut highlighter, &buffer, 0, 600);
+    }
+}
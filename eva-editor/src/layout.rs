@@ -0,0 +1,508 @@
+use std::collections::HashMap;
+
+// 面板和弹出层的名字，同一个 Layout 里不能重复
+pub type PanelId = &'static str;
+
+// 面板沿父面板排列方向占多少空间
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Constraint {
+    // 固定长度
+    Length(f32),
+    // 固定长度和比例分完后，按权重分剩下的空间
+    Fill(f32),
+}
+
+impl Constraint {
+    fn scaled(self, scale: f32) -> Constraint {
+        match self {
+            Constraint::Length(length) => Constraint::Length(length * scale),
+            other => other,
+        }
+    }
+}
+
+// 子面板的排列方向
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    // 从左到右
+    Horizontal,
+    // 从上到下
+    Vertical,
+}
+
+// 四边留白
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Insets {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl Insets {
+    pub fn uniform(value: f32) -> Self {
+        Insets { left: value, top: value, right: value, bottom: value }
+    }
+
+    fn scaled(self, scale: f32) -> Insets {
+        Insets { left: self.left * scale, top: self.top * scale, right: self.right * scale, bottom: self.bottom * scale }
+    }
+
+    // 把 rect 向内收缩，收不下时宽高为 0
+    pub fn shrink(&self, rect: [f32; 4]) -> [f32; 4] {
+        let [x0, y0, x1, y1] = rect;
+        let left = (x0 + self.left).round();
+        let top = (y0 + self.top).round();
+        [left, top, (x1 - self.right).round().max(left), (y1 - self.bottom).round().max(top)]
+    }
+}
+
+// 布局树的节点。长度和留白用逻辑像素，compute 时乘上缩放
+pub struct Panel {
+    pub id: PanelId,
+    constraint: Constraint,
+    axis: Axis,
+    padding: Insets,
+    visible: bool,
+    // 点中后能拿到键盘焦点
+    focusable: bool,
+    children: Vec<Panel>,
+}
+
+impl Panel {
+    pub fn new(id: PanelId, constraint: Constraint) -> Self {
+        Panel {
+            id,
+            constraint,
+            axis: Axis::Vertical,
+            padding: Insets::default(),
+            visible: true,
+            focusable: false,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn axis(mut self, axis: Axis) -> Self {
+        self.axis = axis;
+        self
+    }
+
+    pub fn padding(mut self, padding: Insets) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn focusable(mut self) -> Self {
+        self.focusable = true;
+        self
+    }
+
+    pub fn hidden(mut self) -> Self {
+        self.visible = false;
+        self
+    }
+
+    pub fn child(mut self, child: Panel) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    fn find(&self, id: PanelId) -> Option<&Panel> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| child.find(id))
+    }
+
+    fn find_mut(&mut self, id: PanelId) -> Option<&mut Panel> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.children.iter_mut().find_map(|child| child.find_mut(id))
+    }
+}
+
+// 弹出层放在哪里
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Anchor {
+    // 窗口中央
+    Center,
+}
+
+// 浮在面板上面的弹出层，z 大的在上层
+#[derive(Clone, Debug)]
+pub struct Overlay {
+    pub id: PanelId,
+    pub anchor: Anchor,
+    // 逻辑像素
+    pub size: [f32; 2],
+    pub z: i32,
+    // 打开时挡住下面所有面板的点击
+    pub modal: bool,
+    // 打开时拿走键盘焦点；不拿的（比如文件冲突提示）点一下或按 F6 才获得焦点，正在打的字不会落到它身上
+    pub takes_focus: bool,
+}
+
+// 保留的布局：面板树和弹出层常驻，尺寸变化时 compute 重新算出各自的区域（物理像素），
+// 绘制、命中测试和焦点都按算好的结果来
+pub struct Layout {
+    root: Panel,
+    overlays: Vec<Overlay>,
+    rects: HashMap<PanelId, [f32; 4]>,
+    // 去掉留白后的内容区
+    content: HashMap<PanelId, [f32; 4]>,
+    // 可见面板的先序，父面板在子面板前面
+    order: Vec<PanelId>,
+    // 按 z 从下到上
+    overlay_rects: Vec<(PanelId, [f32; 4])>,
+    area: [f32; 4],
+    scale: f32,
+    focused: Option<PanelId>,
+    // 弹出层抢走焦点前的焦点，关掉时还回去
+    focus_stack: Vec<PanelId>,
+}
+
+impl Layout {
+    pub fn new(root: Panel) -> Self {
+        let mut layout = Layout {
+            root,
+            overlays: Vec::new(),
+            rects: HashMap::new(),
+            content: HashMap::new(),
+            order: Vec::new(),
+            overlay_rects: Vec::new(),
+            area: [0.0; 4],
+            scale: 1.0,
+            focused: None,
+            focus_stack: Vec::new(),
+        };
+        layout.focused = layout.focusable_panels().first().copied();
+        layout
+    }
+
+    // 把整棵树排进 area
+    pub fn compute(&mut self, area: [f32; 4], scale: f32) {
+        self.area = area;
+        self.scale = scale;
+        self.rects.clear();
+        self.content.clear();
+        self.order.clear();
+        if self.root.visible {
+            compute_panel(&self.root, area, scale, &mut self.rects, &mut self.content, &mut self.order);
+        }
+        self.place_overlays();
+    }
+
+    pub fn rect(&self, id: PanelId) -> Option<[f32; 4]> {
+        self.rects.get(id).copied().or_else(|| self.overlay_rect(id))
+    }
+
+    pub fn content(&self, id: PanelId) -> Option<[f32; 4]> {
+        self.content.get(id).copied().or_else(|| self.overlay_rect(id))
+    }
+
+    pub fn is_visible(&self, id: PanelId) -> bool {
+        self.rects.contains_key(id) || self.overlay_rect(id).is_some()
+    }
+
+    // 藏起获得焦点的面板时，焦点交给第一个还能获得焦点的面板
+    pub fn set_visible(&mut self, id: PanelId, visible: bool) {
+        if let Some(panel) = self.root.find_mut(id) {
+            panel.visible = visible;
+        }
+        self.compute(self.area, self.scale);
+        if self.focused.is_some_and(|focused| !self.is_visible(focused)) {
+            self.focused = self.focusable_panels().first().copied();
+        }
+    }
+
+    // 打开弹出层，takes_focus 的把焦点给它；同 id 的会被替换
+    pub fn show_overlay(&mut self, overlay: Overlay) {
+        let id = overlay.id;
+        let takes_focus = overlay.takes_focus;
+        match self.overlays.iter_mut().find(|o| o.id == id) {
+            Some(existing) => *existing = overlay,
+            None => self.overlays.push(overlay),
+        }
+        self.place_overlays();
+        if takes_focus && self.focused != Some(id) {
+            self.focus_stack.extend(self.focused);
+            self.focused = Some(id);
+        }
+    }
+
+    pub fn hide_overlay(&mut self, id: PanelId) {
+        self.overlays.retain(|o| o.id != id);
+        self.place_overlays();
+        self.focus_stack.retain(|&panel| panel != id);
+        if self.focused == Some(id) {
+            self.focused = self.focus_stack.pop().or_else(|| self.focusable_panels().first().copied());
+        }
+    }
+
+    // 最上层的弹出层
+    pub fn top_overlay(&self) -> Option<PanelId> {
+        self.overlay_rects.last().map(|(id, _)| *id)
+    }
+
+    // 按 z 从下到上
+    pub fn overlays(&self) -> &[(PanelId, [f32; 4])] {
+        &self.overlay_rects
+    }
+
+    // 坐标处最上面的东西：先看弹出层，再看最深的面板；模态弹出层挡住下面的一切
+    pub fn hit_test(&self, x: f32, y: f32) -> Option<PanelId> {
+        for (id, rect) in self.overlay_rects.iter().rev() {
+            let modal = self.overlays.iter().any(|o| o.id == *id && o.modal);
+            if modal || contains(*rect, x, y) {
+                return Some(*id);
+            }
+        }
+        self.order.iter().rev().find(|id| contains(self.rects[*id], x, y)).copied()
+    }
+
+    // 按下鼠标：点中的面板能获得焦点就把焦点给它，返回点中的面板
+    pub fn focus_at(&mut self, x: f32, y: f32) -> Option<PanelId> {
+        let hit = self.hit_test(x, y)?;
+        if self.is_focusable(hit) {
+            self.focused = Some(hit);
+        }
+        Some(hit)
+    }
+
+    pub fn focused(&self) -> Option<PanelId> {
+        self.focused
+    }
+
+    // 在能获得焦点的面板和打开的弹出层之间轮换，弹出层排在面板后面；模态弹出层打开时焦点留在它身上
+    pub fn focus_next(&mut self, backward: bool) {
+        let modal = self.top_overlay().is_some_and(|top| self.overlays.iter().any(|o| o.id == top && o.modal));
+        if modal {
+            return;
+        }
+        let mut panels = self.focusable_panels();
+        panels.extend(self.overlay_rects.iter().map(|(id, _)| *id));
+        if panels.is_empty() {
+            return;
+        }
+        let current = self.focused.and_then(|id| panels.iter().position(|&p| p == id));
+        let next = match (current, backward) {
+            (Some(i), false) => (i + 1) % panels.len(),
+            (Some(i), true) => (i + panels.len() - 1) % panels.len(),
+            (None, _) => 0,
+        };
+        self.focused = Some(panels[next]);
+    }
+
+    fn is_focusable(&self, id: PanelId) -> bool {
+        self.overlay_rect(id).is_some()
+            || (self.rects.contains_key(id) && self.root.find(id).is_some_and(|panel| panel.focusable))
+    }
+
+    // 可见且能获得焦点的面板，按树的先序
+    fn focusable_panels(&self) -> Vec<PanelId> {
+        let mut panels = Vec::new();
+        collect_focusable(&self.root, &mut panels);
+        panels
+    }
+
+    fn overlay_rect(&self, id: PanelId) -> Option<[f32; 4]> {
+        self.overlay_rects.iter().find(|(overlay, _)| *overlay == id).map(|(_, rect)| *rect)
+    }
+
+    // 弹出层按 z 排序，夹在窗口范围内
+    fn place_overlays(&mut self) {
+        let mut overlays: Vec<&Overlay> = self.overlays.iter().collect();
+        overlays.sort_by_key(|o| o.z);
+        self.overlay_rects = overlays
+            .into_iter()
+            .map(|overlay| (overlay.id, place_overlay(overlay, self.area, self.scale)))
+            .collect();
+    }
+}
+
+// 沿 axis 把 area 按约束切开，长度是物理像素；分界取整，相邻的块之间不留缝也不重叠
+pub fn split(area: [f32; 4], axis: Axis, constraints: &[Constraint]) -> Vec<[f32; 4]> {
+    let [x0, y0, x1, y1] = area;
+    let (start, end) = match axis {
+        Axis::Horizontal => (x0, x1),
+        Axis::Vertical => (y0, y1),
+    };
+    let total = (end - start).max(0.0);
+    let fixed: f32 = constraints
+        .iter()
+        .map(|c| match c {
+            Constraint::Length(length) => length.max(0.0),
+            Constraint::Fill(_) => 0.0,
+        })
+        .sum();
+    let weights: f32 = constraints.iter().map(|c| if let Constraint::Fill(w) = c { w.max(0.0) } else { 0.0 }).sum();
+    let remaining = (total - fixed).max(0.0);
+
+    let mut offset = 0.0;
+    constraints
+        .iter()
+        .map(|c| {
+            let length = match c {
+                Constraint::Length(length) => length.max(0.0),
+                Constraint::Fill(w) if weights > 0.0 => remaining * w.max(0.0) / weights,
+                Constraint::Fill(_) => 0.0,
+            };
+            // 固定长度加起来超出时，后面的块被挤到 0
+            let a = (start + offset).round().min(end);
+            offset += length;
+            let b = (start + offset).round().min(end);
+            match axis {
+                Axis::Horizontal => [a, y0, b, y1],
+                Axis::Vertical => [x0, a, x1, b],
+            }
+        })
+        .collect()
+}
+
+fn compute_panel(
+    panel: &Panel,
+    rect: [f32; 4],
+    scale: f32,
+    rects: &mut HashMap<PanelId, [f32; 4]>,
+    content: &mut HashMap<PanelId, [f32; 4]>,
+    order: &mut Vec<PanelId>,
+) {
+    let inner = panel.padding.scaled(scale).shrink(rect);
+    rects.insert(panel.id, rect);
+    content.insert(panel.id, inner);
+    order.push(panel.id);
+
+    let children: Vec<&Panel> = panel.children.iter().filter(|child| child.visible).collect();
+    let constraints: Vec<Constraint> = children.iter().map(|child| child.constraint.scaled(scale)).collect();
+    for (child, area) in children.into_iter().zip(split(inner, panel.axis, &constraints)) {
+        compute_panel(child, area, scale, rects, content, order);
+    }
+}
+
+fn collect_focusable(panel: &Panel, panels: &mut Vec<PanelId>) {
+    if !panel.visible {
+        return;
+    }
+    if panel.focusable {
+        panels.push(panel.id);
+    }
+    for child in &panel.children {
+        collect_focusable(child, panels);
+    }
+}
+
+fn place_overlay(overlay: &Overlay, area: [f32; 4], scale: f32) -> [f32; 4] {
+    let [left, top, right, bottom] = area;
+    let width = (overlay.size[0] * scale).round().min(right - left).max(0.0);
+    let height = (overlay.size[1] * scale).round().min(bottom - top).max(0.0);
+    let (x, y) = match overlay.anchor {
+        Anchor::Center => ((left + right - width) / 2.0, (top + bottom - height) / 2.0),
+    };
+    let x = x.clamp(left, (right - width).max(left)).round();
+    let y = y.clamp(top, (bottom - height).max(top)).round();
+    [x, y, x + width, y + height]
+}
+
+fn contains(rect: [f32; 4], x: f32, y: f32) -> bool {
+    x >= rect[0] && x < rect[2] && y >= rect[1] && y < rect[3]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout() -> Layout {
+        let root = Panel::new("root", Constraint::Fill(1.0))
+            .axis(Axis::Horizontal)
+            .child(Panel::new("side", Constraint::Length(100.0)).focusable())
+            .child(Panel::new("main", Constraint::Fill(1.0)).focusable())
+            .child(Panel::new("hidden", Constraint::Length(50.0)).focusable().hidden())
+            .child(Panel::new("status", Constraint::Length(20.0)));
+        let mut layout = Layout::new(root);
+        layout.compute([0.0, 0.0, 800.0, 600.0], 1.0);
+        layout
+    }
+
+    fn overlay(id: PanelId, modal: bool, takes_focus: bool) -> Overlay {
+        Overlay { id, anchor: Anchor::Center, size: [200.0, 100.0], z: 1, modal, takes_focus }
+    }
+
+    #[test]
+    fn split_shares_space_without_gaps() {
+        let constraints = [Constraint::Length(100.0), Constraint::Fill(1.0), Constraint::Fill(2.0)];
+        let parts = split([0.0, 0.0, 400.0, 10.0], Axis::Horizontal, &constraints);
+        assert_eq!(parts, [[0.0, 0.0, 100.0, 10.0], [100.0, 0.0, 200.0, 10.0], [200.0, 0.0, 400.0, 10.0]]);
+        // 固定长度超出时后面的块被挤到 0
+        let parts = split([0.0, 0.0, 10.0, 50.0], Axis::Vertical, &[Constraint::Length(40.0), Constraint::Length(20.0), Constraint::Fill(1.0)]);
+        assert_eq!(parts, [[0.0, 0.0, 10.0, 40.0], [0.0, 40.0, 10.0, 50.0], [0.0, 50.0, 10.0, 50.0]]);
+    }
+
+    #[test]
+    fn showing_and_hiding_panels() {
+        let mut layout = layout();
+        assert!(!layout.is_visible("hidden"));
+        assert_eq!(layout.rect("main"), Some([100.0, 0.0, 780.0, 600.0]));
+
+        layout.set_visible("hidden", true);
+        assert!(layout.is_visible("hidden"));
+        assert_eq!(layout.rect("main"), Some([100.0, 0.0, 730.0, 600.0]));
+        assert_eq!(layout.rect("hidden"), Some([730.0, 0.0, 780.0, 600.0]));
+
+        // 藏起拿着焦点的面板，焦点交给第一个还能拿焦点的
+        layout.focus_next(true);
+        assert_eq!(layout.focused(), Some("hidden"));
+        layout.set_visible("hidden", false);
+        assert_eq!(layout.focused(), Some("side"));
+        assert_eq!(layout.rect("hidden"), None);
+    }
+
+    #[test]
+    fn padding_shrinks_the_content() {
+        let root = Panel::new("root", Constraint::Fill(1.0))
+            .child(Panel::new("list", Constraint::Length(100.0)).padding(Insets::uniform(8.0)));
+        let mut layout = Layout::new(root);
+        layout.compute([0.0, 0.0, 300.0, 600.0], 1.5);
+        assert_eq!(layout.rect("list"), Some([0.0, 0.0, 300.0, 150.0]));
+        assert_eq!(layout.content("list"), Some([12.0, 12.0, 288.0, 138.0]));
+    }
+
+    #[test]
+    fn focus_cycles_through_visible_panels() {
+        let mut layout = layout();
+        assert_eq!(layout.focused(), Some("side"));
+        layout.focus_next(false);
+        assert_eq!(layout.focused(), Some("main"));
+        layout.focus_next(false);
+        assert_eq!(layout.focused(), Some("side"));
+        layout.focus_next(true);
+        assert_eq!(layout.focused(), Some("main"));
+    }
+
+    #[test]
+    fn focus_reaches_overlays_that_did_not_take_it() {
+        let mut layout = layout();
+        layout.focus_next(false);
+        layout.show_overlay(overlay("conflict", false, false));
+        assert_eq!(layout.focused(), Some("main"));
+        layout.focus_next(false);
+        assert_eq!(layout.focused(), Some("conflict"));
+        layout.focus_next(false);
+        assert_eq!(layout.focused(), Some("side"));
+        layout.focus_next(true);
+        assert_eq!(layout.focused(), Some("conflict"));
+        layout.hide_overlay("conflict");
+        assert_eq!(layout.focused(), Some("side"));
+    }
+
+    #[test]
+    fn modal_overlay_keeps_focus() {
+        let mut layout = layout();
+        layout.show_overlay(overlay("dialog", true, true));
+        assert_eq!(layout.focused(), Some("dialog"));
+        layout.focus_next(false);
+        assert_eq!(layout.focused(), Some("dialog"));
+        layout.hide_overlay("dialog");
+        assert_eq!(layout.focused(), Some("side"));
+    }
+}
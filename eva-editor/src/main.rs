@@ -1,20 +1,52 @@
-use std::path::PathBuf;
-use glfw::{Action, Context, Key, WindowEvent};
-use log::{info, error, debug};
-use anyhow::Result;
-use std::time::Instant;
+use glfw::{Action, Context, Key, Modifiers, MouseButton, WindowEvent};
+use log::{info, error};
+use anyhow::{bail, Result};
 use std::sync::mpsc::Receiver;
 
+use crate::autosave::AutoSaver;
+use crate::cli::Command;
 use crate::editor::Editor;
+use crate::markers::MarkerKind;
+use crate::prompt::{AfterSave, PromptAction, Reply};
+use crate::recovery::Journal;
 use crate::renderer::Renderer;
-use crate::theme::Theme;
+use crate::theme::{Theme, THEME_PRESETS};
+use crate::ui::{EDITOR, PROMPT, SIDEBAR, STATUS, TABS};
+use crate::unsaved::Saved;
+use crate::view::{Direction, SplitDirection};
+use crate::watcher::FileWatcher;
 
+mod atlas;
+mod autosave;
+mod cli;
+mod config;
+mod diff;
 mod editor;
+mod encoding;
 mod font;
+mod fontconfig;
+mod gutter;
+mod highlight;
+mod layout;
+mod markers;
+mod minimap;
+mod primitives;
+mod prompt;
+mod recovery;
 mod renderer;
+mod save;
+mod scrollbar;
+mod sdf;
 mod shader;
+mod shaping;
+mod sidebar;
+mod status_bar;
+mod tabs;
 mod theme;
 mod ui;
+mod unsaved;
+mod view;
+mod watcher;
 
 // OpenGL bindings
 pub use gl::types::*;
@@ -27,13 +59,24 @@ fn init_gl() -> Result<(glfw::Glfw, glfw::Window, Receiver<(f64, WindowEvent)>)>
     let mut glfw = glfw::init(glfw::FAIL_ON_ERRORS)?;
 
     glfw.window_hint(glfw::WindowHint::ContextVersion(2, 1));
+    // 窗口按显示器缩放放大，macOS 上使用 Retina 分辨率的帧缓冲
+    glfw.window_hint(glfw::WindowHint::ScaleToMonitor(true));
+    glfw.window_hint(glfw::WindowHint::CocoaRetinaFramebuffer(true));
 
-    let (mut window, events) = glfw.create_window(800, 600, "Eva Editor", glfw::WindowMode::Windowed)
+    let (mut window, events) = glfw.create_window(WINDOW_WIDTH, WINDOW_HEIGHT, "Eva Editor", glfw::WindowMode::Windowed)
         .ok_or_else(|| anyhow::anyhow!("Failed to create GLFW window."))?;
 
     window.make_current();
     window.set_key_polling(true);
+    window.set_char_polling(true);
+    window.set_focus_polling(true);
     window.set_framebuffer_size_polling(true);
+    window.set_content_scale_polling(true);
+    window.set_mouse_button_polling(true);
+    window.set_cursor_pos_polling(true);
+    window.set_scroll_polling(true);
+    // 点窗口的关闭按钮时先查未保存的修改，不直接关
+    window.set_close_polling(true);
 
     gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
 
@@ -46,22 +89,488 @@ fn init_gl() -> Result<(glfw::Glfw, glfw::Window, Receiver<(f64, WindowEvent)>)>
     Ok((glfw, window, events))
 }
 
+fn arrow_direction(key: Key) -> Option<Direction> {
+    match key {
+        Key::Left => Some(Direction::Left),
+        Key::Right => Some(Direction::Right),
+        Key::Up => Some(Direction::Up),
+        Key::Down => Some(Direction::Down),
+        _ => None,
+    }
+}
+
+// 鼠标事件给的是窗口坐标，Retina 屏上要换算成帧缓冲像素
+fn framebuffer_pos(window: &glfw::Window, x: f64, y: f64) -> (f32, f32) {
+    let (width, _) = window.get_size();
+    let (fb_width, _) = window.get_framebuffer_size();
+    let ratio = fb_width as f64 / width.max(1) as f64;
+    ((x * ratio) as f32, (y * ratio) as f32)
+}
+
+fn show_conflict(renderer: &mut Renderer, editor: &Editor, id: u64) {
+    if let Some(index) = editor.index_of(id) {
+        renderer.show_prompt(watcher::conflict_prompt(&editor.buffers[index], AfterSave::Nothing));
+    }
+}
+
+// 没有未保存的修改时返回 true，可以直接退出；有的话弹出提示
+fn request_quit(renderer: &mut Renderer, editor: &Editor) -> bool {
+    match unsaved::quit_prompt(editor) {
+        Some(prompt) => {
+            renderer.show_prompt(prompt);
+            false
+        }
+        None => true,
+    }
+}
+
+// 关闭缓冲区，有未保存的修改时先问
+fn request_close(renderer: &mut Renderer, editor: &mut Editor, index: usize) {
+    match editor.buffers.get(index) {
+        Some(buffer) if buffer.modified => renderer.show_prompt(unsaved::close_prompt(buffer)),
+        Some(_) => editor.close_buffer(index),
+        None => {}
+    }
+}
+
+fn close_by_id(editor: &mut Editor, id: u64) {
+    if let Some(index) = editor.index_of(id) {
+        editor.close_buffer(index);
+    }
+}
+
+// 退出时逐个保存，全部存好就返回 true
+fn continue_save_all(renderer: &mut Renderer, editor: &mut Editor) -> bool {
+    match unsaved::save_all(editor) {
+        Saved::Done => true,
+        Saved::Ask(prompt) => {
+            renderer.show_prompt(prompt);
+            false
+        }
+    }
+}
+
+// 保存一个缓冲区，存好后接着做 then；要问用户时弹出提示停下。返回是否该退出了
+fn save_then(renderer: &mut Renderer, editor: &mut Editor, id: u64, then: AfterSave) -> bool {
+    let Some(index) = editor.index_of(id) else {
+        return false;
+    };
+    match unsaved::save(editor, index, then) {
+        Saved::Done => after_save(renderer, editor, id, then),
+        Saved::Ask(prompt) => {
+            renderer.show_prompt(prompt);
+            false
+        }
+    }
+}
+
+// 保存（或者丢掉修改）之后接着关闭缓冲区或存剩下的再退出
+fn after_save(renderer: &mut Renderer, editor: &mut Editor, id: u64, then: AfterSave) -> bool {
+    match then {
+        AfterSave::Nothing => false,
+        AfterSave::Close => {
+            close_by_id(editor, id);
+            false
+        }
+        AfterSave::Quit => continue_save_all(renderer, editor),
+    }
+}
+
+// 执行提示里选中的选项，返回是否该退出了
+fn run_prompt_action(renderer: &mut Renderer, editor: &mut Editor, journal: &mut Journal, reply: Reply) -> bool {
+    match reply.action {
+        PromptAction::KeepMine(id, then) => {
+            watcher::keep_mine(editor, id);
+            if then != AfterSave::Nothing {
+                return save_then(renderer, editor, id, then);
+            }
+        }
+        PromptAction::TakeTheirs(id, then) => {
+            // 读不回来时缓冲区还留着修改，再问一次，不能接着关掉它
+            if let Err(e) = watcher::take_theirs(editor, id) {
+                error!("Failed to reload: {:#}", e);
+                if let Some(index) = editor.index_of(id) {
+                    let mut prompt = watcher::conflict_prompt(&editor.buffers[index], then).focused();
+                    prompt.message = format!("Could not read the file on disk: {:#}", e);
+                    renderer.show_prompt(prompt);
+                }
+                return false;
+            }
+            return after_save(renderer, editor, id, then);
+        }
+        PromptAction::ShowDiff(id) => {
+            if let Err(e) = watcher::show_diff(editor, id) {
+                error!("Failed to show the diff: {:#}", e);
+            }
+        }
+        PromptAction::Recover(id) => journal.recover(editor, id),
+        PromptAction::ShowRecoveryDiff(id) => journal.show_diff(editor, id),
+        PromptAction::DiscardRecovery(id) => journal.discard(id),
+        PromptAction::RecoverLater(_) => {}
+        PromptAction::SaveAll => return continue_save_all(renderer, editor),
+        PromptAction::DiscardAll => {
+            unsaved::discard_all(editor);
+            return true;
+        }
+        PromptAction::SaveAndClose(id) => return save_then(renderer, editor, id, AfterSave::Close),
+        PromptAction::DiscardAndClose(id) => close_by_id(editor, id),
+        PromptAction::SaveAs(id, then) => {
+            let input = reply.input.unwrap_or_default();
+            if let Err(e) = unsaved::save_as(editor, id, &input) {
+                error!("Failed to save: {:#}", e);
+                renderer.show_prompt(unsaved::retry_save_as(id, then, &e, input));
+                return false;
+            }
+            return after_save(renderer, editor, id, then);
+        }
+        PromptAction::RetrySave(id, then) => return save_then(renderer, editor, id, then),
+        PromptAction::SaveElsewhere(id, then) => renderer.show_prompt(unsaved::save_as_prompt(editor, id, then)),
+        PromptAction::DiscardChanges(id, then) => {
+            unsaved::discard(editor, id);
+            return after_save(renderer, editor, id, then);
+        }
+        PromptAction::SetLineEnding(id, line_ending) => {
+            if let Some(index) = editor.index_of(id) {
+                if let Err(e) = editor.set_line_ending(index, line_ending) {
+                    error!("Failed to convert line endings: {:#}", e);
+                }
+            }
+        }
+        PromptAction::SetEncoding(id) => {
+            let label = reply.input.unwrap_or_default();
+            let result = match (editor.index_of(id), encoding::TextEncoding::from_label(&label)) {
+                (Some(index), Ok(encoding)) => editor.set_encoding(index, encoding),
+                (Some(_), Err(e)) => Err(e),
+                (None, _) => Ok(()),
+            };
+            if let Err(e) = result {
+                error!("Failed to convert the encoding: {:#}", e);
+            }
+        }
+        PromptAction::Cancel => {}
+    }
+    false
+}
+
 fn main() -> Result<()> {
     env_logger::init();
 
-    let (mut glfw, mut window, events) = init_gl()?;
+    let args = match cli::parse(std::env::args_os().skip(1)) {
+        Ok(Command::Run(args)) => args,
+        Ok(Command::Help) => {
+            print!("{}", cli::USAGE);
+            return Ok(());
+        }
+        Ok(Command::Version) => {
+            println!("eva-editor {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("eva-editor: {:#}\n\nRun 'eva-editor --help' for usage.", e);
+            std::process::exit(2);
+        }
+    };
 
+    // 默认值，然后是配置文件，最后是命令行
+    let mut theme = Theme::default();
     let mut editor = Editor::new();
-    let mut renderer = Renderer::new(800, 600)?;
+    config::load(args.config.as_deref(), &mut theme, &mut editor)?;
+    config::apply_overrides(&args.settings, &mut theme, &mut editor)?;
+    if let Some(name) = &args.theme {
+        if !theme.apply_preset(name) {
+            bail!("Unknown theme '{}', expected one of {}", name, THEME_PRESETS.join(", "));
+        }
+    }
+
+    // 标准输入要在建窗口之前读完
+    cli::open(&mut editor, &args);
+
+    let (mut glfw, mut window, events) = init_gl()?;
+    if let Some(root) = &editor.workspace_root {
+        window.set_title(&format!("Eva Editor — {}", root.display()));
+    }
+    // 渲染用帧缓冲的物理像素尺寸，HiDPI 屏上它和窗口尺寸不同
+    let (fb_width, fb_height) = window.get_framebuffer_size();
+    let (content_scale, _) = window.get_content_scale();
+    info!(
+        "Window {:?}, framebuffer {}x{}, content scale {}",
+        window.get_size(),
+        fb_width,
+        fb_height,
+        content_scale
+    );
+    unsafe {
+        gl::Viewport(0, 0, fb_width, fb_height);
+    }
+    let mut renderer = Renderer::new(theme, fb_width as u32, fb_height as u32, content_scale)?;
+    let mut watcher = FileWatcher::new();
+    let mut autosaver = AutoSaver::default();
+    // 上次没正常退出留下的修改，逐个问要不要恢复
+    let mut journal = Journal::open();
+    for prompt in journal.prompts() {
+        renderer.show_prompt(prompt);
+    }
 
-    let start_time = Instant::now();
+    // 左键按住时拖动扩展选区
+    let mut selecting = false;
+    // 正在拖动的标签页
+    let mut dragging_tab: Option<usize> = None;
 
     while !window.should_close() {
         glfw.poll_events();
         for (_, event) in glfw::flush_messages(&events) {
             match event {
+                // Esc 先回答或关掉弹出层，没有弹出层时退出
                 glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
-                    window.set_should_close(true)
+                    if let Some(reply) = renderer.cancel_prompt() {
+                        if run_prompt_action(&mut renderer, &mut editor, &mut journal, reply) {
+                            window.set_should_close(true);
+                        }
+                    } else if !renderer.dismiss_overlay() && request_quit(&mut renderer, &editor) {
+                        window.set_should_close(true);
+                    }
+                }
+                // F6 / Shift+F6 把键盘焦点移到下一个 / 上一个面板或提示，不用鼠标也能回答不抢焦点的提示
+                glfw::WindowEvent::Key(Key::F6, _, Action::Press | Action::Repeat, mods) => {
+                    renderer.focus_next(mods.contains(Modifiers::Shift));
+                    renderer.notify_input();
+                }
+                // 关闭按钮：GLFW 已经把 should_close 置上了，先撤回，没有未保存的修改再关
+                glfw::WindowEvent::Close => {
+                    window.set_should_close(request_quit(&mut renderer, &editor));
+                }
+                // Ctrl（macOS 上 Cmd）+ = / - / 0 缩放字号
+                glfw::WindowEvent::Key(key, _, Action::Press | Action::Repeat, mods)
+                    if mods.intersects(Modifiers::Control | Modifiers::Super) =>
+                {
+                    // Ctrl+Tab / Ctrl+Shift+Tab 按最近使用顺序切换缓冲区
+                    if key == Key::Tab {
+                        editor.cycle_mru(mods.contains(Modifiers::Shift));
+                        renderer.notify_input();
+                        continue;
+                    }
+                    // 窗格：Ctrl+Alt+方向键移动焦点，再加 Shift 和相邻窗格交换，Ctrl+Shift+方向键移动分隔条
+                    if let Some(direction) = arrow_direction(key) {
+                        match (mods.contains(Modifiers::Alt), mods.contains(Modifiers::Shift)) {
+                            (true, false) => editor.focus_direction(direction),
+                            (true, true) => editor.swap_view(direction),
+                            (false, true) => editor.resize_view(direction),
+                            (false, false) => {}
+                        }
+                        renderer.notify_input();
+                        continue;
+                    }
+                    // Ctrl+\ 左右分割，Ctrl+Shift+\ 上下分割，Ctrl+Shift+W 关闭窗格
+                    match key {
+                        Key::Backslash if mods.contains(Modifiers::Shift) => editor.split_view(SplitDirection::Vertical),
+                        Key::Backslash => editor.split_view(SplitDirection::Horizontal),
+                        Key::W if mods.contains(Modifiers::Shift) => editor.close_view(),
+                        // Ctrl+B 显示或藏起列出打开的缓冲区的侧栏
+                        Key::B => renderer.toggle_sidebar(),
+                        // Ctrl+Shift+L 转换换行符，Ctrl+Shift+E 转换编码
+                        Key::L if mods.contains(Modifiers::Shift) => {
+                            if let Some(buffer) = editor.get_active_buffer() {
+                                renderer.show_prompt(encoding::line_ending_prompt(buffer));
+                            }
+                        }
+                        Key::E if mods.contains(Modifiers::Shift) => {
+                            if let Some(buffer) = editor.get_active_buffer() {
+                                renderer.show_prompt(encoding::encoding_prompt(buffer));
+                            }
+                        }
+                        _ => {}
+                    }
+                    // Ctrl+S 保存；文件在外面被改过时先问要留哪一边，未命名的问存到哪里
+                    if key == Key::S {
+                        let id = editor.buffers[editor.active_buffer].id;
+                        save_then(&mut renderer, &mut editor, id, AfterSave::Nothing);
+                        continue;
+                    }
+                    let result = match key {
+                        Key::Equal | Key::KpAdd => renderer.zoom_in(&mut editor),
+                        Key::Minus | Key::KpSubtract => renderer.zoom_out(&mut editor),
+                        Key::Num0 | Key::Kp0 => renderer.reset_zoom(&mut editor),
+                        _ => Ok(()),
+                    };
+                    if let Err(e) = result {
+                        error!("Failed to change font size: {}", e);
+                    }
+                }
+                // 单击放置光标，Shift+单击扩展选区
+                glfw::WindowEvent::MouseButton(MouseButton::Button1, Action::Press, mods) => {
+                    let (cursor_x, cursor_y) = window.get_cursor_pos();
+                    let (x, y) = framebuffer_pos(&window, cursor_x, cursor_y);
+                    match renderer.focus_at(x, y) {
+                        // 点标签页切换缓冲区，按住拖动重排
+                        Some(TABS) => {
+                            if let Some(index) = renderer.tab_at(&editor, x, y) {
+                                editor.switch_to(index);
+                                dragging_tab = Some(index);
+                            }
+                        }
+                        Some(EDITOR) if !renderer.begin_divider_drag(&editor, x, y) => {
+                            // 点到哪个窗格先把焦点给它；滚动条和缩略图按住可以拖动
+                            renderer.focus_pane_at(&mut editor, x, y);
+                            if renderer.begin_scroll_drag(&mut editor, x, y) {
+                                renderer.notify_input();
+                            // 点行号栏左侧的标记道切换断点
+                            } else if let Some(line) = renderer.gutter_marker_hit(&editor, x, y) {
+                                if let Some(buffer) = editor.get_active_buffer_mut() {
+                                    buffer.markers.toggle(line, MarkerKind::Breakpoint);
+                                }
+                            } else if let Some(position) = renderer.hit_test(&editor, x, y) {
+                                editor.set_cursor(position, mods.contains(Modifiers::Shift));
+                                renderer.notify_input();
+                                selecting = true;
+                            }
+                        }
+                        // 点侧栏里的一行切换到那个缓冲区
+                        Some(SIDEBAR) => {
+                            if let Some(index) = renderer.sidebar_row_at(&editor, y) {
+                                editor.switch_to(index);
+                            }
+                        }
+                        // 点状态栏上的换行符和编码转换当前缓冲区，下次保存时写进文件
+                        Some(STATUS) => match renderer.status_segment_at(&editor, x).as_deref() {
+                            Some("line_ending") => {
+                                if let Err(e) = editor.toggle_line_ending() {
+                                    error!("Failed to convert line endings: {:#}", e);
+                                }
+                            }
+                            Some("encoding") => {
+                                if let Err(e) = editor.cycle_encoding() {
+                                    error!("Failed to convert the encoding: {:#}", e);
+                                }
+                            }
+                            _ => {}
+                        },
+                        Some(PROMPT) => {
+                            if let Some(reply) = renderer.prompt_choice_at(x, y) {
+                                if run_prompt_action(&mut renderer, &mut editor, &mut journal, reply) {
+                                    window.set_should_close(true);
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                glfw::WindowEvent::MouseButton(MouseButton::Button1, Action::Release, _) => {
+                    selecting = false;
+                    dragging_tab = None;
+                    renderer.end_drag();
+                }
+                // 中键关闭标签页，右键固定或取消固定
+                glfw::WindowEvent::MouseButton(button @ (MouseButton::Button2 | MouseButton::Button3), Action::Press, _) => {
+                    let (cursor_x, cursor_y) = window.get_cursor_pos();
+                    let (x, y) = framebuffer_pos(&window, cursor_x, cursor_y);
+                    if let Some(index) = renderer.tab_at(&editor, x, y) {
+                        if button == MouseButton::Button3 {
+                            request_close(&mut renderer, &mut editor, index);
+                        } else {
+                            editor.toggle_pin(index);
+                        }
+                    }
+                }
+                glfw::WindowEvent::CursorPos(cursor_x, _) if dragging_tab.is_some() => {
+                    let (x, _) = framebuffer_pos(&window, cursor_x, 0.0);
+                    if let (Some(from), Some(to)) = (dragging_tab, renderer.tab_drop_target(&editor, x)) {
+                        dragging_tab = Some(editor.move_buffer(from, to));
+                    }
+                }
+                glfw::WindowEvent::CursorPos(cursor_x, cursor_y) if renderer.is_dragging() => {
+                    let (x, y) = framebuffer_pos(&window, cursor_x, cursor_y);
+                    renderer.drag_to(&mut editor, x, y);
+                }
+                glfw::WindowEvent::Scroll(dx, dy) => {
+                    let (cursor_x, cursor_y) = window.get_cursor_pos();
+                    let (x, y) = framebuffer_pos(&window, cursor_x, cursor_y);
+                    renderer.scroll(&mut editor, x, y, dx as f32, dy as f32);
+                }
+                glfw::WindowEvent::CursorPos(cursor_x, cursor_y) if selecting => {
+                    let (x, y) = framebuffer_pos(&window, cursor_x, cursor_y);
+                    if let Some(position) = renderer.hit_test(&editor, x, y) {
+                        editor.set_cursor(position, true);
+                        renderer.notify_input();
+                    }
+                }
+                // 编辑和光标移动，Shift 扩展选区；只在窗格拿着键盘焦点时生效
+                glfw::WindowEvent::Key(key, _, Action::Press | Action::Repeat, mods)
+                    if renderer.focused_panel() == Some(EDITOR) =>
+                {
+                    let extend = mods.contains(Modifiers::Shift);
+                    match key {
+                        Key::Backspace => editor.backspace(),
+                        Key::Delete => editor.delete_forward(),
+                        Key::Enter | Key::KpEnter => editor.type_text("\n"),
+                        Key::Tab => editor.indent(),
+                        Key::Insert => editor.overwrite = !editor.overwrite,
+                        Key::Left => editor.move_left(extend),
+                        Key::Right => editor.move_right(extend),
+                        Key::Up => editor.move_vertical(false, extend),
+                        Key::Down => editor.move_vertical(true, extend),
+                        Key::Home => editor.move_line_start(extend),
+                        Key::End => editor.move_line_end(extend),
+                        // F2 在光标行切换书签
+                        Key::F2 => {
+                            let line = editor.cursor.line;
+                            if let Some(buffer) = editor.get_active_buffer_mut() {
+                                buffer.markers.toggle(line, MarkerKind::Bookmark);
+                            }
+                        }
+                        _ => continue,
+                    }
+                    renderer.notify_input();
+                }
+                // 侧栏拿着焦点时上下键在缓冲区之间移动
+                glfw::WindowEvent::Key(key @ (Key::Up | Key::Down), _, Action::Press | Action::Repeat, _)
+                    if renderer.focused_panel() == Some(SIDEBAR) =>
+                {
+                    let count = editor.buffers.len();
+                    let index = match key {
+                        Key::Up => (editor.active_buffer + count - 1) % count,
+                        _ => (editor.active_buffer + 1) % count,
+                    };
+                    editor.switch_to(index);
+                }
+                // 松开 Ctrl 结束 Ctrl+Tab 轮换
+                glfw::WindowEvent::Key(Key::LeftControl | Key::RightControl, _, Action::Release, _) => {
+                    editor.finish_mru_cycle();
+                }
+                glfw::WindowEvent::Char(c) if renderer.focused_panel() == Some(EDITOR) => {
+                    editor.type_text(c.encode_utf8(&mut [0; 4]));
+                    renderer.notify_input();
+                }
+                // 提示拿着焦点时按选项的字母选择，有输入框时打进输入框
+                glfw::WindowEvent::Char(c) if renderer.focused_panel() == Some(PROMPT) => {
+                    if let Some(reply) = renderer.answer_prompt(c) {
+                        if run_prompt_action(&mut renderer, &mut editor, &mut journal, reply) {
+                            window.set_should_close(true);
+                        }
+                    }
+                }
+                // 输入框的退格，回车选第一个选项
+                glfw::WindowEvent::Key(key, _, Action::Press | Action::Repeat, _)
+                    if renderer.focused_panel() == Some(PROMPT) =>
+                {
+                    let reply = match key {
+                        Key::Backspace => {
+                            renderer.prompt_backspace();
+                            None
+                        }
+                        Key::Enter | Key::KpEnter => renderer.submit_prompt(),
+                        _ => None,
+                    };
+                    if let Some(reply) = reply {
+                        if run_prompt_action(&mut renderer, &mut editor, &mut journal, reply) {
+                            window.set_should_close(true);
+                        }
+                    }
+                }
+                glfw::WindowEvent::Focus(focused) => {
+                    renderer.set_focused(focused);
+                    if !focused {
+                        autosaver.focus_lost(&mut editor);
+                    }
                 }
                 glfw::WindowEvent::FramebufferSize(width, height) => {
                     unsafe {
@@ -69,10 +578,26 @@ fn main() -> Result<()> {
                     }
                     renderer.resize(width as u32, height as u32);
                 }
+                glfw::WindowEvent::ContentScale(scale, _) => {
+                    if let Err(e) = renderer.set_content_scale(scale) {
+                        error!("Failed to rescale fonts: {}", e);
+                    }
+                }
                 _ => {}
             }
         }
 
+        // 外部修改了打开的文件：没改过的缓冲区已经重新读入，改过的问用户
+        for id in watcher.poll(&mut editor) {
+            show_conflict(&mut renderer, &editor, id);
+        }
+        autosaver.tick(&mut editor);
+        journal.update(&editor);
+        // 行号栏的改动标记，只有内容变过的缓冲区才重新比较
+        for buffer in &mut editor.buffers {
+            buffer.update_changes();
+        }
+
         unsafe {
             gl::Clear(gl::COLOR_BUFFER_BIT);
         }
@@ -82,5 +607,7 @@ fn main() -> Result<()> {
         window.swap_buffers();
     }
 
+    // 没保存的修改留在恢复目录里，下次启动时再问
+    journal.finish(&editor);
     Ok(())
 }
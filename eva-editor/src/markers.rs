@@ -0,0 +1,202 @@
+// 行标记：断点、书签和相对上次保存的改动，按行存放，由行号栏分道绘制
+
+// 行号栏里的标记道，从左到右排列
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum MarkerLane {
+    Breakpoint,
+    Bookmark,
+    Vcs,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum MarkerKind {
+    Breakpoint,
+    Bookmark,
+    Added,
+    Modified,
+    Deleted,
+}
+
+impl MarkerKind {
+    pub fn lane(self) -> MarkerLane {
+        match self {
+            MarkerKind::Breakpoint => MarkerLane::Breakpoint,
+            MarkerKind::Bookmark => MarkerLane::Bookmark,
+            MarkerKind::Added | MarkerKind::Modified | MarkerKind::Deleted => MarkerLane::Vcs,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Marker {
+    pub line: usize,
+    pub kind: MarkerKind,
+}
+
+// 一个缓冲区的全部标记，按 (行, 种类) 排序
+#[derive(Default)]
+pub struct Markers {
+    markers: Vec<Marker>,
+}
+
+impl Markers {
+    // 断点、书签这类开关式标记
+    pub fn toggle(&mut self, line: usize, kind: MarkerKind) {
+        match self.markers.binary_search_by_key(&(line, kind), |m| (m.line, m.kind)) {
+            Ok(index) => {
+                self.markers.remove(index);
+            }
+            Err(index) => self.markers.insert(index, Marker { line, kind }),
+        }
+    }
+
+    // 整道换成新算出来的标记，比如重新比较过的改动
+    pub fn replace_lane(&mut self, lane: MarkerLane, markers: impl IntoIterator<Item = Marker>) {
+        self.markers.retain(|m| m.kind.lane() != lane);
+        self.markers.extend(markers.into_iter().filter(|m| m.kind.lane() == lane));
+        self.markers.sort_by_key(|m| (m.line, m.kind));
+        self.markers.dedup();
+    }
+
+    pub fn on_line(&self, line: usize) -> impl Iterator<Item = MarkerKind> + '_ {
+        let start = self.markers.partition_point(|m| m.line < line);
+        self.markers[start..].iter().take_while(move |m| m.line == line).map(|m| m.kind)
+    }
+
+    // 行号栏上这一行要画的标记：同一道只留最靠前的一个，有断点时不画书签
+    pub fn shown_on_line(&self, line: usize) -> Vec<MarkerKind> {
+        let mut shown: Vec<MarkerKind> = Vec::new();
+        for kind in self.on_line(line) {
+            let lane = kind.lane();
+            let taken = |lane: MarkerLane| shown.iter().any(|k| k.lane() == lane);
+            if taken(lane) || (lane == MarkerLane::Bookmark && taken(MarkerLane::Breakpoint)) {
+                continue;
+            }
+            shown.push(kind);
+        }
+        shown
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Marker> + '_ {
+        self.markers.iter().copied()
+    }
+
+    // 在 line 之后插入了 count 行，后面的标记跟着下移
+    pub fn insert_lines(&mut self, line: usize, count: usize) {
+        if count == 0 {
+            return;
+        }
+        for marker in &mut self.markers {
+            if marker.line > line {
+                marker.line += count;
+            }
+        }
+    }
+
+    // 从 first 起的 count 行被删掉，其上的标记删除，后面的上移
+    pub fn remove_lines(&mut self, first: usize, count: usize) {
+        if count == 0 {
+            return;
+        }
+        self.markers.retain(|m| m.line < first || m.line >= first + count);
+        for marker in &mut self.markers {
+            if marker.line >= first + count {
+                marker.line -= count;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markers(list: &[(usize, MarkerKind)]) -> Markers {
+        let mut markers = Markers::default();
+        for &(line, kind) in list {
+            markers.toggle(line, kind);
+        }
+        markers
+    }
+
+    fn lines(markers: &Markers) -> Vec<(usize, MarkerKind)> {
+        markers.iter().map(|m| (m.line, m.kind)).collect()
+    }
+
+    #[test]
+    fn toggle_adds_and_removes() {
+        let mut markers = markers(&[(3, MarkerKind::Bookmark), (1, MarkerKind::Breakpoint), (3, MarkerKind::Breakpoint)]);
+        assert_eq!(lines(&markers), [(1, MarkerKind::Breakpoint), (3, MarkerKind::Breakpoint), (3, MarkerKind::Bookmark)]);
+        markers.toggle(3, MarkerKind::Breakpoint);
+        assert_eq!(lines(&markers), [(1, MarkerKind::Breakpoint), (3, MarkerKind::Bookmark)]);
+    }
+
+    #[test]
+    fn inserted_lines_push_markers_down() {
+        let mut markers = markers(&[(2, MarkerKind::Bookmark), (5, MarkerKind::Breakpoint)]);
+        // 在第 2 行里换行，标记留在原来那一行
+        markers.insert_lines(2, 3);
+        assert_eq!(lines(&markers), [(2, MarkerKind::Bookmark), (8, MarkerKind::Breakpoint)]);
+        markers.insert_lines(0, 0);
+        assert_eq!(lines(&markers), [(2, MarkerKind::Bookmark), (8, MarkerKind::Breakpoint)]);
+    }
+
+    #[test]
+    fn deleted_lines_take_their_markers() {
+        let mut markers = markers(&[
+            (1, MarkerKind::Bookmark),
+            (2, MarkerKind::Breakpoint),
+            (3, MarkerKind::Modified),
+            (4, MarkerKind::Bookmark),
+            (6, MarkerKind::Added),
+        ]);
+        // 第 2、3 行被删掉：上面的标记没了，后面的上移两行
+        markers.remove_lines(2, 2);
+        assert_eq!(lines(&markers), [(1, MarkerKind::Bookmark), (2, MarkerKind::Bookmark), (4, MarkerKind::Added)]);
+    }
+
+    #[test]
+    fn buffer_edits_move_markers() {
+        let mut buffer = crate::editor::Buffer::new();
+        buffer.insert(0, "a\nb\nc\nd\n");
+        buffer.markers.toggle(1, MarkerKind::Bookmark);
+        buffer.markers.toggle(3, MarkerKind::Breakpoint);
+        buffer.insert(0, "x\ny\n");
+        assert_eq!(lines(&buffer.markers), [(3, MarkerKind::Bookmark), (5, MarkerKind::Breakpoint)]);
+        // 删掉整行 "b\nc\n"：b 行上的书签跟着删掉，d 行上移
+        let start = buffer.content.find('b').unwrap();
+        buffer.delete(start, start + 4);
+        assert_eq!(lines(&buffer.markers), [(3, MarkerKind::Breakpoint)]);
+        // 从行中间删到下一行：下一行并入这一行，它的标记删掉
+        buffer.insert(0, "p\n");
+        buffer.markers.toggle(0, MarkerKind::Bookmark);
+        buffer.markers.toggle(1, MarkerKind::Bookmark);
+        buffer.delete(1, 2);
+        assert_eq!(lines(&buffer.markers), [(0, MarkerKind::Bookmark), (3, MarkerKind::Breakpoint)]);
+    }
+
+    #[test]
+    fn one_marker_per_lane() {
+        let markers = markers(&[
+            (0, MarkerKind::Modified),
+            (0, MarkerKind::Added),
+            (0, MarkerKind::Bookmark),
+            (1, MarkerKind::Breakpoint),
+            (1, MarkerKind::Bookmark),
+            (1, MarkerKind::Deleted),
+        ]);
+        assert_eq!(markers.shown_on_line(0), [MarkerKind::Bookmark, MarkerKind::Added]);
+        assert_eq!(markers.shown_on_line(1), [MarkerKind::Breakpoint, MarkerKind::Deleted]);
+        assert!(markers.shown_on_line(2).is_empty());
+    }
+
+    #[test]
+    fn replacing_a_lane_keeps_the_others() {
+        let mut markers = markers(&[(0, MarkerKind::Added), (2, MarkerKind::Bookmark), (4, MarkerKind::Modified)]);
+        let changes = [Marker { line: 1, kind: MarkerKind::Deleted }, Marker { line: 2, kind: MarkerKind::Added }];
+        markers.replace_lane(MarkerLane::Vcs, changes);
+        assert_eq!(lines(&markers), [(1, MarkerKind::Deleted), (2, MarkerKind::Bookmark), (2, MarkerKind::Added)]);
+        markers.replace_lane(MarkerLane::Vcs, []);
+        assert_eq!(lines(&markers), [(2, MarkerKind::Bookmark)]);
+    }
+}
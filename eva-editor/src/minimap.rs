@@ -0,0 +1,354 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use anyhow::Result;
+use crate::editor::Buffer;
+use crate::font::{ortho_projection, TextSpan};
+use crate::highlight::Highlighter;
+use crate::markers::MarkerKind;
+use crate::primitives::{PrimitiveRenderer, RectStyle};
+use crate::shader::Shader;
+use crate::theme::Theme;
+use crate::view::View;
+
+const MINIMAP_VERTEX_SHADER: &str = r#"
+#version 120
+attribute vec2 a_position;
+attribute vec2 a_uv;
+uniform mat4 u_projection;
+varying vec2 v_uv;
+
+void main() {
+    v_uv = a_uv;
+    gl_Position = u_projection * vec4(a_position, 0.0, 1.0);
+}
+"#;
+
+const MINIMAP_FRAGMENT_SHADER: &str = r#"
+#version 120
+uniform sampler2D u_texture;
+varying vec2 v_uv;
+
+void main() {
+    gl_FragColor = texture2D(u_texture, v_uv);
+}
+"#;
+
+const ATTRIB_POSITION: u32 = 0;
+const ATTRIB_UV: u32 = 1;
+
+// 纹理里每行一个像素高、每个字符一个像素宽
+const COLUMNS: usize = 128;
+// 再长的文件只画前这么多行，纹理高度有上限
+const MAX_ROWS: usize = 8192;
+// 每帧最多核对这么多行，大文件分几帧画完
+const LINES_PER_FRAME: usize = 2000;
+const TAB_WIDTH: usize = 4;
+// 屏幕上每行、每个字符的大小（逻辑像素）
+const LINE_HEIGHT: f32 = 2.0;
+const CHAR_WIDTH: f32 = 1.0;
+
+// 缩略图：整个缓冲区按语法颜色缩小画进一张纹理，内容变化时只重传变了的行
+pub struct Minimap {
+    shader: Shader,
+    vbo: u32,
+    texture: u32,
+    // 纹理的行数
+    capacity: usize,
+    // 每行已上传内容的哈希
+    rows: Vec<u64>,
+    buffer_id: u64,
+    version: u64,
+    // 下一帧从这一行继续核对，None 表示纹理已是最新
+    next_row: Option<usize>,
+    highlighter: Highlighter,
+    projection: [f32; 16],
+}
+
+impl Minimap {
+    pub fn new(theme: &Theme) -> Result<Self> {
+        let shader = Shader::with_attributes(
+            MINIMAP_VERTEX_SHADER,
+            MINIMAP_FRAGMENT_SHADER,
+            &[(ATTRIB_POSITION, "a_position"), (ATTRIB_UV, "a_uv")],
+        )?;
+        let (mut vbo, mut texture) = (0, 0);
+        unsafe {
+            gl::GenBuffers(1, &mut vbo);
+            gl::GenTextures(1, &mut texture);
+        }
+
+        Ok(Minimap {
+            shader,
+            vbo,
+            texture,
+            capacity: 0,
+            rows: Vec::new(),
+            buffer_id: 0,
+            version: 0,
+            next_row: None,
+            highlighter: Highlighter::new(theme),
+            projection: ortho_projection(1.0, 1.0),
+        })
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.projection = ortho_projection(width.max(1) as f32, height.max(1) as f32);
+    }
+
+    // 把还没核对过的行画进纹理
+    pub fn update(&mut self, buffer: &Buffer, foreground: [f32; 4]) {
+        if buffer.id != self.buffer_id {
+            self.buffer_id = buffer.id;
+            self.rows.clear();
+            self.version = 0;
+        }
+        if buffer.version != self.version {
+            self.version = buffer.version;
+            self.next_row = Some(0);
+        }
+        let Some(first) = self.next_row else {
+            return;
+        };
+
+        let line_count = (buffer.content.matches('\n').count() + 1).min(MAX_ROWS);
+        if line_count > self.capacity {
+            self.allocate(line_count.next_power_of_two().min(MAX_ROWS));
+            self.rows.clear();
+        }
+        self.rows.truncate(line_count);
+        let count = LINES_PER_FRAME.min(line_count.saturating_sub(first));
+        let highlighted = self.highlighter.highlight(buffer, first, count);
+        let mut pixels = vec![0u8; COLUMNS * 4];
+
+        for (i, line) in buffer.content.split('\n').skip(first).take(count).enumerate() {
+            let line = line.trim_end_matches('\r');
+            let spans = highlighted.as_ref().and_then(|lines| lines.get(i));
+            let hash = row_hash(line, spans.map_or(&[], Vec::as_slice));
+            let row = first + i;
+            if self.rows.get(row) == Some(&hash) {
+                continue;
+            }
+
+            rasterize(line, spans.map_or(&[], Vec::as_slice), foreground, &mut pixels);
+            unsafe {
+                gl::BindTexture(gl::TEXTURE_2D, self.texture);
+                gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+                gl::TexSubImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    0,
+                    row as i32,
+                    COLUMNS as i32,
+                    1,
+                    gl::RGBA,
+                    gl::UNSIGNED_BYTE,
+                    pixels.as_ptr() as *const _,
+                );
+            }
+            match self.rows.get_mut(row) {
+                Some(slot) => *slot = hash,
+                None => self.rows.push(hash),
+            }
+        }
+        self.next_row = (first + count < line_count).then_some(first + count);
+    }
+
+    fn allocate(&mut self, capacity: usize) {
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA as i32,
+                COLUMNS as i32,
+                capacity as i32,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                std::ptr::null(),
+            );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+        }
+        self.capacity = capacity;
+    }
+
+    // 缩略图放不下整个文件时跟着视口按比例滚动，返回顶部对应的行
+    pub fn first_line(view: &View, line_count: usize, area: [f32; 4], scale: f32) -> f32 {
+        let line_px = LINE_HEIGHT * scale;
+        let shown = (area[3] - area[1]) / line_px;
+        let max_scroll = line_count.saturating_sub(1) as f32;
+        let line_count = line_count.min(MAX_ROWS) as f32;
+        if line_count <= shown || max_scroll <= 0.0 {
+            return 0.0;
+        }
+        (view.scroll_line / max_scroll).clamp(0.0, 1.0) * (line_count - shown)
+    }
+
+    // 缩略图上 y 处对应的行
+    pub fn line_at(view: &View, line_count: usize, area: [f32; 4], scale: f32, y: f32) -> f32 {
+        Self::first_line(view, line_count, area, scale) + (y - area[1]) / (LINE_HEIGHT * scale)
+    }
+
+    // visible_lines 是编辑区一屏的行数，用来画视口框；matches 是要标出的搜索匹配
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        view: &View,
+        buffer: &Buffer,
+        matches: &[Range<usize>],
+        area: [f32; 4],
+        theme: &Theme,
+        scale: f32,
+        visible_lines: f32,
+        primitives: &mut PrimitiveRenderer,
+    ) {
+        let colors = &theme.colors;
+        let [x0, y0, x1, y1] = area;
+        let background = colors.background;
+        primitives.fill_rect(area, [background[0] * 0.85, background[1] * 0.85, background[2] * 0.85, 1.0]);
+        // 纹理画在底色之上、标记之下
+        primitives.flush();
+
+        let line_px = LINE_HEIGHT * scale;
+        let total_lines = buffer.content.matches('\n').count() + 1;
+        let first = Self::first_line(view, total_lines, area, scale);
+        let line_count = total_lines.min(MAX_ROWS) as f32;
+        let shown = ((y1 - y0) / line_px).min(line_count - first);
+        let columns = ((x1 - x0) / (CHAR_WIDTH * scale)).min(COLUMNS as f32);
+        if self.capacity > 0 && shown > 0.0 {
+            let quad = [x0, y0, x0 + columns * CHAR_WIDTH * scale, y0 + shown * line_px];
+            let capacity = self.capacity as f32;
+            let uv = [0.0, first / capacity, columns / COLUMNS as f32, (first + shown) / capacity];
+            self.draw_texture(quad, uv);
+        }
+
+        let row_top = |line: f32| y0 + (line - first) * line_px;
+        let visible = |y: f32| y + line_px > y0 && y < y1;
+
+        // 搜索匹配整行铺一层
+        let mut match_color = colors.warning;
+        match_color[3] = 0.45;
+        let mut line = 0;
+        let mut scanned = 0;
+        for range in matches {
+            if range.start < scanned || range.start > buffer.content.len() {
+                continue;
+            }
+            line += buffer.content[scanned..range.start].matches('\n').count();
+            scanned = range.start;
+            let y = row_top(line as f32);
+            if visible(y) {
+                primitives.fill_rect([x0, y, x1, y + line_px.max(2.0 * scale)], match_color);
+            }
+        }
+
+        // 相对上次保存的改动画在左边缘
+        let edge = (3.0 * scale).round();
+        for marker in buffer.markers.iter() {
+            let y = row_top(marker.line as f32);
+            if !visible(y) {
+                continue;
+            }
+            let height = line_px.max(2.0 * scale);
+            match marker.kind {
+                MarkerKind::Added => primitives.fill_rect([x0, y, x0 + edge, y + height], colors.success),
+                MarkerKind::Modified => primitives.fill_rect([x0, y, x0 + edge, y + height], colors.warning),
+                MarkerKind::Deleted => primitives.fill_rect([x0, y - scale, x0 + edge * 2.0, y + scale], colors.error),
+                MarkerKind::Breakpoint | MarkerKind::Bookmark => {}
+            }
+        }
+
+        // 当前视口的范围
+        let top = row_top(view.scroll_line).max(y0);
+        let bottom = (row_top(view.scroll_line) + visible_lines * line_px).min(y1);
+        let mut fill = colors.accent;
+        fill[3] = 0.15;
+        let mut border = colors.accent;
+        border[3] = 0.6;
+        primitives.draw_rect([x0, top, x1, bottom], &RectStyle::solid(fill).border(scale.round().max(1.0), border));
+    }
+
+    fn draw_texture(&self, quad: [f32; 4], uv: [f32; 4]) {
+        let [x0, y0, x1, y1] = quad;
+        let [u0, v0, u1, v1] = uv;
+        let vertices: [f32; 24] = [
+            x0, y0, u0, v0, x1, y0, u1, v0, x1, y1, u1, v1,
+            x0, y0, u0, v0, x1, y1, u1, v1, x0, y1, u0, v1,
+        ];
+        let float = std::mem::size_of::<f32>();
+        let stride = (4 * float) as i32;
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            self.shader.use_program();
+            self.shader.set_mat4("u_projection", &self.projection);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            self.shader.set_int("u_texture", 0);
+
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(&vertices) as isize,
+                vertices.as_ptr() as *const _,
+                gl::STREAM_DRAW,
+            );
+            gl::EnableVertexAttribArray(ATTRIB_POSITION);
+            gl::VertexAttribPointer(ATTRIB_POSITION, 2, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+            gl::EnableVertexAttribArray(ATTRIB_UV);
+            gl::VertexAttribPointer(ATTRIB_UV, 2, gl::FLOAT, gl::FALSE, stride, (2 * float) as *const _);
+
+            gl::DrawArrays(gl::TRIANGLES, 0, 6);
+
+            gl::DisableVertexAttribArray(ATTRIB_POSITION);
+            gl::DisableVertexAttribArray(ATTRIB_UV);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::UseProgram(0);
+        }
+    }
+}
+
+impl Drop for Minimap {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteTextures(1, &self.texture);
+        }
+    }
+}
+
+fn row_hash(line: &str, spans: &[TextSpan]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    line.hash(&mut hasher);
+    for span in spans {
+        span.range.hash(&mut hasher);
+        span.color.map(f32::to_bits).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+// 一个字符一个像素，空白透明，没有高亮片段的地方用前景色
+fn rasterize(line: &str, spans: &[TextSpan], foreground: [f32; 4], pixels: &mut [u8]) {
+    pixels.fill(0);
+    let mut column = 0;
+    for (offset, c) in line.char_indices() {
+        if column >= COLUMNS {
+            break;
+        }
+        if c == '\t' {
+            column += TAB_WIDTH - column % TAB_WIDTH;
+            continue;
+        }
+        if !c.is_whitespace() {
+            let color = spans.iter().find(|s| s.range.contains(&offset)).map_or(foreground, |s| s.color);
+            let channel = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+            let pixel = &mut pixels[column * 4..column * 4 + 4];
+            pixel.copy_from_slice(&[channel(color[0]), channel(color[1]), channel(color[2]), channel(color[3] * 0.8)]);
+        }
+        column += 1;
+    }
+}
@@ -0,0 +1,273 @@
+use anyhow::Result;
+use crate::font::ortho_projection;
+use crate::shader::Shader;
+
+// 矩形和线段都展开成带局部坐标的四边形，片段着色器用圆角矩形的距离函数算覆盖率，
+// 边缘和圆角自带 1 像素抗锯齿
+const PRIMITIVE_VERTEX_SHADER: &str = r#"
+#version 120
+attribute vec2 a_position;
+attribute vec2 a_local;
+attribute vec4 a_shape;
+attribute vec4 a_color;
+attribute vec4 a_border_color;
+uniform mat4 u_projection;
+varying vec2 v_local;
+varying vec4 v_shape;
+varying vec4 v_color;
+varying vec4 v_border_color;
+
+void main() {
+    v_local = a_local;
+    v_shape = a_shape;
+    v_color = a_color;
+    v_border_color = a_border_color;
+    gl_Position = u_projection * vec4(a_position, 0.0, 1.0);
+}
+"#;
+
+// v_shape = (半宽, 半高, 圆角半径, 边框宽度)，v_local 是相对中心的像素坐标
+const PRIMITIVE_FRAGMENT_SHADER: &str = r#"
+#version 120
+varying vec2 v_local;
+varying vec4 v_shape;
+varying vec4 v_color;
+varying vec4 v_border_color;
+
+float rounded_box(vec2 p, vec2 half_size, float radius) {
+    vec2 q = abs(p) - half_size + radius;
+    return length(max(q, 0.0)) + min(max(q.x, q.y), 0.0) - radius;
+}
+
+void main() {
+    float dist = rounded_box(v_local, v_shape.xy, v_shape.z);
+    float coverage = clamp(0.5 - dist, 0.0, 1.0);
+    vec4 color = v_color;
+    if (v_shape.w > 0.0) {
+        float inside = clamp(0.5 - (dist + v_shape.w), 0.0, 1.0);
+        color = mix(v_border_color, v_color, inside);
+    }
+    gl_FragColor = vec4(color.rgb, color.a * coverage);
+}
+"#;
+
+const ATTRIB_POSITION: u32 = 0;
+const ATTRIB_LOCAL: u32 = 1;
+const ATTRIB_SHAPE: u32 = 2;
+const ATTRIB_COLOR: u32 = 3;
+const ATTRIB_BORDER_COLOR: u32 = 4;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PrimitiveVertex {
+    pos: [f32; 2],
+    local: [f32; 2],
+    shape: [f32; 4],
+    color: [f32; 4],
+    border_color: [f32; 4],
+}
+
+// 填充方式：纯色，或从左到右的两色渐变
+#[derive(Clone, Copy, Debug)]
+pub enum Fill {
+    Solid([f32; 4]),
+    Horizontal([f32; 4], [f32; 4]),
+}
+
+// 矩形样式，单位都是物理像素
+#[derive(Clone, Copy, Debug)]
+pub struct RectStyle {
+    pub fill: Fill,
+    pub radius: f32,
+    pub border_width: f32,
+    pub border_color: [f32; 4],
+}
+
+impl RectStyle {
+    pub fn solid(color: [f32; 4]) -> Self {
+        RectStyle { fill: Fill::Solid(color), radius: 0.0, border_width: 0.0, border_color: [0.0; 4] }
+    }
+
+    pub fn rounded(mut self, radius: f32) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    pub fn border(mut self, width: f32, color: [f32; 4]) -> Self {
+        self.border_width = width;
+        self.border_color = color;
+        self
+    }
+}
+
+// 批量绘制矩形和线段。坐标系和 FontRenderer 一致：左上角为原点，单位物理像素
+pub struct PrimitiveRenderer {
+    shader: Shader,
+    vbo: u32,
+    vertices: Vec<PrimitiveVertex>,
+    projection: [f32; 16],
+    viewport_height: f32,
+    // 裁剪矩形栈，栈顶是当前生效的（已与外层求交）区域
+    clip_stack: Vec<[f32; 4]>,
+}
+
+impl PrimitiveRenderer {
+    pub fn new() -> Result<Self> {
+        let shader = Shader::with_attributes(
+            PRIMITIVE_VERTEX_SHADER,
+            PRIMITIVE_FRAGMENT_SHADER,
+            &[
+                (ATTRIB_POSITION, "a_position"),
+                (ATTRIB_LOCAL, "a_local"),
+                (ATTRIB_SHAPE, "a_shape"),
+                (ATTRIB_COLOR, "a_color"),
+                (ATTRIB_BORDER_COLOR, "a_border_color"),
+            ],
+        )?;
+        let mut vbo = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut vbo);
+        }
+
+        Ok(PrimitiveRenderer {
+            shader,
+            vbo,
+            vertices: Vec::new(),
+            projection: ortho_projection(1.0, 1.0),
+            viewport_height: 1.0,
+            clip_stack: Vec::new(),
+        })
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.projection = ortho_projection(width.max(1) as f32, height.max(1) as f32);
+        self.viewport_height = height.max(1) as f32;
+    }
+
+    pub fn fill_rect(&mut self, rect: [f32; 4], color: [f32; 4]) {
+        self.draw_rect(rect, &RectStyle::solid(color));
+    }
+
+    // 只画边框，内部透明
+    pub fn stroke_rect(&mut self, rect: [f32; 4], radius: f32, width: f32, color: [f32; 4]) {
+        self.draw_rect(rect, &RectStyle::solid([0.0; 4]).rounded(radius).border(width, color));
+    }
+
+    // rect 是 [x0, y0, x1, y1]
+    pub fn draw_rect(&mut self, rect: [f32; 4], style: &RectStyle) {
+        let [x0, y0, x1, y1] = rect;
+        if x1 <= x0 || y1 <= y0 {
+            return;
+        }
+        let (hw, hh) = ((x1 - x0) / 2.0, (y1 - y0) / 2.0);
+        let shape = [hw, hh, style.radius.clamp(0.0, hw.min(hh)), style.border_width];
+        let (top_left, top_right, bottom_left, bottom_right) = match style.fill {
+            Fill::Solid(c) => (c, c, c, c),
+            Fill::Horizontal(left, right) => (left, right, left, right),
+        };
+        let corners = [
+            ([x0, y0], [-hw, -hh], top_left),
+            ([x1, y0], [hw, -hh], top_right),
+            ([x1, y1], [hw, hh], bottom_right),
+            ([x0, y1], [-hw, hh], bottom_left),
+        ];
+        self.push_quad(corners, shape, style.border_color);
+    }
+
+    fn push_quad(&mut self, corners: [([f32; 2], [f32; 2], [f32; 4]); 4], shape: [f32; 4], border_color: [f32; 4]) {
+        for i in [0, 1, 2, 0, 2, 3] {
+            let (pos, local, color) = corners[i];
+            self.vertices.push(PrimitiveVertex { pos, local, shape, color, border_color });
+        }
+    }
+
+    // 进入一个裁剪区域，和外层区域求交。
+    // 调用前要先 flush 本层和文字的批次，否则已排队的内容也会被裁剪
+    pub fn push_clip(&mut self, rect: [f32; 4]) {
+        let rect = match self.clip_stack.last() {
+            Some(outer) => [
+                rect[0].max(outer[0]),
+                rect[1].max(outer[1]),
+                rect[2].min(outer[2]),
+                rect[3].min(outer[3]),
+            ],
+            None => rect,
+        };
+        self.clip_stack.push(rect);
+        self.apply_clip();
+    }
+
+    // 同样要求调用前已经 flush
+    pub fn pop_clip(&mut self) {
+        self.clip_stack.pop();
+        self.apply_clip();
+    }
+
+    fn apply_clip(&self) {
+        unsafe {
+            match self.clip_stack.last() {
+                Some(&[x0, y0, x1, y1]) => {
+                    // glScissor 的原点在左下角
+                    let x = x0.floor().max(0.0);
+                    let y = (self.viewport_height - y1.ceil()).max(0.0);
+                    let width = (x1.ceil() - x).max(0.0);
+                    let height = (y1.ceil() - y0.floor()).max(0.0);
+                    gl::Enable(gl::SCISSOR_TEST);
+                    gl::Scissor(x as i32, y as i32, width as i32, height as i32);
+                }
+                None => gl::Disable(gl::SCISSOR_TEST),
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        let stride = std::mem::size_of::<PrimitiveVertex>() as i32;
+        let float = std::mem::size_of::<f32>();
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            self.shader.use_program();
+            self.shader.set_mat4("u_projection", &self.projection);
+
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                (self.vertices.len() * stride as usize) as isize,
+                self.vertices.as_ptr() as *const _,
+                gl::STREAM_DRAW,
+            );
+            let attributes = [
+                (ATTRIB_POSITION, 2, 0),
+                (ATTRIB_LOCAL, 2, 2),
+                (ATTRIB_SHAPE, 4, 4),
+                (ATTRIB_COLOR, 4, 8),
+                (ATTRIB_BORDER_COLOR, 4, 12),
+            ];
+            for (attrib, size, offset) in attributes {
+                gl::EnableVertexAttribArray(attrib);
+                gl::VertexAttribPointer(attrib, size, gl::FLOAT, gl::FALSE, stride, (offset * float) as *const _);
+            }
+
+            gl::DrawArrays(gl::TRIANGLES, 0, self.vertices.len() as i32);
+
+            for (attrib, _, _) in attributes {
+                gl::DisableVertexAttribArray(attrib);
+            }
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::UseProgram(0);
+        }
+        self.vertices.clear();
+    }
+}
+
+impl Drop for PrimitiveRenderer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.vbo);
+        }
+    }
+}
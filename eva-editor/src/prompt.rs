@@ -0,0 +1,194 @@
+use crate::encoding::LineEnding;
+use crate::font::{FontRenderer, TextSpan, TextStyle};
+use crate::primitives::{PrimitiveRenderer, RectStyle};
+use crate::theme::Theme;
+
+// 提示里选项的效果，由 main 拿去执行
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PromptAction {
+    // 文件在外部被改了而缓冲区也有修改：留着缓冲区的内容、换成磁盘上的内容、看两者的差异。
+    // 保存时发现的冲突选完之后接着做 AfterSave，留着自己的就先覆盖磁盘上的文件
+    KeepMine(u64, AfterSave),
+    TakeTheirs(u64, AfterSave),
+    ShowDiff(u64),
+    // 上次没正常退出留下的恢复内容：恢复、和磁盘上的文件比较、丢掉、下次启动再问
+    Recover(u64),
+    ShowRecoveryDiff(u64),
+    DiscardRecovery(u64),
+    RecoverLater(u64),
+    // 退出时还有未保存的修改：全部保存后退出、丢掉修改退出
+    SaveAll,
+    DiscardAll,
+    // 关闭有未保存修改的缓冲区
+    SaveAndClose(u64),
+    DiscardAndClose(u64),
+    // 另存为输入框里的路径，成功后接着做 AfterSave
+    SaveAs(u64, AfterSave),
+    // 保存失败后：再试一次、改存到别处、丢掉这个缓冲区的修改，然后接着做 AfterSave
+    RetrySave(u64, AfterSave),
+    SaveElsewhere(u64, AfterSave),
+    DiscardChanges(u64, AfterSave),
+    // 转换换行符；转换成输入框里的编码
+    SetLineEnding(u64, LineEnding),
+    SetEncoding(u64),
+    Cancel,
+}
+
+// 另存为是谁发起的，存好之后接着做什么
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AfterSave {
+    Nothing,
+    Close,
+    Quit,
+}
+
+// 选中的选项，有输入框时带上里面的文字
+pub struct Reply {
+    pub action: PromptAction,
+    pub input: Option<String>,
+}
+
+impl PromptAction {
+    // 选了之后提示是否关掉；看过差异还得接着选
+    pub fn closes(self) -> bool {
+        !matches!(self, PromptAction::ShowDiff(_) | PromptAction::ShowRecoveryDiff(_))
+    }
+}
+
+pub struct Choice {
+    // 获得焦点时按这个字母（不分大小写）选它，标签里第一个相同的字母加下划线
+    pub key: char,
+    pub label: String,
+    pub action: PromptAction,
+}
+
+// 等用户选择的提示：一行说明、可选的输入框和一排按钮，Esc 取 cancel
+pub struct Prompt {
+    // 同 id 的提示会被替换，比如同一个文件又被改了一次
+    pub id: String,
+    pub message: String,
+    pub choices: Vec<Choice>,
+    pub cancel: PromptAction,
+    // 有输入框时字母键用来打字，回车选第一个选项
+    pub input: Option<String>,
+    // 用户自己发起的（退出、另存为）排到最前面并拿走焦点，外面发生的事（文件冲突）不打断打字
+    pub takes_focus: bool,
+}
+
+impl Prompt {
+    pub fn new(id: impl Into<String>, message: impl Into<String>, cancel: PromptAction) -> Self {
+        Prompt {
+            id: id.into(),
+            message: message.into(),
+            choices: Vec::new(),
+            cancel,
+            input: None,
+            takes_focus: false,
+        }
+    }
+
+    pub fn input(mut self, text: impl Into<String>) -> Self {
+        self.input = Some(text.into());
+        self
+    }
+
+    pub fn focused(mut self) -> Self {
+        self.takes_focus = true;
+        self
+    }
+
+    pub fn choice(mut self, key: char, label: impl Into<String>, action: PromptAction) -> Self {
+        self.choices.push(Choice { key, label: label.into(), action });
+        self
+    }
+
+    // 按下字符：有输入框时打进去，否则选字母对应的选项
+    pub fn key(&mut self, key: char) -> Option<PromptAction> {
+        if let Some(input) = &mut self.input {
+            input.push(key);
+            return None;
+        }
+        self.choices.iter().find(|c| c.key.eq_ignore_ascii_case(&key)).map(|c| c.action)
+    }
+
+    pub fn backspace(&mut self) {
+        if let Some(input) = &mut self.input {
+            input.pop();
+        }
+    }
+
+    // 回车选第一个选项
+    pub fn submit(&self) -> Option<PromptAction> {
+        self.choices.first().map(|c| c.action)
+    }
+
+    // 每行之间和上下各留一份 padding，按钮和输入框上下再各多出半份
+    pub fn height(&self, line_height: f32, padding: f32) -> f32 {
+        let rows = if self.input.is_some() { 3.0 } else { 2.0 };
+        line_height * rows + padding * (rows + 2.0)
+    }
+
+    // 画在弹出层的内容区里，返回每个按钮的区域
+    pub fn render(
+        &self,
+        area: [f32; 4],
+        theme: &Theme,
+        scale: f32,
+        primitives: &mut PrimitiveRenderer,
+        font_renderer: &mut FontRenderer,
+    ) -> Vec<([f32; 4], PromptAction)> {
+        let colors = &theme.colors;
+        let padding = theme.metrics.padding * scale;
+        let line_height = font_renderer.line_height();
+        let [x0, y0, x1, _] = area;
+
+        let message = [TextSpan { range: 0..self.message.len(), color: colors.foreground, style: TextStyle::default() }];
+        font_renderer.render_spans(&self.message, &message, (x0 + padding).round(), (y0 + padding).round(), 1.0);
+
+        let mut top = y0 + padding * 2.0 + line_height;
+        if let Some(input) = &self.input {
+            let field = [x0 + padding, top - padding / 2.0, x1 - padding, top + line_height + padding / 2.0];
+            primitives.draw_rect(field, &RectStyle::solid(colors.background).border(1.0, colors.accent));
+            // 太长时让末尾（正在打字的地方）露出来
+            let spans = [TextSpan { range: 0..input.len(), color: colors.foreground, style: TextStyle::default() }];
+            let width = font_renderer.measure_line(input, &spans).width;
+            let visible = field[2] - field[0] - padding * 2.0;
+            let text_x = field[0] + padding - (width - visible).max(0.0);
+            crate::ui::push_clip(primitives, font_renderer, field);
+            font_renderer.render_spans(input, &spans, text_x.round(), top.round(), 1.0);
+            let caret = (text_x + width).round();
+            primitives.fill_rect([caret, top, caret + scale.round().max(1.0), top + line_height], colors.accent);
+            crate::ui::pop_clip(primitives, font_renderer);
+            top += line_height + padding;
+        }
+
+        let mut x = x0 + padding;
+        let mut buttons = Vec::new();
+        for choice in &self.choices {
+            let spans = label_spans(choice, self.input.is_none(), theme);
+            let width = font_renderer.measure_line(&choice.label, &spans).width + padding * 2.0;
+            let rect = [x, top - padding / 2.0, x + width, top + line_height + padding / 2.0];
+            let style = RectStyle::solid(colors.background).rounded(3.0 * scale).border(1.0, colors.grid);
+            primitives.draw_rect(rect, &style);
+            font_renderer.render_spans(&choice.label, &spans, (x + padding).round(), top.round(), 1.0);
+            buttons.push((rect, choice.action));
+            x += width + padding;
+        }
+        buttons
+    }
+}
+
+// 选项字母用强调色加下划线；有输入框时字母键用来打字，不标
+fn label_spans(choice: &Choice, mark_key: bool, theme: &Theme) -> Vec<TextSpan> {
+    let colors = &theme.colors;
+    let plain = |range| TextSpan { range, color: colors.foreground, style: TextStyle::default() };
+    let label = &choice.label;
+    let found = label.char_indices().find(|(_, c)| c.eq_ignore_ascii_case(&choice.key));
+    let Some((start, c)) = found.filter(|_| mark_key) else {
+        return vec![plain(0..label.len())];
+    };
+    let end = start + c.len_utf8();
+    let mut key = TextSpan { range: start..end, color: colors.accent, style: TextStyle::default() };
+    key.style.underline = true;
+    [plain(0..start), key, plain(end..label.len())].into_iter().filter(|s| !s.range.is_empty()).collect()
+}
@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use crate::diff;
+use crate::editor::{Buffer, Editor};
+use crate::prompt::{Prompt, PromptAction};
+use crate::save::{self, SaveOptions};
+
+// 修改过的缓冲区最多隔这么久写一次交换文件
+const JOURNAL_INTERVAL: Duration = Duration::from_secs(2);
+// 交换文件的第一行，格式变了就改版本号
+const SWAP_HEADER: &str = "eva-editor swap 1";
+const LOCK_NAME: &str = "lock";
+
+// 没有 XDG_STATE_HOME 时放在 ~/.local/state 下
+pub fn recovery_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state")))?;
+    Some(base.join("eva-editor").join("recovery"))
+}
+
+// 没正常退出的会话留下的一个缓冲区
+struct Recovered {
+    id: u64,
+    // 已经挪进本会话的目录
+    swap: PathBuf,
+    path: Option<PathBuf>,
+    content: String,
+}
+
+// 把修改过的缓冲区定期写进恢复目录，崩溃或直接关窗口后下次启动能找回来。
+// 每个会话有自己的子目录，里面的 lock 在会话期间一直锁着；锁得上的子目录属于已经退出的会话
+pub struct Journal {
+    // 建不起来时不记录，编辑照常
+    session: Option<PathBuf>,
+    _lock: Option<File>,
+    // 缓冲区 id → 写进交换文件的版本
+    written: HashMap<u64, u64>,
+    last_write: Instant,
+    recovered: Vec<Recovered>,
+    next_recovered: u64,
+}
+
+impl Journal {
+    // 建本会话的目录，再把已经结束的会话留下的交换文件接过来
+    pub fn open() -> Self {
+        let mut journal = Journal {
+            session: None,
+            _lock: None,
+            written: HashMap::new(),
+            last_write: Instant::now(),
+            recovered: Vec::new(),
+            next_recovered: 1,
+        };
+        let Some(root) = recovery_dir() else {
+            return journal;
+        };
+        match create_session(&root) {
+            Ok((session, lock)) => {
+                journal.session = Some(session);
+                journal._lock = Some(lock);
+            }
+            Err(e) => {
+                warn!("Crash recovery is disabled: {:#}", e);
+                return journal;
+            }
+        }
+        journal.claim_orphans(&root);
+        journal
+    }
+
+    fn claim_orphans(&mut self, root: &Path) {
+        let Ok(entries) = fs::read_dir(root) else {
+            return;
+        };
+        for dir in entries.flatten().map(|entry| entry.path()) {
+            if Some(&dir) == self.session.as_ref() || !dir.is_dir() {
+                continue;
+            }
+            // 锁不上说明那个会话还在运行；接管期间一直拿着它的锁，同时启动的另一个窗口不会也来接
+            let lock = File::open(dir.join(LOCK_NAME)).ok();
+            if lock.as_ref().is_some_and(|lock| lock.try_lock().is_err()) {
+                continue;
+            }
+            let Ok(files) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for file in files.flatten().map(|entry| entry.path()) {
+                if file.extension().is_some_and(|ext| ext == "swap") {
+                    self.claim(&file);
+                } else {
+                    let _ = fs::remove_file(&file);
+                }
+            }
+            // 读不了的交换文件还留在里面时删不掉目录，留着它
+            let _ = fs::remove_dir(&dir);
+        }
+    }
+
+    // 挪进本会话的目录：别的窗口不会再接，做出选择前又崩溃了也还在
+    fn claim(&mut self, swap: &Path) {
+        let Some(session) = self.session.clone() else {
+            return;
+        };
+        let (path, content) = match read_swap(swap) {
+            Ok(swap) => swap,
+            Err(e) => {
+                warn!("Skipping recovery file {}: {:#}", swap.display(), e);
+                return;
+            }
+        };
+        // 和磁盘上的一样就没什么可恢复的
+        let unchanged = match &path {
+            Some(path) => Buffer::from_file(path.clone()).is_ok_and(|buffer| buffer.content == content),
+            None => content.is_empty(),
+        };
+        if unchanged {
+            let _ = fs::remove_file(swap);
+            return;
+        }
+
+        let id = self.next_recovered;
+        self.next_recovered += 1;
+        let target = session.join(format!("recovered-{}.swap", id));
+        if let Err(e) = fs::rename(swap, &target) {
+            warn!("Failed to claim recovery file {}: {}", swap.display(), e);
+            return;
+        }
+        info!("Found unsaved changes to {} from an earlier session", describe(path.as_deref()));
+        self.recovered.push(Recovered { id, swap: target, path, content });
+    }
+
+    // 启动时逐个问要不要恢复
+    pub fn prompts(&self) -> Vec<Prompt> {
+        self.recovered
+            .iter()
+            .map(|recovered| {
+                let id = recovered.id;
+                let name = recovered
+                    .path
+                    .as_ref()
+                    .and_then(|path| path.file_name())
+                    .map_or_else(|| "an untitled buffer".to_string(), |name| name.to_string_lossy().into_owned());
+                let mut prompt = Prompt::new(
+                    format!("recovery:{}", id),
+                    format!("Unsaved changes to {} were left by a session that did not exit cleanly.", name),
+                    PromptAction::RecoverLater(id),
+                )
+                .choice('r', "Recover", PromptAction::Recover(id));
+                if recovered.path.is_some() {
+                    prompt = prompt.choice('s', "Show diff", PromptAction::ShowRecoveryDiff(id));
+                }
+                prompt.choice('d', "Discard", PromptAction::DiscardRecovery(id))
+            })
+            .collect()
+    }
+
+    // 打开文件（没打开的话）并换上恢复的内容，作为未保存的修改
+    pub fn recover(&mut self, editor: &mut Editor, id: u64) {
+        let Some(index) = self.recovered.iter().position(|r| r.id == id) else {
+            return;
+        };
+        let recovered = self.recovered.remove(index);
+        let result = match &recovered.path {
+            Some(path) => editor.open_file(path.clone()),
+            None => {
+                editor.open_buffer(Buffer::new());
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            error!("Failed to recover {}: {:#}", describe(recovered.path.as_deref()), e);
+            self.recovered.push(recovered);
+            return;
+        }
+        editor.restore_buffer(editor.active_buffer, recovered.content);
+        info!("Recovered unsaved changes to {}", describe(recovered.path.as_deref()));
+        // 先写进本会话的交换文件，再删掉恢复来的那个
+        self.write(editor);
+        let _ = fs::remove_file(&recovered.swap);
+    }
+
+    pub fn show_diff(&self, editor: &mut Editor, id: u64) {
+        let Some(recovered) = self.recovered.iter().find(|r| r.id == id) else {
+            return;
+        };
+        let Some(path) = &recovered.path else {
+            return;
+        };
+        // 文件已经不在了就和空文件比
+        let on_disk = Buffer::from_file(path.clone()).map(|buffer| buffer.content).unwrap_or_default();
+        let name = path.display();
+        let old_name = format!("{} (on disk)", name);
+        let new_name = format!("{} (recovered)", name);
+        editor.open_buffer(diff::diff_buffer(&on_disk, &recovered.content, &old_name, &new_name));
+    }
+
+    pub fn discard(&mut self, id: u64) {
+        if let Some(index) = self.recovered.iter().position(|r| r.id == id) {
+            let recovered = self.recovered.remove(index);
+            let _ = fs::remove_file(&recovered.swap);
+            info!("Discarded recovered changes to {}", describe(recovered.path.as_deref()));
+        }
+    }
+
+    // 每帧调用，隔一段时间写一次
+    pub fn update(&mut self, editor: &Editor) {
+        if self.last_write.elapsed() >= JOURNAL_INTERVAL {
+            self.write(editor);
+        }
+    }
+
+    // 正常退出：还没保存的缓冲区最后写一次，留给下次启动恢复；什么都没留下时删掉会话目录
+    pub fn finish(mut self, editor: &Editor) {
+        self.write(editor);
+        let Some(session) = self.session.take() else {
+            return;
+        };
+        if !self.written.is_empty() || !self.recovered.is_empty() {
+            info!("Unsaved changes were kept in {} for recovery", session.display());
+            return;
+        }
+        let _ = fs::remove_file(session.join(LOCK_NAME));
+        let _ = fs::remove_dir(&session);
+    }
+
+    // 写下版本变了的修改过的缓冲区；保存了或关掉的缓冲区删掉交换文件
+    fn write(&mut self, editor: &Editor) {
+        self.last_write = Instant::now();
+        let Some(session) = &self.session else {
+            return;
+        };
+        self.written.retain(|&id, _| {
+            let keep = editor.buffers.iter().any(|b| b.id == id && b.modified);
+            if !keep {
+                let _ = fs::remove_file(swap_path(session, id));
+            }
+            keep
+        });
+        for buffer in editor.buffers.iter().filter(|b| b.modified) {
+            if self.written.get(&buffer.id) == Some(&buffer.version) {
+                continue;
+            }
+            // 失败了也记下版本，不每次都报，等下次修改再试
+            if let Err(e) = write_swap(&swap_path(session, buffer.id), buffer) {
+                warn!("Failed to write recovery file: {:#}", e);
+            }
+            self.written.insert(buffer.id, buffer.version);
+        }
+    }
+}
+
+fn create_session(root: &Path) -> Result<(PathBuf, File)> {
+    let started = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis());
+    let session = root.join(format!("{}-{}", std::process::id(), started));
+    create_private_dir(&session)?;
+    let lock = File::create(session.join(LOCK_NAME))?;
+    lock.try_lock()?;
+    Ok((session, lock))
+}
+
+// 交换文件里是未保存的内容，目录只给自己看
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(dir)
+}
+
+fn swap_path(session: &Path, buffer_id: u64) -> PathBuf {
+    session.join(format!("{}.swap", buffer_id))
+}
+
+// 首行、`path: 绝对路径`（未命名的缓冲区没有这一行）、空行，然后是内容
+fn write_swap(swap: &Path, buffer: &Buffer) -> Result<()> {
+    let mut text = format!("{}\n", SWAP_HEADER);
+    if let Some(path) = &buffer.file_path {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.clone());
+        text.push_str(&format!("path: {}\n", path.display()));
+    }
+    text.push('\n');
+    text.push_str(&buffer.content);
+    save::write_atomic(swap, text.as_bytes(), &SaveOptions::default())?;
+    Ok(())
+}
+
+fn read_swap(swap: &Path) -> Result<(Option<PathBuf>, String)> {
+    let text = fs::read_to_string(swap)?;
+    let mut rest = text
+        .strip_prefix(SWAP_HEADER)
+        .and_then(|rest| rest.strip_prefix('\n'))
+        .ok_or_else(|| anyhow!("not a recovery file"))?;
+    let mut path = None;
+    loop {
+        let (line, next) = rest.split_once('\n').ok_or_else(|| anyhow!("truncated header"))?;
+        rest = next;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("path: ") {
+            path = Some(PathBuf::from(value));
+        }
+    }
+    Ok((path, rest.to_string()))
+}
+
+fn describe(path: Option<&Path>) -> String {
+    path.map_or_else(|| "an untitled buffer".to_string(), |path| path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eva-editor-recovery-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // 在 root 下开一个新会话，和 Journal::open 一样，只是不读环境变量
+    fn open_in(root: &Path) -> Journal {
+        let (session, lock) = create_session(root).unwrap();
+        Journal {
+            session: Some(session),
+            _lock: Some(lock),
+            written: HashMap::new(),
+            last_write: Instant::now(),
+            recovered: Vec::new(),
+            next_recovered: 1,
+        }
+    }
+
+    // 一个已经退出的会话目录，lock 没人锁着
+    fn orphan(root: &Path, name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = root.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        File::create(dir.join(LOCK_NAME)).unwrap();
+        for (file, text) in files {
+            fs::write(dir.join(file), text).unwrap();
+        }
+        dir
+    }
+
+    fn swap_text(path: Option<&Path>, content: &str) -> String {
+        let path = path.map_or_else(String::new, |path| format!("path: {}\n", path.display()));
+        format!("{}\n{}\n{}", SWAP_HEADER, path, content)
+    }
+
+    #[test]
+    fn swap_files_round_trip() {
+        let dir = scratch("round-trip");
+        let file = dir.join("notes.txt");
+        // 内容里的空行和像头部的行都原样留着
+        let content = "first\n\npath: not a header\n";
+        let swap = dir.join("1.swap");
+        write_swap(&swap, &Buffer::from_content(content.to_string(), Some(file.clone()))).unwrap();
+        assert_eq!(read_swap(&swap).unwrap(), (Some(file), content.to_string()));
+
+        write_swap(&swap, &Buffer::from_content("untitled".to_string(), None)).unwrap();
+        assert_eq!(read_swap(&swap).unwrap(), (None, "untitled".to_string()));
+
+        fs::write(&swap, "some other file\n\ncontent").unwrap();
+        assert!(read_swap(&swap).is_err());
+        fs::write(&swap, format!("{}\npath: /tmp/a", SWAP_HEADER)).unwrap();
+        assert!(read_swap(&swap).is_err());
+    }
+
+    #[test]
+    fn journal_writes_modified_buffers_and_drops_saved_ones() {
+        let root = scratch("journal");
+        let mut journal = open_in(&root);
+        let session = journal.session.clone().unwrap();
+        let mut editor = Editor::new();
+        editor.open_buffer(Buffer::from_content("draft".to_string(), None));
+        let id = editor.buffers[0].id;
+
+        // 没修改过的不写
+        journal.write(&editor);
+        assert!(!swap_path(&session, id).exists());
+        editor.buffers[0].insert(5, " two");
+        journal.write(&editor);
+        assert_eq!(read_swap(&swap_path(&session, id)).unwrap(), (None, "draft two".to_string()));
+
+        editor.buffers[0].modified = false;
+        journal.write(&editor);
+        assert!(!swap_path(&session, id).exists());
+        journal.finish(&editor);
+        assert!(!session.exists());
+    }
+
+    #[test]
+    fn orphaned_sessions_are_claimed_once() {
+        let root = scratch("orphans");
+        let file = root.join("notes.txt");
+        fs::write(&file, "on disk\n").unwrap();
+
+        let finished = orphan(
+            &root,
+            "1-finished",
+            &[
+                ("1.swap", &swap_text(Some(&file), "edited\n")),
+                // 和磁盘上一样的没什么可恢复的，直接删掉
+                ("2.swap", &swap_text(Some(&file), "on disk\n")),
+                ("leftover.tmp", "junk"),
+            ],
+        );
+        // 读不了的交换文件留着，目录也就删不掉
+        let broken = orphan(&root, "2-broken", &[("1.swap", "not a swap file")]);
+        // 还在运行的会话拿着自己的锁，不去碰它
+        let running = orphan(&root, "3-running", &[("1.swap", &swap_text(None, "theirs"))]);
+        let running_lock = File::open(running.join(LOCK_NAME)).unwrap();
+        running_lock.try_lock().unwrap();
+
+        let mut journal = open_in(&root);
+        journal.claim_orphans(&root);
+        assert_eq!(journal.recovered.len(), 1);
+        let recovered = &journal.recovered[0];
+        assert_eq!((recovered.path.as_deref(), recovered.content.as_str()), (Some(file.as_path()), "edited\n"));
+        assert_eq!(recovered.swap, journal.session.as_ref().unwrap().join("recovered-1.swap"));
+        assert!(recovered.swap.exists());
+        assert!(!finished.exists());
+        assert!(broken.join("1.swap").exists());
+        assert!(running.join("1.swap").exists());
+
+        // 接过来的文件已经在本会话的目录里，另一个新会话不会再接一次
+        // 会话目录按进程号和毫秒命名，同一进程里隔开一点时间才不会撞名
+        std::thread::sleep(Duration::from_millis(5));
+        let mut other = open_in(&root);
+        other.claim_orphans(&root);
+        assert!(other.recovered.is_empty());
+        assert!(journal.recovered[0].swap.exists());
+
+        journal.discard(1);
+        assert!(!journal.session.as_ref().unwrap().join("recovered-1.swap").exists());
+    }
+}
@@ -1,27 +1,59 @@
-use crate::theme::Theme;
-use crate::ui::UI;
+use crate::theme::{CursorShape, Theme};
+use crate::layout::PanelId;
+use crate::ui::{CursorFrame, EDITOR, TABS, UI};
 use crate::editor::Editor;
 use crate::font::FontRenderer;
+use crate::primitives::PrimitiveRenderer;
+use crate::prompt::{Prompt, Reply};
+use crate::status_bar::{SegmentAlign, StatusSegment};
 use anyhow::Result;
-use std::time::Instant;
+use log::info;
+use std::time::{Duration, Instant};
 use gl::*;
 
+// 缩放步长和字号范围（逻辑像素）
+const ZOOM_STEP: f32 = 1.0;
+const MIN_FONT_SIZE: f32 = 6.0;
+const MAX_FONT_SIZE: f32 = 72.0;
+// 滚轮每格滚动的行数
+const WHEEL_SCROLL_LINES: f32 = 3.0;
+
 pub struct Renderer {
     ui: UI,
     font_renderer: FontRenderer,
+    // 背景、选区、光标等非文字图形，先于文字绘制
+    primitives: PrimitiveRenderer,
     theme: Theme,
+    // 当前字号，缩放只改它，theme.font.size 保持为默认值
+    font_size: f32,
     start_time: Instant,
+    // 最近一次输入距 start_time 的时长，光标闪烁从这里重新计时
+    last_input: Duration,
+    focused: bool,
     window_width: u32,
     window_height: u32,
 }
 
 impl Renderer {
-    pub fn new(window_width: u32, window_height: u32) -> Result<Self> {
+    // 宽高是帧缓冲的物理像素尺寸，content_scale 来自 GLFW 的窗口内容缩放
+    pub fn new(theme: Theme, window_width: u32, window_height: u32, content_scale: f32) -> Result<Self> {
+        let mut font_renderer = FontRenderer::new(&theme.font, content_scale)?;
+        font_renderer.resize(window_width, window_height);
+        let mut primitives = PrimitiveRenderer::new()?;
+        primitives.resize(window_width, window_height);
+
+        let mut ui = UI::new(&theme, content_scale)?;
+        ui.resize(window_width as i32, window_height as i32);
+
         Ok(Renderer {
-            ui: UI::new()?,
-            font_renderer: FontRenderer::new("/System/Library/Fonts/Menlo.ttc", 14)?,
-            theme: Theme::default(),
+            ui,
+            font_renderer,
+            primitives,
+            font_size: theme.font.size,
+            theme,
             start_time: Instant::now(),
+            last_input: Duration::ZERO,
+            focused: true,
             window_width,
             window_height,
         })
@@ -31,6 +63,194 @@ impl Renderer {
         self.window_width = width;
         self.window_height = height;
         self.ui.resize(width as i32, height as i32);
+        self.font_renderer.resize(width, height);
+        self.primitives.resize(width, height);
+    }
+
+    // 窗口移到另一块缩放比例不同的显示器上
+    pub fn set_content_scale(&mut self, content_scale: f32) -> Result<()> {
+        self.font_renderer.set_content_scale(content_scale)?;
+        self.ui.set_content_scale(content_scale);
+        Ok(())
+    }
+
+    pub fn zoom_in(&mut self, editor: &mut Editor) -> Result<()> {
+        self.set_font_size(editor, self.font_size + ZOOM_STEP)
+    }
+
+    pub fn zoom_out(&mut self, editor: &mut Editor) -> Result<()> {
+        self.set_font_size(editor, self.font_size - ZOOM_STEP)
+    }
+
+    pub fn reset_zoom(&mut self, editor: &mut Editor) -> Result<()> {
+        self.set_font_size(editor, self.theme.font.size)
+    }
+
+    // 改字号并调整滚动位置，让光标所在行停在屏幕上原来的高度
+    fn set_font_size(&mut self, editor: &mut Editor, size: f32) -> Result<()> {
+        let size = size.clamp(MIN_FONT_SIZE, MAX_FONT_SIZE);
+        if (size - self.font_size).abs() < f32::EPSILON {
+            return Ok(());
+        }
+
+        let old_line_height = self.font_renderer.line_height();
+        self.font_renderer.set_font_size(size)?;
+        self.font_size = size;
+        let new_line_height = self.font_renderer.line_height();
+
+        let cursor_line = editor.cursor.line as f32;
+        let cursor_offset = (cursor_line - editor.scroll_line) * old_line_height;
+        editor.scroll_line = (cursor_line - cursor_offset / new_line_height).max(0.0);
+
+        // 缩放过时在状态栏显示比例，回到默认字号就去掉
+        let default_size = self.theme.font.size;
+        let status_bar = self.ui.status_bar_mut();
+        if (size - default_size).abs() < f32::EPSILON {
+            status_bar.remove_segment("zoom");
+        } else {
+            let zoom = format!("{:.0}%", size / default_size * 100.0);
+            status_bar.set_segment(StatusSegment::new("zoom", zoom, SegmentAlign::Right, 25));
+        }
+
+        info!("Font size set to {}", size);
+        Ok(())
+    }
+
+    // 打字或移动光标时调用：光标保持常亮，停下后再开始闪烁
+    pub fn notify_input(&mut self) {
+        self.last_input = self.start_time.elapsed();
+    }
+
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+        self.notify_input();
+    }
+
+    fn cursor_frame(&self) -> CursorFrame {
+        let config = &self.theme.cursor;
+        if !self.focused {
+            return CursorFrame { shape: CursorShape::HollowBlock, visible: true, smooth: config.smooth };
+        }
+        let idle = self.start_time.elapsed().saturating_sub(self.last_input).as_secs_f32();
+        let visible = !config.blink || ((idle / config.blink_interval.max(0.05)) as u32).is_multiple_of(2);
+        CursorFrame { shape: config.shape, visible, smooth: config.smooth }
+    }
+
+    // 帧缓冲坐标落在行号栏断点道上时返回所在行
+    pub fn gutter_marker_hit(&mut self, editor: &Editor, x: f32, y: f32) -> Option<usize> {
+        self.ui.gutter_marker_hit(editor, &self.theme, &mut self.font_renderer, x, y)
+    }
+
+    // 帧缓冲坐标处对应的文本位置
+    pub fn hit_test(&mut self, editor: &Editor, x: f32, y: f32) -> Option<usize> {
+        self.ui.hit_test(editor, &self.theme, &mut self.font_renderer, x, y)
+    }
+
+    pub fn sidebar_row_at(&self, editor: &Editor, y: f32) -> Option<usize> {
+        self.ui.sidebar_row_at(editor, &self.font_renderer, y)
+    }
+
+    pub fn tab_at(&mut self, editor: &Editor, x: f32, y: f32) -> Option<usize> {
+        self.ui.tab_at(editor, &mut self.font_renderer, x, y)
+    }
+
+    pub fn status_segment_at(&mut self, editor: &Editor, x: f32) -> Option<String> {
+        self.ui.status_segment_at(editor, &self.theme, &mut self.font_renderer, x)
+    }
+
+    pub fn tab_drop_target(&mut self, editor: &Editor, x: f32) -> Option<usize> {
+        self.ui.tab_drop_target(editor, &mut self.font_renderer, x)
+    }
+
+    // 滚轮：在标签栏上横向滚动标签页，其他地方滚动鼠标下的窗格
+    pub fn scroll(&mut self, editor: &mut Editor, x: f32, y: f32, dx: f32, dy: f32) {
+        match self.ui.panel_at(x, y) {
+            Some(TABS) => self.ui.scroll_tabs(dx - dy),
+            Some(EDITOR) => {
+                if let Some(id) = self.ui.pane_at(editor, x, y) {
+                    editor.scroll_view(id, -dy * WHEEL_SCROLL_LINES);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // 按下鼠标：把键盘焦点给点中的面板，返回点中的面板或弹出层
+    pub fn focus_at(&mut self, x: f32, y: f32) -> Option<PanelId> {
+        self.ui.focus_at(x, y)
+    }
+
+    pub fn focused_panel(&self) -> Option<PanelId> {
+        self.ui.focused_panel()
+    }
+
+    // 键盘焦点移到下一个（backward 时上一个）面板或弹出层
+    pub fn focus_next(&mut self, backward: bool) {
+        self.ui.focus_next(backward);
+    }
+
+    // 关掉最上层的弹出层，返回是否关了
+    pub fn dismiss_overlay(&mut self) -> bool {
+        self.ui.dismiss_overlay()
+    }
+
+    pub fn show_prompt(&mut self, prompt: Prompt) {
+        self.ui.show_prompt(prompt, &self.theme.metrics, self.font_renderer.line_height());
+    }
+
+    pub fn answer_prompt(&mut self, key: char) -> Option<Reply> {
+        self.ui.answer_prompt(key)
+    }
+
+    pub fn prompt_backspace(&mut self) {
+        self.ui.prompt_backspace();
+    }
+
+    pub fn submit_prompt(&mut self) -> Option<Reply> {
+        self.ui.submit_prompt()
+    }
+
+    pub fn prompt_choice_at(&mut self, x: f32, y: f32) -> Option<Reply> {
+        self.ui.prompt_choice_at(x, y)
+    }
+
+    // Esc 先交给提示，返回它的取消选项
+    pub fn cancel_prompt(&mut self) -> Option<Reply> {
+        self.ui.cancel_prompt()
+    }
+
+    // 点到哪个窗格就把焦点给它
+    pub fn focus_pane_at(&mut self, editor: &mut Editor, x: f32, y: f32) {
+        if let Some(id) = self.ui.pane_at(editor, x, y) {
+            editor.focus_view(id);
+        }
+    }
+
+    // 在窗格分隔条上按下鼠标，返回是否处理了
+    pub fn begin_divider_drag(&mut self, editor: &Editor, x: f32, y: f32) -> bool {
+        self.ui.begin_divider_drag(editor, x, y)
+    }
+
+    // 在滚动条或缩略图上按下鼠标，返回是否处理了
+    pub fn begin_scroll_drag(&mut self, editor: &mut Editor, x: f32, y: f32) -> bool {
+        self.ui.begin_scroll_drag(editor, &self.theme, &self.font_renderer, x, y)
+    }
+
+    // 拖动滚动条、缩略图或分隔条
+    pub fn drag_to(&mut self, editor: &mut Editor, x: f32, y: f32) -> bool {
+        self.ui.drag_to(editor, &self.theme, &self.font_renderer, x, y)
+    }
+
+    pub fn end_drag(&mut self) {
+        self.ui.end_drag();
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.ui.is_dragging()
+    }
+
+    pub fn toggle_sidebar(&mut self) {
+        self.ui.toggle_sidebar();
     }
 
     pub fn render(&mut self, editor: &Editor) {
@@ -46,7 +266,8 @@ impl Renderer {
         }
 
         // 渲染 UI
-        self.ui.render(editor, &self.theme);
+        let cursor = self.cursor_frame();
+        self.ui.render(editor, &self.theme, &cursor, &mut self.primitives, &mut self.font_renderer);
     }
 }
 
@@ -0,0 +1,239 @@
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use anyhow::{bail, Context, Result};
+use log::warn;
+
+// 符号链接最多跟这么多层，超过当作有环
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+// 临时文件名里的序号，同一进程里同时保存几个文件也不会撞名
+static NEXT_TEMP: AtomicU32 = AtomicU32::new(0);
+
+// 保存选项，由配置文件的 [files] 段设置
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SaveOptions {
+    // 覆盖前把原文件复制成 "文件名~"
+    pub backup: bool,
+}
+
+// 先写到同目录的临时文件并落盘，再改名盖住原文件：中途崩溃或磁盘写满时原文件保持原样。
+// 符号链接写到它指向的真实文件，原文件的权限和属主保留下来。返回实际写入的路径
+pub fn write_atomic(path: &Path, contents: &[u8], options: &SaveOptions) -> Result<PathBuf> {
+    let target = resolve_symlinks(path)?;
+    let dir = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let existing = fs::metadata(&target).ok();
+
+    if options.backup && existing.is_some() {
+        let backup = backup_path(&target);
+        fs::copy(&target, &backup).with_context(|| format!("Failed to write backup {}", backup.display()))?;
+    }
+
+    let (temp_path, mut file) = create_temp(&dir, &target)?;
+    let result = (|| -> Result<()> {
+        // 先改权限再写内容，私密文件的内容不会在临时文件里短暂地对别人可读
+        if let Some(metadata) = &existing {
+            copy_metadata(&file, metadata)?;
+        }
+        file.write_all(contents)?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temp_path, &target)?;
+        sync_dir(&dir);
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result.with_context(|| format!("Failed to save {}", target.display()))?;
+    Ok(target)
+}
+
+// 一层层跟到最终的文件；最终文件可以还不存在（新文件，或者悬空的链接）
+fn resolve_symlinks(path: &Path) -> Result<PathBuf> {
+    let mut current = path.to_path_buf();
+    for _ in 0..MAX_SYMLINK_DEPTH {
+        match fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let link = fs::read_link(&current)?;
+                current = match current.parent() {
+                    Some(parent) if link.is_relative() => parent.join(link),
+                    _ => link,
+                };
+            }
+            _ => return Ok(current),
+        }
+    }
+    bail!("Too many levels of symbolic links: {}", path.display())
+}
+
+fn backup_path(target: &Path) -> PathBuf {
+    let mut name = target.file_name().unwrap_or_default().to_os_string();
+    name.push("~");
+    target.with_file_name(name)
+}
+
+// 在 dir 里建一个不存在的隐藏临时文件
+fn create_temp(dir: &Path, target: &Path) -> Result<(PathBuf, File)> {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    loop {
+        let serial = NEXT_TEMP.fetch_add(1, Ordering::Relaxed);
+        let temp_path = dir.join(format!(".{}.{}.{}.tmp", name, std::process::id(), serial));
+        match OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to create a temporary file in {}", dir.display()))
+            }
+        }
+    }
+}
+
+// 权限照搬；属主只有 root 能改，改不了属组时（不在那个组里）只记一条警告
+#[cfg(unix)]
+fn copy_metadata(file: &File, metadata: &Metadata) -> Result<()> {
+    use std::os::unix::fs::{fchown, MetadataExt};
+    file.set_permissions(metadata.permissions())?;
+    if fchown(file, Some(metadata.uid()), Some(metadata.gid())).is_err() {
+        if let Err(e) = fchown(file, None, Some(metadata.gid())) {
+            warn!("Could not preserve the group of the saved file: {}", e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn copy_metadata(file: &File, metadata: &Metadata) -> Result<()> {
+    file.set_permissions(metadata.permissions())?;
+    Ok(())
+}
+
+// 改名要等目录项落盘才算数；有的文件系统不支持对目录 fsync，失败了也不影响保存
+fn sync_dir(dir: &Path) {
+    #[cfg(unix)]
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    #[cfg(not(unix))]
+    let _ = dir;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 每个测试一个空目录
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eva-editor-save-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> =
+            fs::read_dir(dir).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn replaces_content_and_keeps_mode() {
+        let dir = scratch("mode");
+        let path = dir.join("notes.txt");
+        fs::write(&path, "old").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+
+        let written = write_atomic(&path, b"new content", &SaveOptions::default()).unwrap();
+        assert_eq!(written, path);
+        assert_eq!(fs::read(&path).unwrap(), b"new content");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+        }
+        assert_eq!(entries(&dir), ["notes.txt"]);
+
+        // 新文件
+        let created = dir.join("created.txt");
+        write_atomic(&created, b"hello", &SaveOptions::default()).unwrap();
+        assert_eq!(fs::read(&created).unwrap(), b"hello");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_through_symlinks() {
+        let dir = scratch("symlink");
+        fs::create_dir(dir.join("real")).unwrap();
+        let target = dir.join("real").join("config");
+        fs::write(&target, "old").unwrap();
+        let link = dir.join("link");
+        std::os::unix::fs::symlink("real/config", &link).unwrap();
+        let chain = dir.join("chain");
+        std::os::unix::fs::symlink(&link, &chain).unwrap();
+
+        let written = write_atomic(&chain, b"new", &SaveOptions::default()).unwrap();
+        assert_eq!(written, target);
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert!(fs::symlink_metadata(&chain).unwrap().file_type().is_symlink());
+        assert_eq!(entries(&dir.join("real")), ["config"]);
+
+        // 有环的链接
+        let a = dir.join("a");
+        std::os::unix::fs::symlink(dir.join("b"), &a).unwrap();
+        std::os::unix::fs::symlink(&a, dir.join("b")).unwrap();
+        assert!(write_atomic(&a, b"x", &SaveOptions::default()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn backup_only_when_enabled() {
+        let dir = scratch("backup");
+        let path = dir.join("main.rs");
+        fs::write(&path, "v1").unwrap();
+
+        write_atomic(&path, b"v2", &SaveOptions { backup: false }).unwrap();
+        assert_eq!(entries(&dir), ["main.rs"]);
+
+        write_atomic(&path, b"v3", &SaveOptions { backup: true }).unwrap();
+        assert_eq!(entries(&dir), ["main.rs", "main.rs~"]);
+        assert_eq!(fs::read(dir.join("main.rs~")).unwrap(), b"v2");
+        assert_eq!(fs::read(&path).unwrap(), b"v3");
+
+        // 还没有原文件时没有什么可备份的
+        write_atomic(&dir.join("new.rs"), b"v1", &SaveOptions { backup: true }).unwrap();
+        assert!(!dir.join("new.rs~").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_save_leaves_the_original_alone() {
+        let dir = scratch("failure");
+        let path = dir.join("data.txt");
+        fs::write(&path, "original").unwrap();
+        // 备份的位置被一个目录占着，复制失败
+        fs::create_dir(dir.join("data.txt~")).unwrap();
+        assert!(write_atomic(&path, b"replacement", &SaveOptions { backup: true }).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"original");
+        assert_eq!(entries(&dir), ["data.txt", "data.txt~"]);
+
+        // 改名时失败（目标是个不空的目录），临时文件要删掉
+        let occupied = dir.join("occupied");
+        fs::create_dir(&occupied).unwrap();
+        fs::write(occupied.join("inside"), "keep").unwrap();
+        assert!(write_atomic(&occupied, b"replacement", &SaveOptions::default()).is_err());
+        assert_eq!(fs::read(occupied.join("inside")).unwrap(), b"keep");
+        assert_eq!(entries(&dir), ["data.txt", "data.txt~", "occupied"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
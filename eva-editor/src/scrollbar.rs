@@ -0,0 +1,64 @@
+use crate::primitives::{PrimitiveRenderer, RectStyle};
+use crate::theme::Theme;
+
+// 滑块最短是滚动条宽度的这么多倍，长文件里也容易抓住
+const MIN_THUMB_RATIO: f32 = 2.0;
+
+// 竖直滚动条的几何，每帧按行数和视口高度重新计算
+pub struct ScrollBar {
+    pub track: [f32; 4],
+    pub thumb: [f32; 4],
+    // scroll_line 的上限
+    max_scroll: f32,
+}
+
+impl ScrollBar {
+    // 最后一行可以滚到视口顶部，所以总高度是 (行数 - 1) 行再加一屏
+    pub fn layout(track: [f32; 4], scroll_line: f32, line_count: usize, visible_lines: f32) -> Self {
+        let max_scroll = line_count.saturating_sub(1) as f32;
+        let track_height = track[3] - track[1];
+        let total = max_scroll + visible_lines.max(1.0);
+        let min_thumb = ((track[2] - track[0]) * MIN_THUMB_RATIO).min(track_height);
+        let thumb_height = (track_height * visible_lines / total).clamp(min_thumb, track_height);
+        let travel = track_height - thumb_height;
+        let fraction = if max_scroll > 0.0 { (scroll_line / max_scroll).clamp(0.0, 1.0) } else { 0.0 };
+        let thumb_top = track[1] + travel * fraction;
+        ScrollBar { track, thumb: [track[0], thumb_top, track[2], thumb_top + thumb_height], max_scroll }
+    }
+
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.track[0] && x < self.track[2] && y >= self.track[1] && y < self.track[3]
+    }
+
+    pub fn thumb_contains(&self, y: f32) -> bool {
+        y >= self.thumb[1] && y < self.thumb[3]
+    }
+
+    // 滑块顶边放在 thumb_top 时对应的 scroll_line
+    pub fn scroll_for_thumb(&self, thumb_top: f32) -> f32 {
+        let travel = (self.track[3] - self.track[1]) - (self.thumb[3] - self.thumb[1]);
+        if travel <= 0.0 {
+            return 0.0;
+        }
+        ((thumb_top - self.track[1]) / travel).clamp(0.0, 1.0) * self.max_scroll
+    }
+
+    pub fn render(&self, theme: &Theme, dragging: bool, scale: f32, primitives: &mut PrimitiveRenderer) {
+        let colors = &theme.colors;
+        let mut track = colors.grid;
+        track[3] *= 0.3;
+        primitives.fill_rect(self.track, track);
+        primitives.fill_rect([self.track[0], self.track[1], self.track[0] + 1.0, self.track[3]], colors.grid);
+
+        // 拖动时滑块换成实色
+        let mut thumb_color = colors.accent;
+        if !dragging {
+            thumb_color[3] = 0.55;
+        }
+        let inset = (2.0 * scale).round();
+        let [x0, y0, x1, y1] = self.thumb;
+        let rect = [x0 + inset, y0 + inset, x1 - inset, y1 - inset];
+        let radius = (rect[2] - rect[0]) / 2.0;
+        primitives.draw_rect(rect, &RectStyle::solid(thumb_color).rounded(radius));
+    }
+}
@@ -0,0 +1,101 @@
+// 从灰度覆盖率位图生成有符号距离场（Felzenszwalb–Huttenlocher 欧氏距离变换）
+
+const INF: f64 = 1e20;
+
+// 输入 width x height 的覆盖率位图，输出四周各扩出 spread 像素的距离场。
+// 编码：0.5 为字形边缘，内部大于 0.5，1.0 和 0.0 分别对应内外 spread 像素处
+pub fn coverage_to_sdf(coverage: &[u8], width: usize, height: usize, spread: usize) -> (Vec<u8>, usize, usize) {
+    let out_w = width + spread * 2;
+    let out_h = height + spread * 2;
+    let mut outer = vec![INF; out_w * out_h];
+    let mut inner = vec![0.0; out_w * out_h];
+
+    for y in 0..height {
+        for x in 0..width {
+            let a = coverage[y * width + x] as f64 / 255.0;
+            let i = (y + spread) * out_w + x + spread;
+            if a >= 1.0 {
+                outer[i] = 0.0;
+                inner[i] = INF;
+            } else if a > 0.0 {
+                // 半覆盖像素按覆盖率估计到边缘的亚像素距离
+                outer[i] = (0.5 - a).max(0.0).powi(2);
+                inner[i] = (a - 0.5).max(0.0).powi(2);
+            }
+        }
+    }
+
+    edt(&mut outer, out_w, out_h);
+    edt(&mut inner, out_w, out_h);
+
+    let pixels = outer
+        .iter()
+        .zip(&inner)
+        .map(|(o, i)| {
+            let distance = o.sqrt() - i.sqrt();
+            let value = 0.5 - distance / (2.0 * spread as f64);
+            (value.clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+        .collect();
+    (pixels, out_w, out_h)
+}
+
+// 二维平方距离变换：先按列再按行做一维变换
+fn edt(grid: &mut [f64], width: usize, height: usize) {
+    let n = width.max(height);
+    let mut f = vec![0.0; n];
+    let mut d = vec![0.0; n];
+    let mut v = vec![0usize; n];
+    let mut z = vec![0.0; n + 1];
+
+    for x in 0..width {
+        for y in 0..height {
+            f[y] = grid[y * width + x];
+        }
+        edt_1d(&f[..height], &mut d, &mut v, &mut z);
+        for y in 0..height {
+            grid[y * width + x] = d[y];
+        }
+    }
+    for y in 0..height {
+        f[..width].copy_from_slice(&grid[y * width..(y + 1) * width]);
+        edt_1d(&f[..width], &mut d, &mut v, &mut z);
+        grid[y * width..(y + 1) * width].copy_from_slice(&d[..width]);
+    }
+}
+
+fn edt_1d(f: &[f64], d: &mut [f64], v: &mut [usize], z: &mut [f64]) {
+    let n = f.len();
+    let mut k = 0;
+    v[0] = 0;
+    z[0] = -INF;
+    z[1] = INF;
+
+    for q in 1..n {
+        // z[0] = -INF 保证 k 不会减到 0 以下
+        let mut s;
+        loop {
+            let r = v[k];
+            s = ((f[q] + (q * q) as f64) - (f[r] + (r * r) as f64)) / (2.0 * (q as f64 - r as f64));
+            if s <= z[k] {
+                k -= 1;
+            } else {
+                break;
+            }
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = INF;
+    }
+
+    k = 0;
+    for (q, out) in d.iter_mut().enumerate().take(n) {
+        while z[k + 1] < q as f64 {
+            k += 1;
+        }
+        let r = v[k];
+        let dq = q as f64 - r as f64;
+        *out = dq * dq + f[r];
+    }
+}
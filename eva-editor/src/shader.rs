@@ -9,6 +9,15 @@ pub struct Shader {
 
 impl Shader {
     pub fn new(vertex_source: &str, fragment_source: &str) -> Result<Shader> {
+        Shader::with_attributes(vertex_source, fragment_source, &[])
+    }
+
+    // 链接前把顶点属性绑定到固定位置（GLSL 1.20 没有 layout(location)）
+    pub fn with_attributes(
+        vertex_source: &str,
+        fragment_source: &str,
+        attributes: &[(u32, &str)],
+    ) -> Result<Shader> {
         info!("Creating shader program...");
         let program = unsafe { gl::CreateProgram() };
         if program == 0 {
@@ -35,6 +44,13 @@ impl Shader {
             gl::AttachShader(program, fragment_shader);
         }
 
+        for (location, name) in attributes {
+            let name_c_str = CString::new(*name).context("Attribute name contains null byte")?;
+            unsafe {
+                gl::BindAttribLocation(program, *location, name_c_str.as_ptr());
+            }
+        }
+
         info!("Linking shader program...");
         unsafe {
             gl::LinkProgram(program);
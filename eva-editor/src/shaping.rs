@@ -0,0 +1,149 @@
+use std::ops::Range;
+use std::str::FromStr;
+use log::warn;
+use rustybuzz::{Feature, UnicodeBuffer};
+
+// 整形后的一个字形，坐标单位为像素，x 相对行首
+#[derive(Clone, Copy, Debug)]
+pub struct ShapedGlyph {
+    pub face: usize,
+    pub glyph_id: u32,
+    pub cluster: usize, // 对应文本中的字节偏移
+    pub x: f32,
+    pub y: f32,
+    pub advance: f32,
+}
+
+// 一行整形结果，carets[i] 是第 i 个字符左边缘的 x，最后一项是行宽
+#[derive(Debug, Default)]
+pub struct ShapedLine {
+    pub glyphs: Vec<ShapedGlyph>,
+    pub carets: Vec<f32>,
+    pub width: f32,
+}
+
+impl ShapedLine {
+    // 第 column 个字符（按 char 计）左边缘的 x，超出行尾时返回行宽
+    pub fn caret_x(&self, column: usize) -> f32 {
+        self.carets.get(column).copied().unwrap_or(self.width)
+    }
+
+    // 点击命中测试：返回离 x 最近的字符边界（按 char 计的列）
+    pub fn hit_test(&self, x: f32) -> usize {
+        let last = self.carets.len().saturating_sub(1);
+        (0..last)
+            .find(|&i| x < (self.carets[i] + self.carets[i + 1]) / 2.0)
+            .unwrap_or(last)
+    }
+}
+
+// 解析 FontConfig.features，格式同 harfbuzz："liga"、"-calt"、"ss01=1"
+pub fn parse_features(features: &[String]) -> Vec<Feature> {
+    features
+        .iter()
+        .filter_map(|s| match Feature::from_str(s) {
+            Ok(feature) => Some(feature),
+            Err(_) => {
+                warn!("Ignoring invalid OpenType feature '{}'", s);
+                None
+            }
+        })
+        .collect()
+}
+
+// 组合附加符、变体选择符、ZWJ 和肤色修饰符要和前一个字符留在同一个字体里整形
+pub fn is_cluster_continuation(c: char) -> bool {
+    matches!(c as u32,
+        0x0300..=0x036F
+        | 0x1AB0..=0x1AFF
+        | 0x1DC0..=0x1DFF
+        | 0x200C..=0x200D
+        | 0x20D0..=0x20FF
+        | 0xFE00..=0xFE0F
+        | 0xFE20..=0xFE2F
+        | 0x1F3FB..=0x1F3FF
+        | 0xE0020..=0xE007F
+        | 0xE0100..=0xE01EF)
+}
+
+// 用一个字体整形 text[range]，字形追加到 out，返回整形后的 pen_x
+#[allow(clippy::too_many_arguments)]
+pub fn shape_run(
+    font_data: &[u8],
+    face_index: u32,
+    face: usize,
+    px_per_unit: f32,
+    features: &[Feature],
+    text: &str,
+    range: Range<usize>,
+    pen_x: f32,
+    out: &mut Vec<ShapedGlyph>,
+) -> f32 {
+    let hb_face = match rustybuzz::Face::from_slice(font_data, face_index) {
+        Some(hb_face) => hb_face,
+        None => {
+            warn!("Failed to parse font for shaping");
+            return pen_x;
+        }
+    };
+
+    let mut buffer = UnicodeBuffer::new();
+    for (offset, c) in text[range.clone()].char_indices() {
+        buffer.add(c, (range.start + offset) as u32);
+    }
+    let glyphs = rustybuzz::shape(&hb_face, features, buffer);
+
+    let mut x = pen_x;
+    for (info, pos) in glyphs.glyph_infos().iter().zip(glyphs.glyph_positions()) {
+        let advance = pos.x_advance as f32 * px_per_unit;
+        out.push(ShapedGlyph {
+            face,
+            glyph_id: info.glyph_id,
+            cluster: info.cluster as usize,
+            x: x + pos.x_offset as f32 * px_per_unit,
+            y: pos.y_offset as f32 * px_per_unit,
+            advance,
+        });
+        x += advance;
+    }
+    x
+}
+
+// 按簇把宽度分给簇内的每个字符，连字内部也能逐字符放光标
+pub fn compute_carets(text: &str, glyphs: &[ShapedGlyph], width: f32) -> Vec<f32> {
+    // 每个簇的起始字节、起始 x 和总宽度；同一簇的字形在整形结果中是连续的
+    let mut clusters: Vec<(usize, f32, f32)> = Vec::new();
+    for g in glyphs {
+        match clusters.last_mut() {
+            Some((start, x, advance)) if *start == g.cluster => {
+                *x = x.min(g.x);
+                *advance += g.advance;
+            }
+            _ => clusters.push((g.cluster, g.x, g.advance)),
+        }
+    }
+    clusters.sort_by_key(|&(start, _, _)| start);
+
+    let char_offsets: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+    let mut carets = Vec::with_capacity(char_offsets.len() + 1);
+    let mut next_char = 0;
+    for (i, &(start, x, advance)) in clusters.iter().enumerate() {
+        let end = clusters.get(i + 1).map_or(text.len(), |&(next, _, _)| next);
+        // 整形器丢掉的字符（极少见）放在下一个簇的起点
+        while next_char < char_offsets.len() && char_offsets[next_char] < start {
+            carets.push(x);
+            next_char += 1;
+        }
+        let first = next_char;
+        while next_char < char_offsets.len() && char_offsets[next_char] < end {
+            next_char += 1;
+        }
+        let count = next_char - first;
+        for k in 0..count {
+            carets.push(x + advance * k as f32 / count as f32);
+        }
+    }
+    carets.resize(char_offsets.len(), width);
+    carets.push(width);
+    carets
+}
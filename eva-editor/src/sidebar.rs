@@ -0,0 +1,67 @@
+use crate::editor::Editor;
+use crate::font::{FontRenderer, TextSpan, TextStyle};
+use crate::primitives::PrimitiveRenderer;
+use crate::tabs::tab_labels;
+use crate::theme::Theme;
+use crate::ui::{pop_clip, push_clip};
+
+// 侧栏：打开的缓冲区一行一个，活动的那个高亮，有未保存修改的名字前面加圆点。
+// area 是侧栏去掉留白后的内容区
+pub fn render(
+    editor: &Editor,
+    area: [f32; 4],
+    theme: &Theme,
+    primitives: &mut PrimitiveRenderer,
+    font_renderer: &mut FontRenderer,
+) {
+    let colors = &theme.colors;
+    let [x0, y0, x1, y1] = area;
+    let line_height = font_renderer.line_height();
+    push_clip(primitives, font_renderer, area);
+    for (index, label) in tab_labels(&editor.buffers).into_iter().enumerate() {
+        let y = y0 + index as f32 * line_height;
+        if y >= y1 {
+            break;
+        }
+        let buffer = &editor.buffers[index];
+        if index == editor.active_buffer {
+            primitives.fill_rect([x0, y, x1, y + line_height], colors.selection);
+        }
+        let text = if buffer.modified { format!("● {}", label) } else { label };
+        let color = if buffer.modified { colors.warning } else { colors.foreground };
+        let span = TextSpan { range: 0..text.len(), color, style: TextStyle::default() };
+        font_renderer.render_spans(&text, &[span], x0, y, 1.0);
+    }
+    pop_clip(primitives, font_renderer);
+}
+
+// 点中的那一行对应的缓冲区下标
+pub fn row_at(editor: &Editor, area: [f32; 4], line_height: f32, y: f32) -> Option<usize> {
+    if y < area[1] || y >= area[3] || line_height <= 0.0 {
+        return None;
+    }
+    let row = ((y - area[1]) / line_height) as usize;
+    (row < editor.buffers.len()).then_some(row)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::Buffer;
+
+    #[test]
+    fn rows_map_to_buffers() {
+        let mut editor = Editor::new();
+        for content in ["a", "b", "c"] {
+            editor.open_buffer(Buffer::from_content(content.to_string(), None));
+        }
+        let area = [0.0, 100.0, 200.0, 400.0];
+        assert_eq!(row_at(&editor, area, 20.0, 100.0), Some(0));
+        assert_eq!(row_at(&editor, area, 20.0, 139.0), Some(1));
+        assert_eq!(row_at(&editor, area, 20.0, 159.9), Some(2));
+        // 最后一个缓冲区下面的空白、内容区外面都不算
+        assert_eq!(row_at(&editor, area, 20.0, 160.0), None);
+        assert_eq!(row_at(&editor, area, 20.0, 99.0), None);
+        assert_eq!(row_at(&editor, [0.0, 100.0, 200.0, 130.0], 20.0, 135.0), None);
+    }
+}
@@ -0,0 +1,297 @@
+use crate::editor::Editor;
+use crate::font::{FontRenderer, TextSpan, TextStyle};
+use crate::primitives::{Fill, PrimitiveRenderer, RectStyle};
+use crate::theme::Theme;
+use crate::watcher::DiskStatus;
+
+// 段落靠哪一侧排列
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SegmentAlign {
+    Left,
+    Right,
+}
+
+// 状态栏里的一段文字。priority 越小越靠近所在一侧的边缘；
+// 内置段落占用 0、10、20…，其他模块插在它们之间
+#[derive(Clone, Debug)]
+pub struct StatusSegment {
+    pub id: String,
+    pub text: String,
+    pub align: SegmentAlign,
+    pub priority: i32,
+    // 不指定时用前景色
+    pub color: Option<[f32; 4]>,
+    // 指定时画成一块色块，文字自动换成背景色
+    pub background: Option<[f32; 4]>,
+}
+
+impl StatusSegment {
+    pub fn new(id: impl Into<String>, text: impl Into<String>, align: SegmentAlign, priority: i32) -> Self {
+        StatusSegment { id: id.into(), text: text.into(), align, priority, color: None, background: None }
+    }
+
+    pub fn color(mut self, color: [f32; 4]) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn background(mut self, background: [f32; 4]) -> Self {
+        self.background = Some(background);
+        self
+    }
+}
+
+// 窗口底部的状态栏：内置段落每帧从编辑器状态生成，其他模块的段落按 id 登记
+#[derive(Default)]
+pub struct StatusBar {
+    segments: Vec<StatusSegment>,
+}
+
+impl StatusBar {
+    // 同 id 的段落会被替换
+    pub fn set_segment(&mut self, segment: StatusSegment) {
+        match self.segments.iter_mut().find(|s| s.id == segment.id) {
+            Some(existing) => *existing = segment,
+            None => self.segments.push(segment),
+        }
+    }
+
+    pub fn remove_segment(&mut self, id: &str) {
+        self.segments.retain(|s| s.id != id);
+    }
+
+    // area 是 [x0, y0, x1, y1]，高度由 Metrics.status_bar_height 决定
+    pub fn render(
+        &self,
+        editor: &Editor,
+        area: [f32; 4],
+        theme: &Theme,
+        scale: f32,
+        primitives: &mut PrimitiveRenderer,
+        font_renderer: &mut FontRenderer,
+    ) {
+        let colors = &theme.colors;
+        let [x0, y0, x1, y1] = area;
+        if y1 <= y0 {
+            return;
+        }
+
+        // 紫色从左侧渐隐到底色，顶边一条强调色细线
+        let mut tint = colors.accent;
+        tint[3] = 0.35;
+        let fill = Fill::Horizontal(tint, colors.background);
+        primitives.draw_rect(area, &RectStyle { fill, ..RectStyle::solid(colors.background) });
+        primitives.fill_rect([x0, y0, x1, y0 + scale.round().max(1.0)], colors.accent);
+
+        let padding = (8.0 * scale).round();
+        let line_height = font_renderer.line_height();
+        let text_y = (y0 + (y1 - y0 - line_height) / 2.0).round();
+
+        for (segment, start, width) in self.layout(editor, area, theme, scale, font_renderer) {
+            let spans = segment_spans(&segment, theme);
+            match segment.background {
+                Some(background) => primitives.fill_rect([start, y0, start + width, y1], background),
+                None if segment.align == SegmentAlign::Right => {
+                    primitives.fill_rect([start, y0 + padding / 2.0, start + 1.0, y1 - padding / 2.0], colors.grid);
+                }
+                None => {}
+            }
+            font_renderer.render_spans(&segment.text, &spans, (start + padding).round(), text_y, 1.0);
+        }
+    }
+
+    // 点中的段落的 id
+    pub fn segment_at(
+        &self,
+        editor: &Editor,
+        area: [f32; 4],
+        theme: &Theme,
+        scale: f32,
+        font_renderer: &mut FontRenderer,
+        x: f32,
+    ) -> Option<String> {
+        self.layout(editor, area, theme, scale, font_renderer)
+            .into_iter()
+            .find(|(_, start, width)| x >= *start && x < start + width)
+            .map(|(segment, _, _)| segment.id)
+    }
+
+    // 排好的段落和各自的起点、宽度
+    fn layout(
+        &self,
+        editor: &Editor,
+        area: [f32; 4],
+        theme: &Theme,
+        scale: f32,
+        font_renderer: &mut FontRenderer,
+    ) -> Vec<(StatusSegment, f32, f32)> {
+        let mut segments = self.builtin_segments(editor, theme);
+        segments.extend(self.segments.iter().cloned());
+        let padding = (8.0 * scale).round();
+        place_segments(segments, area, |segment| {
+            font_renderer.measure_line(&segment.text, &segment_spans(segment, theme)).width + padding * 2.0
+        })
+    }
+
+    // 文件名和修改、只读、磁盘上的状态，输入模式靠左；光标位置、选区、缩进、换行符、编码和语法靠右
+    fn builtin_segments(&self, editor: &Editor, theme: &Theme) -> Vec<StatusSegment> {
+        let colors = &theme.colors;
+        let mode = if editor.overwrite { "OVR" } else { "INS" };
+        let mut segments = vec![StatusSegment::new("mode", mode, SegmentAlign::Left, 0).background(colors.warning)];
+
+        let Some(buffer) = editor.get_active_buffer() else {
+            return segments;
+        };
+        let name = buffer
+            .file_path
+            .as_ref()
+            .map_or_else(|| "untitled".to_string(), |path| path.display().to_string());
+        segments.push(StatusSegment::new("file", name, SegmentAlign::Left, 10));
+        if buffer.modified {
+            segments.push(StatusSegment::new("modified", "●", SegmentAlign::Left, 11).color(colors.warning));
+        }
+        if buffer.readonly {
+            segments.push(StatusSegment::new("readonly", "RO", SegmentAlign::Left, 12).color(colors.error));
+        }
+        match buffer.disk_status {
+            DiskStatus::Synced => {}
+            DiskStatus::Changed => {
+                segments.push(StatusSegment::new("disk", "Changed on disk", SegmentAlign::Left, 13).color(colors.warning))
+            }
+            DiskStatus::Deleted => {
+                segments.push(StatusSegment::new("disk", "Deleted on disk", SegmentAlign::Left, 13).color(colors.error))
+            }
+        }
+
+        let cursor = &editor.cursor;
+        let line_start = cursor.position - cursor.column;
+        let column = buffer.content[line_start..cursor.position].chars().count() + 1;
+        let position = format!("Ln {}, Col {}", cursor.line + 1, column);
+        segments.push(StatusSegment::new("position", position, SegmentAlign::Right, 0).color(colors.accent));
+        if let Some(selection) = editor.selection() {
+            let count = buffer.content[selection].chars().count();
+            let text = format!("{} selected", count);
+            segments.push(StatusSegment::new("selection", text, SegmentAlign::Right, 10).color(colors.warning));
+        }
+
+        let indentation = if buffer.indentation.use_tabs {
+            format!("Tab Size: {}", buffer.indentation.width)
+        } else {
+            format!("Spaces: {}", buffer.indentation.width)
+        };
+        segments.push(StatusSegment::new("indentation", indentation, SegmentAlign::Right, 20));
+        segments.push(StatusSegment::new("line_ending", buffer.line_ending.name(), SegmentAlign::Right, 30));
+        segments.push(StatusSegment::new("encoding", buffer.encoding.name(), SegmentAlign::Right, 40));
+        let syntax = buffer.syntax.map_or("Plain Text", |syntax| syntax.name.as_str());
+        segments.push(StatusSegment::new("syntax", syntax, SegmentAlign::Right, 50).color(colors.accent));
+        segments
+    }
+}
+
+// 有底色的段落文字用背景色加粗
+fn segment_spans(segment: &StatusSegment, theme: &Theme) -> [TextSpan; 1] {
+    let colors = &theme.colors;
+    let color = match segment.background {
+        Some(_) => colors.background,
+        None => segment.color.unwrap_or(colors.foreground),
+    };
+    let mut span = TextSpan { range: 0..segment.text.len(), color, style: TextStyle::default() };
+    span.style.bold = segment.background.is_some();
+    [span]
+}
+
+// 按 priority 从两侧往中间排，width 给出每段的宽度；两侧挤到一起时剩下的段落不再排
+fn place_segments(
+    mut segments: Vec<StatusSegment>,
+    area: [f32; 4],
+    mut width: impl FnMut(&StatusSegment) -> f32,
+) -> Vec<(StatusSegment, f32, f32)> {
+    segments.sort_by_key(|s| s.priority);
+    let mut left = area[0];
+    let mut right = area[2];
+    let mut placed = Vec::new();
+    for segment in segments.into_iter().filter(|s| !s.text.is_empty()) {
+        let width = width(&segment);
+        let start = match segment.align {
+            SegmentAlign::Left => {
+                left += width;
+                left - width
+            }
+            SegmentAlign::Right => {
+                right -= width;
+                right
+            }
+        };
+        if left > right {
+            break;
+        }
+        placed.push((segment, start, width));
+    }
+    placed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(id: &str, align: SegmentAlign, priority: i32) -> StatusSegment {
+        StatusSegment::new(id, id, align, priority)
+    }
+
+    // 每段宽度是文字长度的 10 倍
+    fn placed(segments: Vec<StatusSegment>, width: f32) -> Vec<(String, f32, f32)> {
+        place_segments(segments, [0.0, 0.0, width, 20.0], |s| s.text.len() as f32 * 10.0)
+            .into_iter()
+            .map(|(segment, start, width)| (segment.id, start, width))
+            .collect()
+    }
+
+    #[test]
+    fn segments_line_up_by_priority_on_each_side() {
+        let segments = vec![
+            segment("bb", SegmentAlign::Left, 10),
+            segment("a", SegmentAlign::Left, 0),
+            segment("zz", SegmentAlign::Right, 5),
+            segment("y", SegmentAlign::Right, 0),
+            StatusSegment::new("empty", "", SegmentAlign::Left, 1),
+        ];
+        assert_eq!(
+            placed(segments, 200.0),
+            [
+                ("a".to_string(), 0.0, 10.0),
+                ("y".to_string(), 190.0, 10.0),
+                ("zz".to_string(), 170.0, 20.0),
+                ("bb".to_string(), 10.0, 20.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn placing_stops_where_the_sides_meet() {
+        let segments = vec![
+            segment("aaaa", SegmentAlign::Left, 0),
+            segment("bbbb", SegmentAlign::Right, 0),
+            segment("cc", SegmentAlign::Left, 10),
+            segment("d", SegmentAlign::Right, 20),
+        ];
+        // 第三段放进去就和右侧重叠，它和后面的都不排
+        let ids: Vec<String> = placed(segments.clone(), 90.0).into_iter().map(|(id, _, _)| id).collect();
+        assert_eq!(ids, ["aaaa", "bbbb"]);
+        // 刚好挤满时还放得下
+        let ids: Vec<String> = placed(segments, 110.0).into_iter().map(|(id, _, _)| id).collect();
+        assert_eq!(ids, ["aaaa", "bbbb", "cc", "d"]);
+    }
+
+    #[test]
+    fn same_id_replaces_the_segment() {
+        let mut bar = StatusBar::default();
+        bar.set_segment(segment("zoom", SegmentAlign::Right, 25));
+        bar.set_segment(segment("git", SegmentAlign::Left, 15));
+        bar.set_segment(StatusSegment::new("zoom", "150%", SegmentAlign::Right, 25));
+        let texts: Vec<&str> = bar.segments.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, ["150%", "git"]);
+        bar.remove_segment("zoom");
+        bar.remove_segment("missing");
+        assert_eq!(bar.segments.len(), 1);
+    }
+}
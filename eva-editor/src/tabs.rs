@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::path::{Component, Path};
+use crate::editor::{Buffer, Editor};
+use crate::font::{FontRenderer, TextSpan, TextStyle};
+use crate::primitives::{Fill, PrimitiveRenderer, RectStyle};
+use crate::theme::Theme;
+use crate::ui::{pop_clip, push_clip};
+
+// 标签页的位置（物理像素，已计入滚动）和显示的名字
+struct TabLayout {
+    index: usize,
+    x0: f32,
+    x1: f32,
+    label: String,
+}
+
+// 顶部标签栏：固定的标签页靠左不动，其余的超出宽度时可以横向滚动
+#[derive(Default)]
+pub struct TabBar {
+    scroll: f32,
+    // 上一帧的活动缓冲区，切换后把新的活动标签页滚进视野
+    active_id: Option<u64>,
+}
+
+impl TabBar {
+    pub fn render(
+        &mut self,
+        editor: &Editor,
+        area: [f32; 4],
+        theme: &Theme,
+        scale: f32,
+        primitives: &mut PrimitiveRenderer,
+        font_renderer: &mut FontRenderer,
+    ) {
+        let colors = &theme.colors;
+        let [x0, y0, x1, y1] = area;
+        let background = colors.background;
+        let shade = [background[0] * 0.7, background[1] * 0.7, background[2] * 0.7, 1.0];
+        primitives.fill_rect(area, shade);
+        primitives.fill_rect([x0, y1 - 1.0, x1, y1], colors.grid);
+
+        let (tabs, scroll_start) = self.clamped_layout(editor, area, scale, font_renderer);
+        let active_id = editor.get_active_buffer().map(|b| b.id);
+        if active_id != self.active_id {
+            self.active_id = active_id;
+            if let Some(tab) = tabs.iter().find(|t| t.index == editor.active_buffer && !editor.buffers[t.index].pinned) {
+                if tab.x0 < scroll_start {
+                    self.scroll -= scroll_start - tab.x0;
+                } else if tab.x1 > x1 {
+                    self.scroll += tab.x1 - x1;
+                }
+            }
+        }
+        let (tabs, scroll_start) = self.clamped_layout(editor, area, scale, font_renderer);
+
+        let line_height = font_renderer.line_height();
+        let text_y = (y0 + (y1 - y0 - line_height) / 2.0).round();
+        let padding = (12.0 * scale).round();
+        let dot = (6.0 * scale).round();
+
+        // 固定的标签页先画，滚动区里的裁剪到固定区右侧
+        let mut clipped = false;
+        for tab in &tabs {
+            let buffer = &editor.buffers[tab.index];
+            if !buffer.pinned && !clipped {
+                push_clip(primitives, font_renderer, [scroll_start, y0, x1, y1]);
+                clipped = true;
+            }
+
+            let active = tab.index == editor.active_buffer;
+            if active {
+                // 活动标签页和下面的文字区连成一片，顶边一条强调色
+                primitives.fill_rect([tab.x0, y0, tab.x1, y1], background);
+                primitives.fill_rect([tab.x0, y0, tab.x1, y0 + (2.0 * scale).round()], colors.accent);
+            }
+            if buffer.pinned {
+                primitives.fill_rect([tab.x0, y1 - (2.0 * scale).round(), tab.x1, y1], colors.warning);
+            }
+            primitives.fill_rect([tab.x1 - 1.0, y0 + padding / 2.0, tab.x1, y1 - padding / 2.0], colors.grid);
+
+            let color = if active { colors.foreground } else { theme.syntax.comments };
+            let spans = [TextSpan { range: 0..tab.label.len(), color, style: TextStyle::default() }];
+            font_renderer.render_spans(&tab.label, &spans, tab.x0 + padding, text_y, 1.0);
+            if buffer.modified {
+                let cx = tab.x1 - padding / 2.0 - dot / 2.0;
+                let cy = (y0 + y1) / 2.0;
+                let rect = [cx - dot / 2.0, cy - dot / 2.0, cx + dot / 2.0, cy + dot / 2.0];
+                primitives.draw_rect(rect, &RectStyle::solid(colors.warning).rounded(dot / 2.0));
+            }
+        }
+
+        // 两端还有被滚出去的标签页时画一道渐隐
+        let content_end = tabs.last().map_or(scroll_start, |t| t.x1);
+        let fade = (16.0 * scale).round();
+        let transparent = [shade[0], shade[1], shade[2], 0.0];
+        if self.scroll > 0.0 {
+            let fill = Fill::Horizontal(shade, transparent);
+            primitives.draw_rect([scroll_start, y0, scroll_start + fade, y1], &RectStyle { fill, ..RectStyle::solid(shade) });
+        }
+        if content_end > x1 {
+            let fill = Fill::Horizontal(transparent, shade);
+            primitives.draw_rect([x1 - fade, y0, x1, y1], &RectStyle { fill, ..RectStyle::solid(shade) });
+        }
+        if clipped {
+            pop_clip(primitives, font_renderer);
+        }
+    }
+
+    // 点中的标签页下标
+    pub fn tab_at(&self, editor: &Editor, area: [f32; 4], scale: f32, font_renderer: &mut FontRenderer, x: f32, y: f32) -> Option<usize> {
+        if y < area[1] || y >= area[3] {
+            return None;
+        }
+        let (tabs, scroll_start) = self.layout(editor, area, scale, font_renderer);
+        tabs.iter()
+            .filter(|t| editor.buffers[t.index].pinned || x >= scroll_start)
+            .find(|t| x >= t.x0 && x < t.x1)
+            .map(|t| t.index)
+    }
+
+    // 拖动标签页时，按横坐标找离它最近的位置
+    pub fn drop_target(&self, editor: &Editor, area: [f32; 4], scale: f32, font_renderer: &mut FontRenderer, x: f32) -> Option<usize> {
+        let (tabs, _) = self.layout(editor, area, scale, font_renderer);
+        let last = tabs.last()?;
+        if x >= last.x1 {
+            return Some(last.index);
+        }
+        tabs.iter().find(|t| x < t.x1).map(|t| t.index)
+    }
+
+    pub fn scroll_by(&mut self, delta: f32) {
+        // 下一帧画的时候再夹到有效范围内
+        self.scroll = (self.scroll + delta).max(0.0);
+    }
+
+    // 先把滚动量夹到内容范围内再排版
+    fn clamped_layout(&mut self, editor: &Editor, area: [f32; 4], scale: f32, font_renderer: &mut FontRenderer) -> (Vec<TabLayout>, f32) {
+        let (tabs, scroll_start) = self.layout(editor, area, scale, font_renderer);
+        let content_end = tabs.last().map_or(scroll_start, |t| t.x1) + self.scroll;
+        let max_scroll = (content_end - area[2]).max(0.0);
+        if self.scroll > max_scroll || self.scroll < 0.0 {
+            self.scroll = self.scroll.clamp(0.0, max_scroll);
+            return self.layout(editor, area, scale, font_renderer);
+        }
+        (tabs, scroll_start)
+    }
+
+    // 返回所有标签页的位置，以及滚动区的起点（固定标签页的右边）
+    fn layout(&self, editor: &Editor, area: [f32; 4], scale: f32, font_renderer: &mut FontRenderer) -> (Vec<TabLayout>, f32) {
+        let padding = (12.0 * scale).round();
+        let dot = (6.0 * scale).round();
+        let labels = tab_labels(&editor.buffers);
+        let mut x = area[0];
+        let mut scroll_start = None;
+        let mut tabs = Vec::with_capacity(labels.len());
+
+        // 固定的标签页总是排在最前面
+        for (index, label) in labels.into_iter().enumerate() {
+            if !editor.buffers[index].pinned && scroll_start.is_none() {
+                scroll_start = Some(x);
+                x -= self.scroll;
+            }
+            let spans = [TextSpan { range: 0..label.len(), color: [0.0; 4], style: TextStyle::default() }];
+            // 右侧给修改标记留位置，有没有标记宽度都一样
+            let width = (font_renderer.measure_line(&label, &spans).width + padding * 2.0 + dot).round();
+            tabs.push(TabLayout { index, x0: x, x1: x + width, label });
+            x += width;
+        }
+        (tabs, scroll_start.unwrap_or(x))
+    }
+}
+
+// 标签页显示文件名；重名时往上加父目录，直到能区分开为止
+pub fn tab_labels(buffers: &[Buffer]) -> Vec<String> {
+    let components: Vec<Vec<String>> = buffers
+        .iter()
+        .map(|buffer| match &buffer.file_path {
+            Some(path) => reversed_components(path),
+            None => vec!["untitled".to_string()],
+        })
+        .collect();
+    let mut depths = vec![1; buffers.len()];
+
+    loop {
+        let labels: Vec<String> = components.iter().zip(&depths).map(|(parts, &depth)| label(parts, depth)).collect();
+        let mut groups: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (index, label) in labels.iter().enumerate() {
+            groups.entry(label.as_str()).or_default().push(index);
+        }
+
+        let mut changed = false;
+        for indices in groups.values().filter(|indices| indices.len() > 1) {
+            for &index in indices {
+                if depths[index] < components[index].len() {
+                    depths[index] += 1;
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            return labels;
+        }
+    }
+}
+
+// 文件名在前，依次是各级父目录；根目录不算
+fn reversed_components(path: &Path) -> Vec<String> {
+    path.components()
+        .rev()
+        .filter_map(|part| match part {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+// "main.rs — src/eva"：文件名后面跟着用来区分的父目录
+fn label(parts: &[String], depth: usize) -> String {
+    let name = parts[0].clone();
+    if depth <= 1 {
+        return name;
+    }
+    let parents: Vec<&str> = parts[1..depth].iter().rev().map(String::as_str).collect();
+    format!("{} — {}", name, parents.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn labels(paths: &[Option<&str>]) -> Vec<String> {
+        let buffers: Vec<Buffer> =
+            paths.iter().map(|path| Buffer::from_content(String::new(), path.map(PathBuf::from))).collect();
+        tab_labels(&buffers)
+    }
+
+    #[test]
+    fn same_name_in_different_directories() {
+        assert_eq!(
+            labels(&[Some("/work/eva/src/main.rs"), Some("/work/tools/main.rs"), Some("/work/eva/src/lib.rs")]),
+            ["main.rs — src", "main.rs — tools", "lib.rs"]
+        );
+    }
+
+    #[test]
+    fn shared_parent_names_go_up_another_level() {
+        assert_eq!(
+            labels(&[Some("/work/a/src/main.rs"), Some("/work/b/src/main.rs"), Some("/work/c/lib/main.rs")]),
+            ["main.rs — a/src", "main.rs — b/src", "main.rs — lib"]
+        );
+    }
+
+    #[test]
+    fn untitled_buffers() {
+        assert_eq!(labels(&[None, Some("/work/notes.txt"), None]), ["untitled", "notes.txt", "untitled"]);
+        // 加到根也分不开时停在整条路径上，根目录不显示
+        assert_eq!(labels(&[Some("/a/x.rs"), Some("/a/x.rs")]), ["x.rs — a", "x.rs — a"]);
+    }
+
+    fn order(editor: &Editor) -> Vec<String> {
+        editor
+            .buffers
+            .iter()
+            .map(|b| {
+                let name = b.file_path.as_ref().unwrap().file_name().unwrap().to_string_lossy().into_owned();
+                if b.pinned { format!("{}*", name) } else { name }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pinned_tabs_stay_in_front() {
+        let mut editor = Editor::new();
+        for name in ["a", "b", "c", "d"] {
+            editor.open_file(PathBuf::from("/no/such/dir").join(name)).unwrap();
+        }
+        assert_eq!(order(&editor), ["a", "b", "c", "d"]);
+
+        editor.toggle_pin(2);
+        assert_eq!(order(&editor), ["c*", "a", "b", "d"]);
+        editor.toggle_pin(3);
+        assert_eq!(order(&editor), ["c*", "d*", "a", "b"]);
+        // 未固定的拖不进固定区段，固定的拖不出去
+        assert_eq!(editor.move_buffer(3, 0), 2);
+        assert_eq!(order(&editor), ["c*", "d*", "b", "a"]);
+        assert_eq!(editor.move_buffer(0, 3), 1);
+        assert_eq!(order(&editor), ["d*", "c*", "b", "a"]);
+        // 取消固定后排到未固定区段的开头
+        editor.toggle_pin(0);
+        assert_eq!(order(&editor), ["c*", "d", "b", "a"]);
+        // 活动缓冲区跟着移动
+        assert_eq!(editor.buffers[editor.active_buffer].file_path, Some(PathBuf::from("/no/such/dir/d")));
+    }
+}
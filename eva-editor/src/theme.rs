@@ -8,6 +8,7 @@ pub struct EvaTheme {
     pub success: [f32; 4],
     pub grid: [f32; 4],
     pub selection: [f32; 4],
+    pub cursor: [f32; 4],
 }
 
 impl Default for EvaTheme {
@@ -29,6 +30,8 @@ impl Default for EvaTheme {
             grid: [0.2, 0.8, 0.2, 0.3],
             // 选中文本背景色
             selection: [0.3, 0.0, 0.4, 0.4],
+            // 光标使用警告橙色
+            cursor: [1.0, 0.5, 0.0, 1.0],
         }
     }
 }
@@ -62,11 +65,70 @@ impl Default for SyntaxTheme {
     }
 }
 
+// 字形光栅化方式：小字号用位图最清晰，大字号和特效用距离场
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GlyphRenderMode {
+    Bitmap,
+    Sdf,
+}
+
+// 位图字形的抗锯齿方式。次像素模式要和显示器的子像素排列一致
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Antialiasing {
+    None,
+    Grayscale,
+    SubpixelRgb,
+    SubpixelBgr,
+}
+
+// 字形微调强度
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Hinting {
+    None,
+    Slight,
+    Full,
+}
+
+// 距离场模式下的文字特效，宽度单位为逻辑像素
+#[derive(Clone, Copy)]
+pub struct TextEffects {
+    pub outline_width: f32,
+    pub outline_color: [f32; 4],
+    pub glow_radius: f32,
+    pub glow_color: [f32; 4],
+}
+
+impl Default for TextEffects {
+    fn default() -> Self {
+        TextEffects {
+            outline_width: 0.0,
+            outline_color: [0.0, 0.0, 0.0, 1.0],
+            glow_radius: 0.0,
+            // EVA 橙色辉光
+            glow_color: [1.0, 0.5, 0.0, 0.6],
+        }
+    }
+}
+
 // 字体设置
 pub struct FontConfig {
     pub family: String,
     pub size: f32,
     pub line_height: f32,
+    // 主字体缺字时按顺序查找的字体族
+    pub fallback: Vec<String>,
+    // OpenType 特性开关，harfbuzz 语法，例如 "-liga"、"+calt"、"ss01"
+    pub features: Vec<String>,
+    pub render_mode: GlyphRenderMode,
+    // 距离场模式下，小于这个逻辑字号时仍用位图
+    pub sdf_min_size: f32,
+    pub effects: TextEffects,
+    pub antialiasing: Antialiasing,
+    pub hinting: Hinting,
+    // 按文字亮度校正覆盖率的 gamma，1.0 表示不校正
+    pub gamma: f32,
+    // 覆盖率对比度增强，0.0 表示不增强
+    pub contrast: f32,
 }
 
 impl Default for FontConfig {
@@ -75,6 +137,90 @@ impl Default for FontConfig {
             family: "Source Code Pro".to_string(),
             size: 14.0,
             line_height: 1.5,
+            fallback: vec![
+                "Noto Sans CJK SC".to_string(),
+                "Noto Color Emoji".to_string(),
+                "Noto Sans Symbols 2".to_string(),
+            ],
+            features: vec!["kern".to_string(), "liga".to_string(), "calt".to_string()],
+            render_mode: GlyphRenderMode::Bitmap,
+            sdf_min_size: 20.0,
+            effects: TextEffects::default(),
+            antialiasing: Antialiasing::Grayscale,
+            hinting: Hinting::Slight,
+            gamma: 1.8,
+            contrast: 0.25,
+        }
+    }
+}
+
+// 光标形状，窗口失去焦点时总是画成空心方块
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CursorShape {
+    Block,
+    Beam,
+    Underline,
+    HollowBlock,
+}
+
+// 光标设置
+pub struct CursorConfig {
+    pub shape: CursorShape,
+    pub blink: bool,
+    // 亮、灭各持续的秒数
+    pub blink_interval: f32,
+    // 光标移动时平滑滑过去
+    pub smooth: bool,
+}
+
+impl Default for CursorConfig {
+    fn default() -> Self {
+        CursorConfig {
+            shape: CursorShape::Block,
+            blink: true,
+            blink_interval: 0.53,
+            smooth: true,
+        }
+    }
+}
+
+// 行号显示方式：绝对行号、相对光标行的距离，或当前行绝对其余相对
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    Relative,
+    Hybrid,
+}
+
+// 行号栏设置
+pub struct GutterConfig {
+    pub line_numbers: LineNumbers,
+    // 断点、书签和改动标记道
+    pub show_markers: bool,
+}
+
+impl Default for GutterConfig {
+    fn default() -> Self {
+        GutterConfig {
+            line_numbers: LineNumbers::Absolute,
+            show_markers: true,
+        }
+    }
+}
+
+// 滚动条旁边的缩略图
+pub struct MinimapConfig {
+    pub enabled: bool,
+    // 逻辑像素
+    pub width: f32,
+}
+
+impl Default for MinimapConfig {
+    fn default() -> Self {
+        MinimapConfig {
+            enabled: true,
+            width: 100.0,
         }
     }
 }
@@ -85,6 +231,7 @@ pub struct Metrics {
     pub scroll_bar_width: f32,
     pub tab_height: f32,
     pub status_bar_height: f32,
+    pub sidebar_width: f32,
     pub padding: f32,
 }
 
@@ -95,16 +242,34 @@ impl Default for Metrics {
             scroll_bar_width: 12.0,
             tab_height: 32.0,
             status_bar_height: 24.0,
+            sidebar_width: 240.0,
             padding: 8.0,
         }
     }
 }
 
+impl Metrics {
+    // 逻辑像素换算到物理像素
+    pub fn scaled(&self, scale: f32) -> Metrics {
+        Metrics {
+            line_numbers_width: self.line_numbers_width * scale,
+            scroll_bar_width: self.scroll_bar_width * scale,
+            tab_height: self.tab_height * scale,
+            status_bar_height: self.status_bar_height * scale,
+            sidebar_width: self.sidebar_width * scale,
+            padding: self.padding * scale,
+        }
+    }
+}
+
 // 完整主题配置
 pub struct Theme {
     pub colors: EvaTheme,
     pub syntax: SyntaxTheme,
     pub font: FontConfig,
+    pub cursor: CursorConfig,
+    pub gutter: GutterConfig,
+    pub minimap: MinimapConfig,
     pub metrics: Metrics,
     pub background: (u8, u8, u8),
     pub foreground: (u8, u8, u8),
@@ -116,9 +281,47 @@ impl Default for Theme {
             colors: EvaTheme::default(),
             syntax: SyntaxTheme::default(),
             font: FontConfig::default(),
+            cursor: CursorConfig::default(),
+            gutter: GutterConfig::default(),
+            minimap: MinimapConfig::default(),
             metrics: Metrics::default(),
             background: (30, 30, 40),
             foreground: (220, 220, 220),
         }
     }
-} 
\ No newline at end of file
+} 
+// 内置配色，用 --theme 或配置文件里的 theme 选择
+pub const THEME_PRESETS: [&str; 3] = ["eva-01", "eva-00", "eva-02"];
+
+impl Theme {
+    // 换成内置配色，只改颜色；名字不认识时返回 false
+    pub fn apply_preset(&mut self, name: &str) -> bool {
+        let (colors, syntax) = match name {
+            "eva-01" => (EvaTheme::default(), SyntaxTheme::default()),
+            // 零号机：蓝白
+            "eva-00" => (
+                EvaTheme {
+                    accent: [0.1, 0.35, 0.8, 1.0],
+                    selection: [0.1, 0.25, 0.6, 0.4],
+                    cursor: [0.85, 0.9, 1.0, 1.0],
+                    ..EvaTheme::default()
+                },
+                SyntaxTheme { keywords: [0.35, 0.55, 1.0, 1.0], ..SyntaxTheme::default() },
+            ),
+            // 二号机：红色
+            "eva-02" => (
+                EvaTheme {
+                    accent: [0.75, 0.05, 0.1, 1.0],
+                    selection: [0.5, 0.05, 0.1, 0.4],
+                    cursor: [1.0, 0.75, 0.0, 1.0],
+                    ..EvaTheme::default()
+                },
+                SyntaxTheme { keywords: [0.95, 0.25, 0.25, 1.0], ..SyntaxTheme::default() },
+            ),
+            _ => return false,
+        };
+        self.colors = colors;
+        self.syntax = syntax;
+        true
+    }
+}
@@ -1,8 +1,22 @@
-use crate::editor::Editor;
-use crate::font::FontRenderer;
-use crate::theme::Theme;
-use log::info;
+use crate::editor::{Buffer, Editor};
+use crate::font::{FontRenderer, TextSpan, TextStyle};
+use crate::gutter::Gutter;
+use crate::highlight::Highlighter;
+use crate::layout::{self, Anchor, Axis, Constraint, Insets, Layout, Overlay, Panel, PanelId};
+use crate::minimap::Minimap;
+use crate::primitives::{PrimitiveRenderer, RectStyle};
+use crate::prompt::{Prompt, PromptAction, Reply};
+use crate::scrollbar::ScrollBar;
+use crate::sidebar;
+use crate::status_bar::StatusBar;
+use crate::tabs::TabBar;
+use crate::theme::{CursorShape, Metrics, Theme};
+use crate::view::{Divider, SplitDirection, View};
+use log::{info, warn};
 use anyhow::Result;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::time::Instant;
 use gl::*;
 
 // EVA主题颜色
@@ -10,20 +24,125 @@ pub const EVA_GREEN: [f32; 3] = [0.0, 0.8, 0.2];
 pub const EVA_ORANGE: [f32; 3] = [1.0, 0.5, 0.0];
 pub const EVA_PURPLE: [f32; 3] = [0.5, 0.0, 0.5];
 
+// 光标平滑移动的速度，越大越快跟上（每秒）
+const CARET_ANIMATION_SPEED: f32 = 24.0;
+
+// 滚轮每格让标签栏滚动的距离（逻辑像素）
+const TAB_SCROLL_STEP: f32 = 40.0;
+
+// 窗格之间分隔条的宽度（逻辑像素）
+const DIVIDER_WIDTH: f32 = 4.0;
+
+// 提示框的宽度（逻辑像素）
+const PROMPT_WIDTH: f32 = 520.0;
+
+// 窗口布局里的面板
+pub const TABS: PanelId = "tabs";
+pub const SIDEBAR: PanelId = "sidebar";
+pub const EDITOR: PanelId = "editor";
+pub const STATUS: PanelId = "status";
+// 弹出层
+pub const PROMPT: PanelId = "prompt";
+
+// 正在拖动的控件
+#[derive(Clone)]
+enum Drag {
+    // 抓住滑块时鼠标离滑块顶边的距离
+    Thumb(f32),
+    Minimap,
+    // 分隔条所在分割节点的路径
+    Divider(Vec<bool>),
+}
+
+// 本帧光标怎么画，由 Renderer 根据焦点和闪烁计时决定
+pub struct CursorFrame {
+    pub shape: CursorShape,
+    pub visible: bool,
+    pub smooth: bool,
+}
+
+// 每个窗格自己的高亮缓存和缩略图，几个窗格显示不同缓冲区时不会互相冲掉
+struct PaneCache {
+    highlighter: Highlighter,
+    minimap: Option<Minimap>,
+}
+
+// 一个窗格里各部分的区域（物理像素）
+struct PaneLayout {
+    rect: [f32; 4],
+    // 行号栏加文字，到缩略图或滚动条左边为止
+    text: [f32; 4],
+    minimap: Option<[f32; 4]>,
+    scrollbar: [f32; 4],
+}
+
 pub struct UI {
-    font_renderer: FontRenderer,
+    // 显示器内容缩放，布局用逻辑像素乘以它得到物理像素
+    scale: f32,
+    viewport_width: f32,
+    viewport_height: f32,
+    // 按视图 id 存放
+    panes: HashMap<usize, PaneCache>,
+    // 平滑移动中的光标左上角，以及上一帧的时间
+    caret: Option<[f32; 2]>,
+    last_frame: Instant,
+    status_bar: StatusBar,
+    tab_bar: TabBar,
+    drag: Option<Drag>,
+    layout: Layout,
+    // 排队等选择的提示，显示第一个；以及上一帧画出的按钮
+    prompts: Vec<Prompt>,
+    prompt_buttons: Vec<([f32; 4], PromptAction)>,
+    // 提示框按它算高度：行高（逻辑像素）和留白，换下一个提示时要用
+    prompt_metrics: [f32; 2],
 }
 
 impl UI {
-    pub fn new() -> Result<Self> {
+    pub fn new(theme: &Theme, scale: f32) -> Result<Self> {
         info!("Initializing UiRenderer...");
         
         Ok(Self {
-            font_renderer: FontRenderer::new("/System/Library/Fonts/Menlo.ttc", 14)?,
+            scale,
+            viewport_width: 0.0,
+            viewport_height: 0.0,
+            panes: HashMap::new(),
+            caret: None,
+            last_frame: Instant::now(),
+            status_bar: StatusBar::default(),
+            tab_bar: TabBar::default(),
+            drag: None,
+            layout: window_layout(&theme.metrics),
+            prompts: Vec::new(),
+            prompt_buttons: Vec::new(),
+            prompt_metrics: [0.0, 0.0],
         })
     }
 
-    pub fn render(&mut self, editor: &Editor, theme: &Theme) {
+    // 其他模块通过它往状态栏里登记自己的段落
+    pub fn status_bar_mut(&mut self) -> &mut StatusBar {
+        &mut self.status_bar
+    }
+
+    // 显示或藏起侧栏，编辑区跟着让出或收回地方
+    pub fn toggle_sidebar(&mut self) {
+        let visible = self.layout.is_visible(SIDEBAR);
+        self.layout.set_visible(SIDEBAR, !visible);
+    }
+
+    pub fn render(
+        &mut self,
+        editor: &Editor,
+        theme: &Theme,
+        cursor: &CursorFrame,
+        primitives: &mut PrimitiveRenderer,
+        font_renderer: &mut FontRenderer,
+    ) {
+        font_renderer.begin_frame();
+        let now = Instant::now();
+        let dt = now.duration_since(self.last_frame).as_secs_f32();
+        self.last_frame = now;
+        let mut caret_drawn = false;
+
         unsafe {
             // 设置背景颜色
             gl::ClearColor(
@@ -32,46 +151,713 @@ impl UI {
                 theme.background.2 as f32 / 255.0,
                 1.0
             );
-            
-            // 渲染文本
-            self.font_renderer.render_text(
-                "EVA Editor",
-                10.0,
-                10.0,
-                1.0,
-                &[
-                    theme.foreground.0 as f32 / 255.0,
-                    theme.foreground.1 as f32 / 255.0,
-                    theme.foreground.2 as f32 / 255.0,
-                    1.0,
-                ],
-            );
-            
-            // 渲染编辑器内容
-            if let Some(buffer) = editor.get_active_buffer() {
-                let mut y = 30.0;
-                for line in buffer.content.lines() {
-                    self.font_renderer.render_text(
-                        line,
-                        10.0,
-                        y,
-                        1.0,
-                        &[
-                            theme.foreground.0 as f32 / 255.0,
-                            theme.foreground.1 as f32 / 255.0,
-                            theme.foreground.2 as f32 / 255.0,
-                            1.0,
-                        ],
-                    );
-                    y += 20.0;
+        }
+
+        if let Some(area) = self.layout.rect(TABS) {
+            self.tab_bar.render(editor, area, theme, self.scale, primitives, font_renderer);
+        }
+        if let (Some(area), Some(content)) = (self.layout.rect(SIDEBAR), self.layout.content(SIDEBAR)) {
+            render_panel_frame(area, theme, primitives);
+            sidebar::render(editor, content, theme, primitives, font_renderer);
+        }
+
+        let (panes, dividers) = self.pane_rects(editor);
+        self.panes.retain(|id, _| panes.iter().any(|(pane, _)| pane == id));
+        let focused = editor.panes.focused();
+        for &(id, rect) in &panes {
+            let Some(view) = editor.view(id) else {
+                continue;
+            };
+            let Some(buffer) = editor.buffers.iter().find(|b| b.id == view.buffer_id) else {
+                continue;
+            };
+            // 搜索匹配只属于获得焦点的窗格
+            let matches = if id == focused { editor.search_matches.as_slice() } else { &[] };
+            let frame = (id == focused).then_some(cursor);
+            caret_drawn |= self.render_pane(id, &view, buffer, matches, rect, frame, dt, theme, primitives, font_renderer);
+            if id == focused && panes.len() > 1 {
+                primitives.fill_rect([rect[0], rect[1], rect[2], rect[1] + (2.0 * self.scale).round()], theme.colors.accent);
+            }
+        }
+
+        for divider in &dividers {
+            let dragging = matches!(&self.drag, Some(Drag::Divider(path)) if *path == divider.path);
+            let color = if dragging { theme.colors.accent } else { theme.colors.grid };
+            let [x0, y0, x1, y1] = divider.rect;
+            primitives.fill_rect(divider.rect, theme.colors.background);
+            match divider.direction {
+                SplitDirection::Horizontal => {
+                    let x = ((x0 + x1) / 2.0).floor();
+                    primitives.fill_rect([x, y0, x + 1.0, y1], color);
                 }
+                SplitDirection::Vertical => {
+                    let y = ((y0 + y1) / 2.0).floor();
+                    primitives.fill_rect([x0, y, x1, y + 1.0], color);
+                }
+            }
+        }
+
+        // 光标滚出视口后，下次出现时直接落位而不是从旧位置滑过来
+        if !caret_drawn {
+            self.caret = None;
+        }
+
+        if let Some(area) = self.layout.rect(STATUS) {
+            self.status_bar.render(editor, area, theme, self.scale, primitives, font_renderer);
+        }
+
+        // 弹出层盖在最上面，按 z 从下往上画框，里面的内容由打开它的模块画
+        for &(id, area) in self.layout.overlays() {
+            let shadow = (6.0 * self.scale).round();
+            let style = RectStyle::solid([0.0, 0.0, 0.0, 0.35]).rounded(shadow);
+            primitives.draw_rect([area[0] - shadow, area[1], area[2] + shadow, area[3] + shadow], &style);
+            render_panel_frame(area, theme, primitives);
+            if let (PROMPT, Some(prompt)) = (id, self.prompts.first()) {
+                push_clip(primitives, font_renderer, area);
+                self.prompt_buttons = prompt.render(area, theme, self.scale, primitives, font_renderer);
+                pop_clip(primitives, font_renderer);
             }
         }
+
+        primitives.flush();
+        font_renderer.flush();
+    }
+
+    // 画一个窗格，从 scroll_line 所在的行开始；cursor 只有获得焦点的窗格才有。返回是否画了动画光标
+    #[allow(clippy::too_many_arguments)]
+    fn render_pane(
+        &mut self,
+        id: usize,
+        view: &View,
+        buffer: &Buffer,
+        matches: &[Range<usize>],
+        rect: [f32; 4],
+        cursor: Option<&CursorFrame>,
+        dt: f32,
+        theme: &Theme,
+        primitives: &mut PrimitiveRenderer,
+        font_renderer: &mut FontRenderer,
+    ) -> bool {
+        let metrics = theme.metrics.scaled(self.scale);
+        let layout = self.pane_layout(rect, theme);
+        let line_height = font_renderer.line_height();
+        let [_, text_top, _, text_bottom] = layout.text;
+        let mut caret_drawn = false;
+
+        // 滚到一半的首行不能压到窗格上方的内容上
+        push_clip(primitives, font_renderer, layout.text);
+        let first_line = view.scroll_line.max(0.0).floor() as usize;
+        let mut y = line_top(text_top, &metrics, view.scroll_line, line_height, first_line);
+        let visible_lines = ((text_bottom - y) / line_height).ceil().max(0.0) as usize + 1;
+        let highlighted = self.pane_cache(id, theme).highlighter.highlight(buffer, first_line, visible_lines);
+        let foreground = foreground_color(theme);
+        let selection = view.selection();
+        let gutter = Gutter::layout(buffer, theme, &metrics, font_renderer, self.scale);
+        let (gutter_area, code_area) = split_gutter(layout.text, &gutter, &metrics);
+        let text_x = code_area[0];
+        let first_y = y;
+        let mut last_line = first_line;
+
+        for (i, (line_start, line)) in lines_with_offsets(&buffer.content).skip(first_line).enumerate() {
+            if y > text_bottom {
+                break;
+            }
+            let mut spans = highlighted
+                .as_ref()
+                .and_then(|lines| lines.get(i).cloned())
+                .unwrap_or_else(|| plain_spans(line, foreground));
+            let shaped = font_renderer.measure_line(line, &spans);
+            let line_end = line_start + line.len();
+
+            // 选区在文字下面；跨过行尾时多画一小段表示选中了换行
+            if let Some(selection) = &selection {
+                if selection.start <= line_end && selection.end > line_start {
+                    let start = selection.start.max(line_start) - line_start;
+                    let end = selection.end.min(line_end) - line_start;
+                    let x0 = shaped.caret_x(line[..start].chars().count());
+                    let mut x1 = shaped.caret_x(line[..end].chars().count());
+                    if selection.end > line_end {
+                        x1 += (line_height * 0.3).round();
+                    }
+                    let rect = [text_x + x0, y, text_x + x1, y + line_height];
+                    primitives.fill_rect(rect, theme.colors.selection);
+                }
+            }
+
+            if view.cursor.line == first_line + i {
+                let column = view.cursor.column.min(line.len());
+                let char_index = line[..column].chars().count();
+                let x = (text_x + shaped.caret_x(char_index)).round();
+                // 行尾没有字符时按空格宽度画
+                let width = match line[column..].chars().next() {
+                    Some(_) => shaped.caret_x(char_index + 1) - shaped.caret_x(char_index),
+                    None => font_renderer.measure_line(" ", &plain_spans(" ", foreground)).width,
+                };
+
+                match cursor {
+                    Some(cursor) => {
+                        let [caret_x, caret_y] = self.animate_caret([x, y], dt, cursor.smooth);
+                        caret_drawn = true;
+
+                        if cursor.visible {
+                            let rect = [caret_x, caret_y, caret_x + width.max(1.0), caret_y + line_height];
+                            self.draw_caret(primitives, rect, cursor.shape, theme.colors.cursor);
+                            // 方块光标停稳后，把它盖住的字符反色画出来
+                            let settled = (caret_x - x).abs() < 0.5 && (caret_y - y).abs() < 0.5;
+                            if cursor.shape == CursorShape::Block && settled {
+                                if let Some(c) = line[column..].chars().next() {
+                                    let range = column..column + c.len_utf8();
+                                    spans = recolor(&spans, range, theme.colors.background);
+                                }
+                            }
+                        }
+                    }
+                    // 没有焦点的窗格画一个不闪的空心框
+                    None => {
+                        let rect = [x, y, x + width.max(1.0), y + line_height];
+                        self.draw_caret(primitives, rect, CursorShape::HollowBlock, theme.syntax.comments);
+                    }
+                }
+            }
+
+            font_renderer.render_spans(line, &spans, text_x, y, 1.0);
+            y += line_height;
+            last_line = first_line + i + 1;
+        }
+
+        gutter.render(
+            buffer,
+            view.cursor.line,
+            first_line..last_line,
+            first_y,
+            gutter_area,
+            theme,
+            primitives,
+            font_renderer,
+        );
+        pop_clip(primitives, font_renderer);
+
+        let page = self.visible_lines(theme, rect, line_height);
+        if let Some(area) = layout.minimap {
+            let scale = self.scale;
+            if let Some(minimap) = self.pane_cache(id, theme).minimap.as_mut() {
+                minimap.update(buffer, foreground);
+                minimap.render(view, buffer, matches, area, theme, scale, page, primitives);
+            }
+        }
+        let dragging = cursor.is_some() && matches!(self.drag, Some(Drag::Thumb(_)));
+        let line_count = buffer.content.matches('\n').count() + 1;
+        let scroll_bar = ScrollBar::layout(layout.scrollbar, view.scroll_line, line_count, page);
+        scroll_bar.render(theme, dragging, self.scale, primitives);
+        caret_drawn
+    }
+
+    // 窗格的缓存，第一次用到时创建；缩略图建不出来时只是不画
+    fn pane_cache(&mut self, id: usize, theme: &Theme) -> &mut PaneCache {
+        let (width, height) = (self.viewport_width, self.viewport_height);
+        self.panes.entry(id).or_insert_with(|| {
+            let minimap = match Minimap::new(theme) {
+                Ok(mut minimap) => {
+                    minimap.resize(width.max(1.0) as u32, height.max(1.0) as u32);
+                    Some(minimap)
+                }
+                Err(e) => {
+                    warn!("Failed to create minimap: {}", e);
+                    None
+                }
+            };
+            PaneCache { highlighter: Highlighter::new(theme), minimap }
+        })
+    }
+
+    // 光标向目标位置指数逼近，距离足够小时直接落位
+    fn animate_caret(&mut self, target: [f32; 2], dt: f32, smooth: bool) -> [f32; 2] {
+        let position = match self.caret {
+            Some([x, y]) if smooth => {
+                let t = 1.0 - (-dt * CARET_ANIMATION_SPEED).exp();
+                let (nx, ny) = (x + (target[0] - x) * t, y + (target[1] - y) * t);
+                if (target[0] - nx).abs() < 0.5 && (target[1] - ny).abs() < 0.5 {
+                    target
+                } else {
+                    [nx, ny]
+                }
+            }
+            _ => target,
+        };
+        self.caret = Some(position);
+        position
+    }
+
+    fn draw_caret(&self, primitives: &mut PrimitiveRenderer, rect: [f32; 4], shape: CursorShape, color: [f32; 4]) {
+        let [x0, y0, x1, y1] = rect;
+        let thickness = self.scale.round().max(1.0);
+        match shape {
+            CursorShape::Block => primitives.fill_rect(rect, color),
+            CursorShape::Beam => primitives.fill_rect([x0, y0, x0 + thickness * 2.0, y1], color),
+            CursorShape::Underline => primitives.fill_rect([x0, y1 - thickness * 2.0, x1, y1], color),
+            CursorShape::HollowBlock => primitives.stroke_rect(rect, 0.0, thickness, color),
+        }
+    }
+
+    // 标签栏和状态栏之间留给窗格的区域
+    fn editor_area(&self) -> [f32; 4] {
+        self.layout.rect(EDITOR).unwrap_or_default()
+    }
+
+    fn pane_rects(&self, editor: &Editor) -> (Vec<(usize, [f32; 4])>, Vec<Divider>) {
+        let gap = (DIVIDER_WIDTH * self.scale).round();
+        editor.panes.layout(self.editor_area(), gap)
+    }
+
+    // 滚动条贴着窗格右边，缩略图在它左边；窗格太窄时不放缩略图
+    fn pane_layout(&self, rect: [f32; 4], theme: &Theme) -> PaneLayout {
+        let metrics = theme.metrics.scaled(self.scale);
+        let minimap_width = (theme.minimap.width * self.scale).round();
+        let show_minimap = theme.minimap.enabled && rect[2] - rect[0] > minimap_width + metrics.scroll_bar_width;
+        let mut constraints = vec![Constraint::Fill(1.0), Constraint::Length(metrics.scroll_bar_width)];
+        if show_minimap {
+            constraints.insert(1, Constraint::Length(minimap_width));
+        }
+        let parts = layout::split(rect, Axis::Horizontal, &constraints);
+        PaneLayout {
+            rect,
+            text: parts[0],
+            minimap: show_minimap.then_some(parts[1]),
+            scrollbar: parts[parts.len() - 1],
+        }
+    }
+
+    fn focused_layout(&self, editor: &Editor, theme: &Theme) -> Option<PaneLayout> {
+        let focused = editor.panes.focused();
+        let (panes, _) = self.pane_rects(editor);
+        let &(_, rect) = panes.iter().find(|(id, _)| *id == focused)?;
+        Some(self.pane_layout(rect, theme))
+    }
+
+    // 窗格一屏能放下的行数
+    fn visible_lines(&self, theme: &Theme, rect: [f32; 4], line_height: f32) -> f32 {
+        let padding = theme.metrics.padding * self.scale;
+        ((rect[3] - rect[1] - padding) / line_height).max(1.0)
+    }
+
+    // 坐标所在的窗格
+    pub fn pane_at(&self, editor: &Editor, x: f32, y: f32) -> Option<usize> {
+        let (panes, _) = self.pane_rects(editor);
+        panes
+            .into_iter()
+            .find(|(_, [x0, y0, x1, y1])| x >= *x0 && x < *x1 && y >= *y0 && y < *y1)
+            .map(|(id, _)| id)
+    }
+
+    // 把帧缓冲坐标换算成获得焦点的窗格里的字节偏移，点到文字区外时取最近的位置
+    pub fn hit_test(
+        &mut self,
+        editor: &Editor,
+        theme: &Theme,
+        font_renderer: &mut FontRenderer,
+        x: f32,
+        y: f32,
+    ) -> Option<usize> {
+        let buffer = editor.get_active_buffer()?;
+        let metrics = theme.metrics.scaled(self.scale);
+        if y >= self.editor_area()[3] {
+            return None;
+        }
+        let layout = self.focused_layout(editor, theme)?;
+        let line_height = font_renderer.line_height();
+        let top = line_top(layout.rect[1], &metrics, editor.scroll_line, line_height, 0);
+        let row = ((y - top) / line_height).floor().max(0.0) as usize;
+        let (index, (line_start, line)) = lines_with_offsets(&buffer.content).enumerate().take(row + 1).last()?;
+
+        let spans = self
+            .pane_cache(editor.panes.focused(), theme)
+            .highlighter
+            .highlight(buffer, index, 1)
+            .and_then(|mut lines| lines.pop())
+            .unwrap_or_else(|| plain_spans(line, foreground_color(theme)));
+        let shaped = font_renderer.measure_line(line, &spans);
+        let gutter = Gutter::layout(buffer, theme, &metrics, font_renderer, self.scale);
+        let (_, code_area) = split_gutter(layout.text, &gutter, &metrics);
+        let column = shaped.hit_test(x - code_area[0]);
+        Some(line_start + line.char_indices().nth(column).map_or(line.len(), |(i, _)| i))
+    }
+
+    // 点在获得焦点的窗格的断点道上时返回所在行
+    pub fn gutter_marker_hit(
+        &mut self,
+        editor: &Editor,
+        theme: &Theme,
+        font_renderer: &mut FontRenderer,
+        x: f32,
+        y: f32,
+    ) -> Option<usize> {
+        let buffer = editor.get_active_buffer()?;
+        let metrics = theme.metrics.scaled(self.scale);
+        let layout = self.focused_layout(editor, theme)?;
+        let [_, y0, _, y1] = layout.rect;
+        let gutter = Gutter::layout(buffer, theme, &metrics, font_renderer, self.scale);
+        let (gutter_area, _) = split_gutter(layout.text, &gutter, &metrics);
+        if !gutter.in_marker_lane(x - gutter_area[0]) || y < y0 || y >= y1 {
+            return None;
+        }
+        let line_height = font_renderer.line_height();
+        let top = line_top(y0, &metrics, editor.scroll_line, line_height, 0);
+        let line = ((y - top) / line_height).floor().max(0.0) as usize;
+        (line <= buffer.content.matches('\n').count()).then_some(line)
+    }
+
+    // 点在分隔条上时开始拖动，返回是否处理了
+    pub fn begin_divider_drag(&mut self, editor: &Editor, x: f32, y: f32) -> bool {
+        let (_, dividers) = self.pane_rects(editor);
+        // 分隔条很窄，判定范围两边各放宽一点
+        let slack = (2.0 * self.scale).round();
+        let hit = dividers.into_iter().find(|d| {
+            let [x0, y0, x1, y1] = d.rect;
+            x >= x0 - slack && x < x1 + slack && y >= y0 - slack && y < y1 + slack
+        });
+        match hit {
+            Some(divider) => {
+                self.drag = Some(Drag::Divider(divider.path));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 点在获得焦点的窗格的滚动条或缩略图上时开始拖动并立即滚动，返回是否处理了
+    pub fn begin_scroll_drag(&mut self, editor: &mut Editor, theme: &Theme, font_renderer: &FontRenderer, x: f32, y: f32) -> bool {
+        let Some(layout) = self.focused_layout(editor, theme) else {
+            return false;
+        };
+        let page = self.visible_lines(theme, layout.rect, font_renderer.line_height());
+        let scroll_bar = ScrollBar::layout(layout.scrollbar, editor.scroll_line, editor.line_count(), page);
+        if scroll_bar.contains(x, y) {
+            // 点在滑块外时先让滑块中心跳到鼠标处
+            let grab = if scroll_bar.thumb_contains(y) {
+                y - scroll_bar.thumb[1]
+            } else {
+                let grab = (scroll_bar.thumb[3] - scroll_bar.thumb[1]) / 2.0;
+                editor.set_scroll(scroll_bar.scroll_for_thumb(y - grab));
+                grab
+            };
+            self.drag = Some(Drag::Thumb(grab));
+            return true;
+        }
+        match layout.minimap {
+            Some(area) if x >= area[0] && x < area[2] && y >= area[1] && y < area[3] => {
+                self.drag = Some(Drag::Minimap);
+                self.drag_to(editor, theme, font_renderer, x, y);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // 拖动中返回真
+    pub fn drag_to(&mut self, editor: &mut Editor, theme: &Theme, font_renderer: &FontRenderer, x: f32, y: f32) -> bool {
+        let Some(drag) = self.drag.clone() else {
+            return false;
+        };
+        if let Drag::Divider(path) = drag {
+            let (_, dividers) = self.pane_rects(editor);
+            if let Some(divider) = dividers.iter().find(|d| d.path == path) {
+                let [x0, y0, x1, y1] = divider.area;
+                let ratio = match divider.direction {
+                    SplitDirection::Horizontal => (x - x0) / (x1 - x0).max(1.0),
+                    SplitDirection::Vertical => (y - y0) / (y1 - y0).max(1.0),
+                };
+                editor.panes.set_ratio(&path, ratio);
+            }
+            return true;
+        }
+
+        let Some(layout) = self.focused_layout(editor, theme) else {
+            return true;
+        };
+        let page = self.visible_lines(theme, layout.rect, font_renderer.line_height());
+        match drag {
+            Drag::Thumb(grab) => {
+                let scroll_bar = ScrollBar::layout(layout.scrollbar, editor.scroll_line, editor.line_count(), page);
+                editor.set_scroll(scroll_bar.scroll_for_thumb(y - grab));
+            }
+            // 让鼠标所在的行落在视口中间
+            Drag::Minimap => {
+                if let (Some(area), Some(view)) = (layout.minimap, editor.view(editor.panes.focused())) {
+                    let line = Minimap::line_at(&view, editor.line_count(), area, self.scale, y);
+                    editor.set_scroll(line - page / 2.0);
+                }
+            }
+            Drag::Divider(_) => {}
+        }
+        true
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag = None;
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    // 侧栏里点中的缓冲区
+    pub fn sidebar_row_at(&self, editor: &Editor, font_renderer: &FontRenderer, y: f32) -> Option<usize> {
+        let area = self.layout.content(SIDEBAR)?;
+        sidebar::row_at(editor, area, font_renderer.line_height(), y)
+    }
+
+    // 点中的标签页
+    pub fn tab_at(&self, editor: &Editor, font_renderer: &mut FontRenderer, x: f32, y: f32) -> Option<usize> {
+        let area = self.layout.rect(TABS)?;
+        self.tab_bar.tab_at(editor, area, self.scale, font_renderer, x, y)
+    }
+
+    // 拖动中的标签页应当落到的位置
+    pub fn tab_drop_target(&self, editor: &Editor, font_renderer: &mut FontRenderer, x: f32) -> Option<usize> {
+        let area = self.layout.rect(TABS)?;
+        self.tab_bar.drop_target(editor, area, self.scale, font_renderer, x)
+    }
+
+    // 滚轮在标签栏上时横向滚动标签页
+    pub fn scroll_tabs(&mut self, delta: f32) {
+        self.tab_bar.scroll_by(delta * TAB_SCROLL_STEP * self.scale);
+    }
+
+    // 点中的状态栏段落
+    pub fn status_segment_at(&self, editor: &Editor, theme: &Theme, font_renderer: &mut FontRenderer, x: f32) -> Option<String> {
+        let area = self.layout.rect(STATUS)?;
+        self.status_bar.segment_at(editor, area, theme, self.scale, font_renderer, x)
+    }
+
+    // 坐标处最上面的面板或弹出层
+    pub fn panel_at(&self, x: f32, y: f32) -> Option<PanelId> {
+        self.layout.hit_test(x, y)
+    }
+
+    // 按下鼠标时调用，点中能获得焦点的面板就把键盘焦点给它
+    pub fn focus_at(&mut self, x: f32, y: f32) -> Option<PanelId> {
+        self.layout.focus_at(x, y)
+    }
+
+    pub fn focused_panel(&self) -> Option<PanelId> {
+        self.layout.focused()
+    }
+
+    pub fn focus_next(&mut self, backward: bool) {
+        self.layout.focus_next(backward);
+    }
+
+    // 关掉最上层的弹出层，返回是否关了
+    pub fn dismiss_overlay(&mut self) -> bool {
+        match self.layout.top_overlay() {
+            Some(id) => {
+                self.layout.hide_overlay(id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // 显示提示，已有提示在等时排到后面，拿焦点的提示插到最前面；
+    // line_height 是当前字体的行高（物理像素），决定提示框的高度
+    pub fn show_prompt(&mut self, prompt: Prompt, metrics: &Metrics, line_height: f32) {
+        self.prompt_metrics = [line_height / self.scale, metrics.padding];
+        let existing = self.prompts.iter().position(|p| p.id == prompt.id);
+        match existing {
+            Some(index) if !prompt.takes_focus => self.prompts[index] = prompt,
+            _ => {
+                if let Some(index) = existing {
+                    self.prompts.remove(index);
+                }
+                if prompt.takes_focus {
+                    self.prompts.insert(0, prompt);
+                } else {
+                    self.prompts.push(prompt);
+                }
+            }
+        }
+        self.place_prompt();
+    }
+
+    // 按排在最前面的提示摆弹出层
+    fn place_prompt(&mut self) {
+        let Some(prompt) = self.prompts.first() else {
+            self.layout.hide_overlay(PROMPT);
+            return;
+        };
+        let [line_height, padding] = self.prompt_metrics;
+        self.layout.show_overlay(Overlay {
+            id: PROMPT,
+            anchor: Anchor::Center,
+            size: [PROMPT_WIDTH, prompt.height(line_height, padding)],
+            z: 10,
+            modal: false,
+            takes_focus: prompt.takes_focus,
+        });
+    }
+
+    // 提示获得焦点时按下字符：有输入框时打字，否则选字母对应的选项
+    pub fn answer_prompt(&mut self, key: char) -> Option<Reply> {
+        let action = self.prompts.first_mut()?.key(key)?;
+        Some(self.finish_prompt(action))
+    }
+
+    pub fn prompt_backspace(&mut self) {
+        if let Some(prompt) = self.prompts.first_mut() {
+            prompt.backspace();
+        }
+    }
+
+    // 回车
+    pub fn submit_prompt(&mut self) -> Option<Reply> {
+        let action = self.prompts.first()?.submit()?;
+        Some(self.finish_prompt(action))
+    }
+
+    // 点中提示上的按钮
+    pub fn prompt_choice_at(&mut self, x: f32, y: f32) -> Option<Reply> {
+        let &(_, action) = self
+            .prompt_buttons
+            .iter()
+            .find(|(rect, _)| x >= rect[0] && x < rect[2] && y >= rect[1] && y < rect[3])?;
+        Some(self.finish_prompt(action))
+    }
+
+    // Esc：提示在最上层时取它的 cancel
+    pub fn cancel_prompt(&mut self) -> Option<Reply> {
+        if self.layout.top_overlay() != Some(PROMPT) {
+            return None;
+        }
+        let action = self.prompts.first()?.cancel;
+        Some(self.finish_prompt(action))
+    }
+
+    // 选项会关掉提示时换下一个，没有了就收起弹出层。先收起再摆下一个，
+    // 焦点还给原来的面板，不拿焦点的下一个提示不会接住正在打的字
+    fn finish_prompt(&mut self, action: PromptAction) -> Reply {
+        let input = self.prompts.first().and_then(|p| p.input.clone());
+        if action.closes() && !self.prompts.is_empty() {
+            self.prompts.remove(0);
+            self.prompt_buttons.clear();
+            self.layout.hide_overlay(PROMPT);
+            self.place_prompt();
+        }
+        Reply { action, input }
+    }
+
+    pub fn set_content_scale(&mut self, scale: f32) {
+        self.scale = scale;
+        self.layout.compute([0.0, 0.0, self.viewport_width, self.viewport_height], scale);
     }
 
     pub fn resize(&mut self, width: i32, height: i32) {
+        self.viewport_width = width as f32;
+        self.viewport_height = height as f32;
+        self.layout.compute([0.0, 0.0, self.viewport_width, self.viewport_height], self.scale);
+        for cache in self.panes.values_mut() {
+            if let Some(minimap) = cache.minimap.as_mut() {
+                minimap.resize(width.max(1) as u32, height.max(1) as u32);
+            }
+        }
         unsafe {
             gl::Viewport(0, 0, width, height);
         }
     }
-} 
\ No newline at end of file
+}
+
+// 窗口的面板树：上面标签栏，下面状态栏，中间是侧栏（默认收起）和窗格
+fn window_layout(metrics: &Metrics) -> Layout {
+    let body = Panel::new("body", Constraint::Fill(1.0))
+        .axis(Axis::Horizontal)
+        .child(
+            Panel::new(SIDEBAR, Constraint::Length(metrics.sidebar_width))
+                .padding(Insets::uniform(metrics.padding))
+                .focusable()
+                .hidden(),
+        )
+        .child(Panel::new(EDITOR, Constraint::Fill(1.0)).focusable());
+    Layout::new(
+        Panel::new("window", Constraint::Fill(1.0))
+            .child(Panel::new(TABS, Constraint::Length(metrics.tab_height)))
+            .child(body)
+            .child(Panel::new(STATUS, Constraint::Length(metrics.status_bar_height))),
+    )
+}
+
+// 侧栏和弹出层的底板，内容由各自的模块画在 Layout::content 里
+fn render_panel_frame(area: [f32; 4], theme: &Theme, primitives: &mut PrimitiveRenderer) {
+    let background = theme.colors.background;
+    let shade = [background[0] * 0.85, background[1] * 0.85, background[2] * 0.85, 1.0];
+    primitives.draw_rect(area, &RectStyle::solid(shade).border(1.0, theme.colors.grid));
+}
+
+// 文字区左边是行号栏，文字和行号栏之间再留出 padding
+fn split_gutter(text: [f32; 4], gutter: &Gutter, metrics: &Metrics) -> ([f32; 4], [f32; 4]) {
+    let constraints = [Constraint::Length(gutter.width), Constraint::Length(metrics.padding), Constraint::Fill(1.0)];
+    let parts = layout::split(text, Axis::Horizontal, &constraints);
+    (parts[0], parts[2])
+}
+
+// 换裁剪区域前先把已排队的图形和文字画掉，它们属于外层区域
+pub fn push_clip(primitives: &mut PrimitiveRenderer, font_renderer: &mut FontRenderer, rect: [f32; 4]) {
+    primitives.flush();
+    font_renderer.flush();
+    primitives.push_clip(rect);
+}
+
+pub fn pop_clip(primitives: &mut PrimitiveRenderer, font_renderer: &mut FontRenderer) {
+    primitives.flush();
+    font_renderer.flush();
+    primitives.pop_clip();
+}
+
+// 第 line 行的行顶 y，文字从窗格顶边 top 留出 padding 开始
+fn line_top(top: f32, metrics: &Metrics, scroll_line: f32, line_height: f32, line: usize) -> f32 {
+    top + metrics.padding + (line as f32 - scroll_line) * line_height
+}
+
+// 逐行返回 (行首字节偏移, 不含换行符的内容)；末尾换行后的空行也算一行，光标可以停在那里
+fn lines_with_offsets(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    let trailing = (content.is_empty() || content.ends_with('\n')).then_some((content.len(), ""));
+    content
+        .split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line.trim_end_matches(['\n', '\r'])))
+        })
+        .chain(trailing)
+}
+
+// 把 range 内的文字换成 color，样式不变
+fn recolor(spans: &[TextSpan], range: Range<usize>, color: [f32; 4]) -> Vec<TextSpan> {
+    let mut result = Vec::with_capacity(spans.len() + 2);
+    for span in spans {
+        let (start, end) = (span.range.start, span.range.end);
+        if end <= range.start || start >= range.end {
+            result.push(span.clone());
+            continue;
+        }
+        if start < range.start {
+            result.push(TextSpan { range: start..range.start, ..span.clone() });
+        }
+        result.push(TextSpan { range: start.max(range.start)..end.min(range.end), color, style: span.style });
+        if end > range.end {
+            result.push(TextSpan { range: range.end..end, ..span.clone() });
+        }
+    }
+    result
+}
+
+fn plain_spans(line: &str, color: [f32; 4]) -> Vec<TextSpan> {
+    vec![TextSpan { range: 0..line.len(), color, style: TextStyle::default() }]
+}
+
+fn foreground_color(theme: &Theme) -> [f32; 4] {
+    [
+        theme.foreground.0 as f32 / 255.0,
+        theme.foreground.1 as f32 / 255.0,
+        theme.foreground.2 as f32 / 255.0,
+        1.0,
+    ]
+}
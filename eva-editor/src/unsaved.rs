@@ -0,0 +1,334 @@
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail, Result};
+use log::{error, info};
+use crate::editor::{Buffer, Editor};
+use crate::highlight;
+use crate::prompt::{AfterSave, Prompt, PromptAction};
+use crate::watcher;
+
+// 退出提示里最多列出这么多个文件名，其余的只报个数
+const LISTED_NAMES: usize = 3;
+
+// 保存的结果；要先回答提示（另存为、外部修改冲突、保存失败）时这次关闭或退出就停下，等用户选择
+pub enum Saved {
+    Done,
+    Ask(Prompt),
+}
+
+// 还有未保存的修改时问要不要保存，没有时返回 None，直接退出
+pub fn quit_prompt(editor: &Editor) -> Option<Prompt> {
+    let names: Vec<String> = editor.buffers.iter().filter(|b| b.modified).map(name).collect();
+    if names.is_empty() {
+        return None;
+    }
+    let listed = match names.len() {
+        1 => names[0].clone(),
+        n if n <= LISTED_NAMES => format!("{} and {}", names[..n - 1].join(", "), names[n - 1]),
+        n => format!("{} and {} more", names[..LISTED_NAMES].join(", "), n - LISTED_NAMES),
+    };
+    let prompt = Prompt::new("quit", format!("Save changes to {} before quitting?", listed), PromptAction::Cancel)
+        .focused()
+        .choice('s', "Save all", PromptAction::SaveAll)
+        .choice('d', "Discard", PromptAction::DiscardAll)
+        .choice('c', "Cancel", PromptAction::Cancel);
+    Some(prompt)
+}
+
+pub fn close_prompt(buffer: &Buffer) -> Prompt {
+    let id = buffer.id;
+    Prompt::new(
+        format!("close:{}", id),
+        format!("Save changes to {} before closing it?", name(buffer)),
+        PromptAction::Cancel,
+    )
+    .focused()
+    .choice('s', "Save", PromptAction::SaveAndClose(id))
+    .choice('d', "Don't save", PromptAction::DiscardAndClose(id))
+    .choice('c', "Cancel", PromptAction::Cancel)
+}
+
+// 未命名的缓冲区输入框里先填好工作区（没有时是当前目录）加上分隔符，有路径的填它原来的路径
+pub fn save_as_prompt(editor: &Editor, id: u64, then: AfterSave) -> Prompt {
+    let buffer = editor.index_of(id).map(|index| &editor.buffers[index]);
+    if let Some(path) = buffer.and_then(|b| b.file_path.as_ref()) {
+        let message = format!("Save {} as:", buffer.map(name).unwrap_or_default());
+        return path_prompt(id, then, message, path.display().to_string());
+    }
+    let mut dir = editor
+        .workspace_root
+        .clone()
+        .or_else(|| std::env::current_dir().ok())
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_default();
+    if !dir.is_empty() && !dir.ends_with(std::path::MAIN_SEPARATOR) {
+        dir.push(std::path::MAIN_SEPARATOR);
+    }
+    path_prompt(id, then, "Save the untitled buffer as:".to_string(), dir)
+}
+
+// 保存失败时告诉用户为什么，让他选再试、存到别处、丢掉修改或者停下。
+// 只读的缓冲区再试也没用；单独按 Ctrl+S 时没有接下来要做的事，丢掉修改也就没有意义
+pub fn save_failed_prompt(buffer: &Buffer, problem: &anyhow::Error, then: AfterSave) -> Prompt {
+    let id = buffer.id;
+    let mut prompt = Prompt::new(
+        format!("save-failed:{}", id),
+        format!("Could not save {}: {:#}", name(buffer), problem),
+        PromptAction::Cancel,
+    )
+    .focused();
+    if !buffer.readonly {
+        prompt = prompt.choice('r', "Retry", PromptAction::RetrySave(id, then));
+    }
+    prompt = prompt.choice('a', "Save as", PromptAction::SaveElsewhere(id, then));
+    if then != AfterSave::Nothing {
+        prompt = prompt.choice('d', "Discard", PromptAction::DiscardChanges(id, then));
+    }
+    prompt.choice('c', "Cancel", PromptAction::Cancel)
+}
+
+// 另存为失败后再问一次，保留用户打的路径
+pub fn retry_save_as(id: u64, then: AfterSave, problem: &anyhow::Error, input: String) -> Prompt {
+    path_prompt(id, then, format!("{:#}. Save as:", problem), input)
+}
+
+fn path_prompt(id: u64, then: AfterSave, message: String, input: String) -> Prompt {
+    Prompt::new(format!("save-as:{}", id), message, PromptAction::Cancel)
+        .input(input)
+        .focused()
+        .choice('s', "Save", PromptAction::SaveAs(id, then))
+        .choice('c', "Cancel", PromptAction::Cancel)
+}
+
+// 保存一个缓冲区。没有路径的切过去问另存为，磁盘上的文件被外部改过的切过去问留哪一边
+pub fn save(editor: &mut Editor, index: usize, then: AfterSave) -> Saved {
+    let buffer = &editor.buffers[index];
+    if buffer.readonly {
+        error!("Cannot save {}: the buffer is read-only", name(buffer));
+        editor.switch_to(index);
+        let problem = anyhow!("the buffer is read-only");
+        return Saved::Ask(save_failed_prompt(&editor.buffers[index], &problem, then));
+    }
+    if buffer.file_path.is_none() {
+        let id = buffer.id;
+        editor.switch_to(index);
+        return Saved::Ask(save_as_prompt(editor, id, then));
+    }
+    if watcher::conflict_before_save(editor, index).is_some() {
+        editor.switch_to(index);
+        return Saved::Ask(watcher::conflict_prompt(&editor.buffers[index], then).focused());
+    }
+    match editor.save_buffer(index) {
+        Ok(()) => Saved::Done,
+        Err(e) => {
+            error!("Failed to save {}: {:#}", name(&editor.buffers[index]), e);
+            editor.switch_to(index);
+            Saved::Ask(save_failed_prompt(&editor.buffers[index], &e, then))
+        }
+    }
+}
+
+// 退出前逐个保存，遇到要问的或失败的就停下；回答之后从这里接着存剩下的
+pub fn save_all(editor: &mut Editor) -> Saved {
+    for index in 0..editor.buffers.len() {
+        if !editor.buffers[index].modified {
+            continue;
+        }
+        match save(editor, index, AfterSave::Quit) {
+            Saved::Done => {}
+            other => return other,
+        }
+    }
+    Saved::Done
+}
+
+// 不保存就退出：清掉修改标记，恢复目录里的交换文件也会在退出时删掉
+pub fn discard_all(editor: &mut Editor) {
+    for buffer in editor.buffers.iter_mut().filter(|b| b.modified) {
+        info!("Discarding unsaved changes to {}", name(buffer));
+        buffer.modified = false;
+    }
+}
+
+// 保存失败后选了丢掉：只清这一个缓冲区的修改标记，退出时不再问它
+pub fn discard(editor: &mut Editor, id: u64) {
+    if let Some(index) = editor.index_of(id) {
+        let buffer = &mut editor.buffers[index];
+        info!("Discarding unsaved changes to {}", name(buffer));
+        buffer.modified = false;
+    }
+}
+
+// 存到输入的路径；不覆盖已有的文件。相对路径从工作区算起，~/ 是主目录
+pub fn save_as(editor: &mut Editor, id: u64, input: &str) -> Result<()> {
+    let index = editor.index_of(id).ok_or_else(|| anyhow!("The buffer was closed"))?;
+    let text = input.trim();
+    if text.is_empty() || text.ends_with(std::path::MAIN_SEPARATOR) {
+        bail!("No file name given");
+    }
+    let path = resolve(editor, text);
+    if path.is_dir() {
+        bail!("{} is a directory", path.display());
+    }
+    if path.exists() {
+        bail!("{} already exists", path.display());
+    }
+
+    // 只读打开的缓冲区可以另存一份，存到新路径后就能编辑了；失败时都还原
+    let buffer = &mut editor.buffers[index];
+    let previous = (buffer.file_path.replace(path.clone()), buffer.readonly);
+    buffer.readonly = false;
+    if buffer.syntax.is_none() {
+        buffer.syntax = highlight::detect_syntax(&path, &buffer.content);
+    }
+    if let Err(e) = editor.save_buffer(index) {
+        let buffer = &mut editor.buffers[index];
+        (buffer.file_path, buffer.readonly) = previous;
+        return Err(e);
+    }
+    Ok(())
+}
+
+fn resolve(editor: &Editor, text: &str) -> PathBuf {
+    if let (Some(rest), Some(home)) = (text.strip_prefix("~/"), std::env::var_os("HOME")) {
+        return PathBuf::from(home).join(rest);
+    }
+    let path = Path::new(text);
+    match &editor.workspace_root {
+        Some(root) if path.is_relative() => root.join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn name(buffer: &Buffer) -> String {
+    buffer
+        .file_path
+        .as_ref()
+        .and_then(|path| path.file_name())
+        .map_or_else(|| "untitled".to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    // 每个测试一个空目录
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eva-editor-unsaved-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // 按顺序打开几个有未保存修改的缓冲区，返回它们的 id
+    fn editor(dir: &Path, files: &[(Option<&str>, &str)]) -> (Editor, Vec<u64>) {
+        let mut editor = Editor::new();
+        editor.workspace_root = Some(dir.to_path_buf());
+        let mut ids = Vec::new();
+        for &(name, content) in files {
+            let mut buffer = Buffer::from_content(content.to_string(), name.map(|name| dir.join(name)));
+            buffer.modified = true;
+            ids.push(buffer.id);
+            editor.open_buffer(buffer);
+        }
+        (editor, ids)
+    }
+
+    fn asked(saved: Saved) -> Prompt {
+        match saved {
+            Saved::Ask(prompt) => prompt,
+            Saved::Done => panic!("saved without asking"),
+        }
+    }
+
+    fn actions(prompt: &Prompt) -> Vec<PromptAction> {
+        prompt.choices.iter().map(|c| c.action).collect()
+    }
+
+    #[test]
+    fn save_all_asks_where_to_put_an_untitled_buffer() {
+        let dir = scratch("untitled");
+        let (mut editor, ids) = editor(&dir, &[(Some("a.txt"), "a"), (None, "b"), (Some("c.txt"), "c")]);
+
+        let prompt = asked(save_all(&mut editor));
+        assert_eq!(prompt.id, format!("save-as:{}", ids[1]));
+        assert_eq!(actions(&prompt)[0], PromptAction::SaveAs(ids[1], AfterSave::Quit));
+        assert_eq!(editor.buffers[editor.active_buffer].id, ids[1]);
+        // 前面的已经存好，后面的等回答之后再存
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "a");
+        assert!(!dir.join("c.txt").exists());
+
+        save_as(&mut editor, ids[1], "b.txt").unwrap();
+        assert!(matches!(save_all(&mut editor), Saved::Done));
+        assert_eq!(fs::read_to_string(dir.join("b.txt")).unwrap(), "b");
+        assert_eq!(fs::read_to_string(dir.join("c.txt")).unwrap(), "c");
+        assert!(editor.buffers.iter().all(|b| !b.modified));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read_only_buffer_can_only_go_elsewhere() {
+        let dir = scratch("readonly");
+        let (mut editor, ids) = editor(&dir, &[(Some("locked.txt"), "mine")]);
+        editor.buffers[0].readonly = true;
+
+        let prompt = asked(save_all(&mut editor));
+        assert_eq!(prompt.id, format!("save-failed:{}", ids[0]));
+        assert!(prompt.message.contains("read-only"), "{}", prompt.message);
+        assert_eq!(
+            actions(&prompt),
+            [
+                PromptAction::SaveElsewhere(ids[0], AfterSave::Quit),
+                PromptAction::DiscardChanges(ids[0], AfterSave::Quit),
+                PromptAction::Cancel,
+            ]
+        );
+        assert!(!dir.join("locked.txt").exists());
+
+        save_as(&mut editor, ids[0], "copy.txt").unwrap();
+        assert_eq!(fs::read_to_string(dir.join("copy.txt")).unwrap(), "mine");
+        assert!(!editor.buffers[0].readonly);
+        assert!(matches!(save_all(&mut editor), Saved::Done));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_save_stops_save_all() {
+        let dir = scratch("failed");
+        fs::write(dir.join("file"), "not a directory").unwrap();
+        let (mut editor, ids) =
+            editor(&dir, &[(Some("a.txt"), "a"), (Some("file/b.txt"), "b"), (Some("c.txt"), "c")]);
+
+        let prompt = asked(save_all(&mut editor));
+        assert_eq!(prompt.id, format!("save-failed:{}", ids[1]));
+        assert_eq!(actions(&prompt)[0], PromptAction::RetrySave(ids[1], AfterSave::Quit));
+        assert!(editor.buffers[1].modified);
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "a");
+        assert!(!dir.join("c.txt").exists());
+
+        // 再试还是失败；丢掉它的修改后接着存剩下的
+        asked(save(&mut editor, 1, AfterSave::Quit));
+        discard(&mut editor, ids[1]);
+        assert!(matches!(save_all(&mut editor), Saved::Done));
+        assert_eq!(fs::read_to_string(dir.join("c.txt")).unwrap(), "c");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn save_as_keeps_existing_files_and_the_old_path() {
+        let dir = scratch("save-as");
+        fs::write(dir.join("taken.txt"), "theirs").unwrap();
+        let (mut editor, ids) = editor(&dir, &[(Some("mine.txt"), "mine"), (None, "scratch")]);
+
+        assert!(save_as(&mut editor, ids[0], "taken.txt").is_err());
+        assert!(save_as(&mut editor, ids[0], "").is_err());
+        assert!(save_as(&mut editor, ids[0], "missing/dir/x.txt").is_err());
+        assert_eq!(editor.buffers[0].file_path, Some(dir.join("mine.txt")));
+        assert_eq!(fs::read_to_string(dir.join("taken.txt")).unwrap(), "theirs");
+
+        discard_all(&mut editor);
+        assert!(editor.buffers.iter().all(|b| !b.modified));
+        assert!(quit_prompt(&editor).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
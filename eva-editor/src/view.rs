@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use crate::editor::Cursor;
+
+// 窗格调整大小时每次移动的比例，以及两侧至少保留的比例
+const RESIZE_STEP: f32 = 0.05;
+const MIN_RATIO: f32 = 0.1;
+// 找相邻窗格时排布用的区域边长
+const NEIGHBOR_AREA: f32 = 10_000.0;
+
+// 一个窗格里显示的内容：缓冲区，以及这个窗格自己的光标、选区和滚动位置
+#[derive(Clone, Copy)]
+pub struct View {
+    pub buffer_id: u64,
+    pub cursor: Cursor,
+    pub selection_anchor: Option<usize>,
+    pub scroll_line: f32,
+}
+
+impl View {
+    pub fn new(buffer_id: u64) -> Self {
+        View { buffer_id, cursor: Cursor::default(), selection_anchor: None, scroll_line: 0.0 }
+    }
+
+    pub fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.selection_anchor?;
+        let position = self.cursor.position;
+        match anchor.cmp(&position) {
+            std::cmp::Ordering::Less => Some(anchor..position),
+            std::cmp::Ordering::Greater => Some(position..anchor),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+}
+
+// 分割方向
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SplitDirection {
+    // 左右并排
+    Horizontal,
+    // 上下排列
+    Vertical,
+}
+
+// 在窗格之间移动焦点、交换和调整大小的方向
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl Direction {
+    fn axis(self) -> SplitDirection {
+        match self {
+            Direction::Left | Direction::Right => SplitDirection::Horizontal,
+            Direction::Up | Direction::Down => SplitDirection::Vertical,
+        }
+    }
+}
+
+enum PaneNode {
+    Leaf(usize),
+    Split {
+        direction: SplitDirection,
+        // 前一半占的比例
+        ratio: f32,
+        first: Box<PaneNode>,
+        second: Box<PaneNode>,
+    },
+}
+
+// 两个窗格之间的分隔条。path 是从根走到这个分割节点的路径（false 为前一半），
+// area 是整个分割节点的区域，拖动时按它换算比例
+pub struct Divider {
+    pub path: Vec<bool>,
+    pub direction: SplitDirection,
+    pub rect: [f32; 4],
+    pub area: [f32; 4],
+}
+
+// 窗格树：叶子是视图 id。获得焦点的视图的实时状态在 Editor 上，这里存的是它上次失焦时的状态
+pub struct PaneTree {
+    root: PaneNode,
+    views: HashMap<usize, View>,
+    focused: usize,
+    next_id: usize,
+}
+
+impl PaneTree {
+    pub fn new(view: View) -> Self {
+        PaneTree { root: PaneNode::Leaf(0), views: HashMap::from([(0, view)]), focused: 0, next_id: 1 }
+    }
+
+    pub fn focused(&self) -> usize {
+        self.focused
+    }
+
+    pub fn set_focused(&mut self, id: usize) {
+        if self.views.contains_key(&id) {
+            self.focused = id;
+        }
+    }
+
+    pub fn view(&self, id: usize) -> Option<&View> {
+        self.views.get(&id)
+    }
+
+    pub fn view_mut(&mut self, id: usize) -> Option<&mut View> {
+        self.views.get_mut(&id)
+    }
+
+    pub fn views_mut(&mut self) -> impl Iterator<Item = (usize, &mut View)> {
+        self.views.iter_mut().map(|(&id, view)| (id, view))
+    }
+
+    // 把 id 所在的窗格一分为二，新视图放在后一半，返回新视图的 id
+    pub fn split(&mut self, id: usize, direction: SplitDirection, view: View) -> Option<usize> {
+        let new_id = self.next_id;
+        let leaf = find_leaf(&mut self.root, id)?;
+        *leaf = PaneNode::Split {
+            direction,
+            ratio: 0.5,
+            first: Box::new(PaneNode::Leaf(id)),
+            second: Box::new(PaneNode::Leaf(new_id)),
+        };
+        self.next_id += 1;
+        self.views.insert(new_id, view);
+        Some(new_id)
+    }
+
+    // 关掉一个窗格，兄弟节点占据它的位置；返回接替焦点的视图，只剩一个窗格时不关
+    pub fn close(&mut self, id: usize) -> Option<usize> {
+        let path = leaf_path(&self.root, id)?;
+        let (&last, parent_path) = path.split_last()?;
+        let parent = node_at(&mut self.root, parent_path)?;
+        let PaneNode::Split { first, second, .. } = std::mem::replace(parent, PaneNode::Leaf(usize::MAX)) else {
+            return None;
+        };
+        *parent = if last { *first } else { *second };
+        self.views.remove(&id);
+        let next = first_leaf(parent);
+        if self.focused == id {
+            self.focused = next;
+        }
+        Some(next)
+    }
+
+    // 交换两个窗格里的视图
+    pub fn swap(&mut self, a: usize, b: usize) {
+        for_each_leaf(&mut self.root, &mut |leaf| {
+            if *leaf == a {
+                *leaf = b;
+            } else if *leaf == b {
+                *leaf = a;
+            }
+        });
+    }
+
+    // 移动离 id 最近的、方向相符的分隔条
+    pub fn resize(&mut self, id: usize, direction: Direction) {
+        let Some(path) = leaf_path(&self.root, id) else {
+            return;
+        };
+        let step = match direction {
+            Direction::Left | Direction::Up => -RESIZE_STEP,
+            Direction::Right | Direction::Down => RESIZE_STEP,
+        };
+        for depth in (0..path.len()).rev() {
+            if let Some(PaneNode::Split { direction: split, ratio, .. }) = node_at(&mut self.root, &path[..depth]) {
+                if *split == direction.axis() {
+                    *ratio = (*ratio + step).clamp(MIN_RATIO, 1.0 - MIN_RATIO);
+                    return;
+                }
+            }
+        }
+    }
+
+    pub fn set_ratio(&mut self, path: &[bool], value: f32) {
+        if let Some(PaneNode::Split { ratio, .. }) = node_at(&mut self.root, path) {
+            *ratio = value.clamp(MIN_RATIO, 1.0 - MIN_RATIO);
+        }
+    }
+
+    // 在 area 里排布所有窗格，gap 是分隔条的宽度
+    pub fn layout(&self, area: [f32; 4], gap: f32) -> (Vec<(usize, [f32; 4])>, Vec<Divider>) {
+        let mut panes = Vec::new();
+        let mut dividers = Vec::new();
+        layout_node(&self.root, area, gap, &mut Vec::new(), &mut panes, &mut dividers);
+        (panes, dividers)
+    }
+
+    // id 在 direction 方向上紧挨着的窗格：先看距离，再看重叠的长度。
+    // layout 会把分界取整，区域要足够大，比例才不会被取整吃掉
+    pub fn neighbor(&self, id: usize, direction: Direction) -> Option<usize> {
+        let (panes, _) = self.layout([0.0, 0.0, NEIGHBOR_AREA, NEIGHBOR_AREA], 0.0);
+        let &(_, [x0, y0, x1, y1]) = panes.iter().find(|(pane, _)| *pane == id)?;
+        let epsilon = 1e-4;
+        panes
+            .iter()
+            .filter(|(pane, _)| *pane != id)
+            .filter_map(|&(pane, [a0, b0, a1, b1])| {
+                let (distance, overlap) = match direction {
+                    Direction::Left => (x0 - a1, y1.min(b1) - y0.max(b0)),
+                    Direction::Right => (a0 - x1, y1.min(b1) - y0.max(b0)),
+                    Direction::Up => (y0 - b1, x1.min(a1) - x0.max(a0)),
+                    Direction::Down => (b0 - y1, x1.min(a1) - x0.max(a0)),
+                };
+                (distance > -epsilon && overlap > epsilon).then_some((pane, distance, overlap))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1).then(b.2.total_cmp(&a.2)))
+            .map(|(pane, _, _)| pane)
+    }
+}
+
+fn layout_node(
+    node: &PaneNode,
+    area: [f32; 4],
+    gap: f32,
+    path: &mut Vec<bool>,
+    panes: &mut Vec<(usize, [f32; 4])>,
+    dividers: &mut Vec<Divider>,
+) {
+    match node {
+        PaneNode::Leaf(id) => panes.push((*id, area)),
+        PaneNode::Split { direction, ratio, first, second } => {
+            let [x0, y0, x1, y1] = area;
+            let (first_area, rect, second_area) = match direction {
+                SplitDirection::Horizontal => {
+                    let split = (x0 + (x1 - x0 - gap) * ratio).round();
+                    ([x0, y0, split, y1], [split, y0, split + gap, y1], [split + gap, y0, x1, y1])
+                }
+                SplitDirection::Vertical => {
+                    let split = (y0 + (y1 - y0 - gap) * ratio).round();
+                    ([x0, y0, x1, split], [x0, split, x1, split + gap], [x0, split + gap, x1, y1])
+                }
+            };
+            dividers.push(Divider { path: path.clone(), direction: *direction, rect, area });
+            path.push(false);
+            layout_node(first, first_area, gap, path, panes, dividers);
+            path.pop();
+            path.push(true);
+            layout_node(second, second_area, gap, path, panes, dividers);
+            path.pop();
+        }
+    }
+}
+
+fn find_leaf(node: &mut PaneNode, id: usize) -> Option<&mut PaneNode> {
+    let path = leaf_path(node, id)?;
+    node_at(node, &path)
+}
+
+fn leaf_path(node: &PaneNode, id: usize) -> Option<Vec<bool>> {
+    match node {
+        PaneNode::Leaf(leaf) => (*leaf == id).then(Vec::new),
+        PaneNode::Split { first, second, .. } => {
+            for (side, child) in [(false, first), (true, second)] {
+                if let Some(mut path) = leaf_path(child, id) {
+                    path.insert(0, side);
+                    return Some(path);
+                }
+            }
+            None
+        }
+    }
+}
+
+fn node_at<'a>(node: &'a mut PaneNode, path: &[bool]) -> Option<&'a mut PaneNode> {
+    match path.split_first() {
+        None => Some(node),
+        Some((&side, rest)) => match node {
+            PaneNode::Split { first, second, .. } => node_at(if side { second } else { first }, rest),
+            PaneNode::Leaf(_) => None,
+        },
+    }
+}
+
+fn first_leaf(node: &PaneNode) -> usize {
+    match node {
+        PaneNode::Leaf(id) => *id,
+        PaneNode::Split { first, .. } => first_leaf(first),
+    }
+}
+
+fn for_each_leaf(node: &mut PaneNode, f: &mut impl FnMut(&mut usize)) {
+    match node {
+        PaneNode::Leaf(id) => f(id),
+        PaneNode::Split { first, second, .. } => {
+            for_each_leaf(first, f);
+            for_each_leaf(second, f);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AREA: [f32; 4] = [0.0, 0.0, 100.0, 100.0];
+
+    // 0 在左边；右边上下分成 1 和 2
+    fn three_panes() -> PaneTree {
+        let mut panes = PaneTree::new(View::new(10));
+        assert_eq!(panes.split(0, SplitDirection::Horizontal, View::new(11)), Some(1));
+        assert_eq!(panes.split(1, SplitDirection::Vertical, View::new(12)), Some(2));
+        panes
+    }
+
+    fn rects(panes: &PaneTree) -> Vec<(usize, [f32; 4])> {
+        panes.layout(AREA, 0.0).0
+    }
+
+    #[test]
+    fn nested_splits() {
+        let panes = three_panes();
+        assert_eq!(
+            rects(&panes),
+            [(0, [0.0, 0.0, 50.0, 100.0]), (1, [50.0, 0.0, 100.0, 50.0]), (2, [50.0, 50.0, 100.0, 100.0])]
+        );
+        let (_, dividers) = panes.layout(AREA, 4.0);
+        let paths: Vec<&[bool]> = dividers.iter().map(|d| d.path.as_slice()).collect();
+        assert_eq!(paths, [&[][..], &[true][..]]);
+        assert_eq!(dividers[0].rect, [48.0, 0.0, 52.0, 100.0]);
+        assert_eq!(panes.view(2).map(|v| v.buffer_id), Some(12));
+        assert!(panes.view(3).is_none());
+    }
+
+    #[test]
+    fn closing_panes() {
+        let mut panes = PaneTree::new(View::new(10));
+        // 最后一个窗格不关
+        assert_eq!(panes.close(0), None);
+        assert_eq!(rects(&panes), [(0, AREA)]);
+
+        let mut panes = three_panes();
+        panes.set_focused(1);
+        // 兄弟窗格占据它的位置，也接过焦点
+        assert_eq!(panes.close(1), Some(2));
+        assert_eq!(panes.focused(), 2);
+        assert_eq!(rects(&panes), [(0, [0.0, 0.0, 50.0, 100.0]), (2, [50.0, 0.0, 100.0, 100.0])]);
+        assert_eq!(panes.close(0), Some(2));
+        assert_eq!(rects(&panes), [(2, AREA)]);
+        assert_eq!(panes.close(2), None);
+        assert_eq!(panes.close(7), None);
+    }
+
+    #[test]
+    fn neighbors_across_splits() {
+        let mut panes = three_panes();
+        assert_eq!(panes.neighbor(2, Direction::Left), Some(0));
+        assert_eq!(panes.neighbor(1, Direction::Left), Some(0));
+        assert_eq!(panes.neighbor(2, Direction::Up), Some(1));
+        assert_eq!(panes.neighbor(1, Direction::Down), Some(2));
+        assert_eq!(panes.neighbor(0, Direction::Left), None);
+        assert_eq!(panes.neighbor(0, Direction::Up), None);
+        // 右边两个一样近时取重叠更长的
+        panes.set_ratio(&[true], 0.3);
+        assert_eq!(panes.neighbor(0, Direction::Right), Some(2));
+        panes.set_ratio(&[true], 0.7);
+        assert_eq!(panes.neighbor(0, Direction::Right), Some(1));
+    }
+
+    #[test]
+    fn resize_ratio_is_clamped() {
+        let mut panes = three_panes();
+        // 左右方向移动的是根节点的分隔条，上下方向移动的是右边的
+        panes.resize(2, Direction::Left);
+        assert_eq!(rects(&panes)[0].1, [0.0, 0.0, 45.0, 100.0]);
+        panes.resize(0, Direction::Down);
+        assert_eq!(rects(&panes)[0].1, [0.0, 0.0, 45.0, 100.0]);
+        panes.resize(1, Direction::Down);
+        assert_eq!(rects(&panes)[1].1, [45.0, 0.0, 100.0, 55.0]);
+
+        for _ in 0..30 {
+            panes.resize(0, Direction::Right);
+            panes.resize(2, Direction::Up);
+        }
+        assert_eq!(rects(&panes), [
+            (0, [0.0, 0.0, 90.0, 100.0]),
+            (1, [90.0, 0.0, 100.0, 10.0]),
+            (2, [90.0, 10.0, 100.0, 100.0]),
+        ]);
+        panes.set_ratio(&[], -1.0);
+        assert_eq!(rects(&panes)[0].1, [0.0, 0.0, 10.0, 100.0]);
+        // 路径不是分割节点时什么也不做
+        panes.set_ratio(&[false], 0.5);
+        assert_eq!(rects(&panes)[0].1, [0.0, 0.0, 10.0, 100.0]);
+    }
+}
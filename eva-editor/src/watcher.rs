@@ -0,0 +1,406 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::SystemTime;
+use anyhow::Result;
+use log::{debug, error, info, warn};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use crate::diff;
+use crate::editor::{Buffer, Editor};
+use crate::prompt::{AfterSave, Prompt, PromptAction};
+
+// 打开、保存和重新读入时记下的文件状态。之后的文件事件都拿磁盘和它比，
+// 自己保存引起的事件、只碰了同目录其他文件的事件就不会当成外部修改
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DiskState {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl DiskState {
+    // 文件不存在时返回 None；符号链接看它指向的文件
+    pub fn read(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(DiskState { modified: metadata.modified().ok(), len: metadata.len() })
+    }
+}
+
+// 缓冲区和磁盘上的文件的关系，显示在状态栏上
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum DiskStatus {
+    #[default]
+    Synced,
+    // 外部改了文件，缓冲区也有修改，等用户选择
+    Changed,
+    // 文件被删掉，或者移到了监视不到的地方；保存时重新创建
+    Deleted,
+}
+
+// 监视打开的文件所在的目录。不直接监视文件：原子保存（包括我们自己的）会换掉 inode，
+// 监视跟着旧文件走就收不到后面的事件了
+pub struct FileWatcher {
+    // 建不起来时（比如 inotify 的数量用完）只是不监视，编辑照常
+    watcher: Option<RecommendedWatcher>,
+    events: Receiver<notify::Result<Event>>,
+    directories: HashSet<PathBuf>,
+    // 缓冲区 id → (缓冲区的路径, 解析后的绝对路径)，缓冲区换了路径才重新解析
+    files: HashMap<u64, (PathBuf, PathBuf)>,
+}
+
+impl FileWatcher {
+    pub fn new() -> Self {
+        let (sender, events) = channel();
+        let watcher = match notify::recommended_watcher(sender) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                warn!("File watching is unavailable: {}", e);
+                None
+            }
+        };
+        FileWatcher { watcher, events, directories: HashSet::new(), files: HashMap::new() }
+    }
+
+    // 每帧调用：跟上新开、关掉和换了路径的缓冲区，再处理攒下的事件。
+    // 没有修改的缓冲区直接重新读入；有修改的返回它们的 id，由调用方弹出冲突提示
+    pub fn poll(&mut self, editor: &mut Editor) -> Vec<u64> {
+        self.track(editor);
+
+        let mut touched = HashSet::new();
+        let mut moves = HashMap::new();
+        while let Ok(result) = self.events.try_recv() {
+            let event = match result {
+                Ok(event) => event,
+                Err(e) => {
+                    warn!("File watcher error: {}", e);
+                    continue;
+                }
+            };
+            if let (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to]) = (event.kind, event.paths.as_slice()) {
+                for id in self.buffers_at(from) {
+                    moves.insert(id, to.clone());
+                }
+            }
+            for path in &event.paths {
+                touched.extend(self.buffers_at(path));
+            }
+        }
+
+        let mut conflicts = Vec::new();
+        for id in touched {
+            if let Some(index) = editor.index_of(id) {
+                if check(editor, index, moves.remove(&id)) {
+                    conflicts.push(id);
+                }
+            }
+        }
+        conflicts
+    }
+
+    fn buffers_at<'a>(&'a self, path: &'a Path) -> impl Iterator<Item = u64> + 'a {
+        self.files.iter().filter(move |(_, (_, real))| real == path).map(|(&id, _)| id)
+    }
+
+    fn track(&mut self, editor: &Editor) {
+        let mut changed = false;
+        self.files.retain(|&id, (path, _)| {
+            let keep = editor.buffers.iter().any(|b| b.id == id && b.file_path.as_ref() == Some(path));
+            changed |= !keep;
+            keep
+        });
+        for buffer in &editor.buffers {
+            if let (Some(path), false) = (&buffer.file_path, self.files.contains_key(&buffer.id)) {
+                self.files.insert(buffer.id, (path.clone(), real_path(path)));
+                changed = true;
+            }
+        }
+        if changed {
+            self.sync_directories();
+        }
+    }
+
+    // 监视失败的目录（比如还不存在）下次缓冲区有变化时再试
+    fn sync_directories(&mut self) {
+        let Some(watcher) = self.watcher.as_mut() else {
+            return;
+        };
+        let wanted: HashSet<PathBuf> =
+            self.files.values().filter_map(|(_, real)| real.parent().map(Path::to_path_buf)).collect();
+        self.directories.retain(|dir| {
+            let keep = wanted.contains(dir);
+            if !keep {
+                let _ = watcher.unwatch(dir);
+            }
+            keep
+        });
+        for dir in wanted {
+            if self.directories.contains(&dir) {
+                continue;
+            }
+            match watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                Ok(()) => {
+                    debug!("Watching {}", dir.display());
+                    self.directories.insert(dir);
+                }
+                Err(e) => debug!("Cannot watch {}: {}", dir.display(), e),
+            }
+        }
+    }
+}
+
+// 事件里的路径是监视的目录拼上文件名，监视的目录是解析过的，这里也一样解析；
+// 文件还不存在时解析它所在的目录
+fn real_path(path: &Path) -> PathBuf {
+    if let Ok(real) = fs::canonicalize(path) {
+        return real;
+    }
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match (fs::canonicalize(parent), path.file_name()) {
+        (Ok(dir), Some(name)) => dir.join(name),
+        _ => std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+// 拿磁盘对照一个缓冲区，返回是否要用户来决定
+fn check(editor: &mut Editor, index: usize, moved_to: Option<PathBuf>) -> bool {
+    let buffer = &mut editor.buffers[index];
+    let Some(path) = buffer.file_path.clone() else {
+        return false;
+    };
+    let state = DiskState::read(&path);
+    if state.is_some() && state == buffer.disk_state {
+        // 删掉后又原样放回来了
+        if buffer.disk_status == DiskStatus::Deleted {
+            buffer.disk_status = DiskStatus::Synced;
+        }
+        return false;
+    }
+    if state.is_none() {
+        // 在监视的目录之间改了名：缓冲区跟着新路径走。有的编辑器保存时先把原文件改名成备份，
+        // 那时原路径上马上又有了文件，不会走到这里
+        if let Some(to) = moved_to.filter(|to| DiskState::read(to) == buffer.disk_state) {
+            info!("{} was moved to {}", path.display(), to.display());
+            buffer.file_path = Some(to);
+        } else if buffer.disk_status != DiskStatus::Deleted {
+            warn!("{} was deleted on disk", path.display());
+            buffer.disk_status = DiskStatus::Deleted;
+        }
+        return false;
+    }
+    if buffer.modified {
+        warn!("{} changed on disk and has unsaved changes", path.display());
+        buffer.disk_status = DiskStatus::Changed;
+        return true;
+    }
+    if let Err(e) = editor.reload_buffer(index) {
+        error!("Failed to reload {}: {:#}", path.display(), e);
+    }
+    false
+}
+
+// 保存前再对照一次磁盘：事件可能还没处理，有的文件系统（网络盘）也不发事件。
+// 磁盘上的文件和打开时不一样就返回缓冲区 id，这时不要保存，先弹出冲突提示
+pub fn conflict_before_save(editor: &mut Editor, index: usize) -> Option<u64> {
+    let buffer = editor.buffers.get_mut(index)?;
+    let state = DiskState::read(buffer.file_path.as_ref()?);
+    if state.is_none() || state == buffer.disk_state {
+        return None;
+    }
+    buffer.disk_status = DiskStatus::Changed;
+    Some(buffer.id)
+}
+
+// then 不是 Nothing 时是关闭或退出前的保存碰上了冲突，按 Esc 就停下不再接着做
+pub fn conflict_prompt(buffer: &Buffer, then: AfterSave) -> Prompt {
+    let name = buffer
+        .file_path
+        .as_ref()
+        .and_then(|path| path.file_name())
+        .map_or_else(|| "The file".to_string(), |name| name.to_string_lossy().into_owned());
+    let id = buffer.id;
+    let cancel = match then {
+        AfterSave::Nothing => PromptAction::KeepMine(id, then),
+        _ => PromptAction::Cancel,
+    };
+    Prompt::new(
+        format!("conflict:{}", id),
+        format!("{} changed on disk, and you have unsaved changes.", name),
+        cancel,
+    )
+    .choice('t', "Take theirs", PromptAction::TakeTheirs(id, then))
+    .choice('k', "Keep mine", PromptAction::KeepMine(id, then))
+    .choice('d', "Show diff", PromptAction::ShowDiff(id))
+}
+
+// 留着缓冲区的内容：把磁盘现在的样子记下来不再提示，下次保存时覆盖它
+pub fn keep_mine(editor: &mut Editor, id: u64) {
+    let Some(buffer) = editor.index_of(id).map(|index| &mut editor.buffers[index]) else {
+        return;
+    };
+    buffer.disk_state = buffer.file_path.as_deref().and_then(DiskState::read);
+    buffer.disk_status = DiskStatus::Synced;
+    buffer.modified = true;
+}
+
+pub fn take_theirs(editor: &mut Editor, id: u64) -> Result<()> {
+    match editor.index_of(id) {
+        Some(index) => editor.reload_buffer(index),
+        None => Ok(()),
+    }
+}
+
+// 在新的只读缓冲区里打开磁盘版本到缓冲区内容的差异
+pub fn show_diff(editor: &mut Editor, id: u64) -> Result<()> {
+    let Some(buffer) = editor.index_of(id).map(|index| &editor.buffers[index]) else {
+        return Ok(());
+    };
+    let Some(path) = buffer.file_path.clone() else {
+        return Ok(());
+    };
+    let theirs = Buffer::from_file(path.clone())?;
+    let name = path.display();
+    let old_name = format!("{} (on disk)", name);
+    let new_name = format!("{} (unsaved)", name);
+    editor.open_buffer(diff::diff_buffer(&theirs.content, &buffer.content, &old_name, &new_name));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eva-editor-watcher-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // 写入内容并把修改时间设成固定的秒数，不用等文件系统的时间精度
+    fn write_at(path: &Path, content: &str, seconds: u64) {
+        fs::write(path, content).unwrap();
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(seconds)).unwrap();
+    }
+
+    // 打开一个文件，返回编辑器、缓冲区下标和 id
+    fn open(path: &Path) -> (Editor, usize, u64) {
+        let mut editor = Editor::new();
+        editor.open_buffer(Buffer::from_file(path.to_path_buf()).unwrap());
+        let id = editor.buffers.last().unwrap().id;
+        let index = editor.index_of(id).unwrap();
+        (editor, index, id)
+    }
+
+    #[test]
+    fn disk_state_compares_time_and_length() {
+        let dir = scratch("disk-state");
+        let path = dir.join("a.txt");
+        write_at(&path, "abc", 1_000);
+        let state = DiskState::read(&path).unwrap();
+        assert_eq!(DiskState::read(&path), Some(state));
+        // 只比时间和长度：两样都一样的改写看不出来
+        write_at(&path, "xyz", 1_000);
+        assert_eq!(DiskState::read(&path), Some(state));
+        write_at(&path, "abc", 2_000);
+        assert_ne!(DiskState::read(&path), Some(state));
+        write_at(&path, "abcd", 1_000);
+        assert_ne!(DiskState::read(&path), Some(state));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(DiskState::read(&path), None);
+    }
+
+    #[test]
+    fn unmodified_buffers_reload() {
+        let dir = scratch("reload");
+        let path = dir.join("a.txt");
+        write_at(&path, "one\n", 1_000);
+        let (mut editor, index, _) = open(&path);
+
+        // 自己保存或者只碰了同目录的其他文件：状态没变，什么都不做
+        assert!(!check(&mut editor, index, None));
+        write_at(&path, "one\ntwo\n", 2_000);
+        assert!(!check(&mut editor, index, None));
+        let buffer = &editor.buffers[index];
+        assert_eq!(buffer.content, "one\ntwo\n");
+        assert!(!buffer.modified);
+        assert_eq!(buffer.disk_status, DiskStatus::Synced);
+        assert_eq!(buffer.disk_state, DiskState::read(&path));
+    }
+
+    #[test]
+    fn modified_buffers_ask_and_keep_or_take() {
+        let dir = scratch("conflict");
+        let path = dir.join("a.txt");
+        write_at(&path, "one\n", 1_000);
+        let (mut editor, index, id) = open(&path);
+        editor.buffers[index].insert(0, "mine ");
+
+        write_at(&path, "theirs\n", 2_000);
+        assert!(check(&mut editor, index, None));
+        assert_eq!(editor.buffers[index].disk_status, DiskStatus::Changed);
+        assert_eq!(editor.buffers[index].content, "mine one\n");
+
+        // 留着自己的：记下磁盘现在的状态，同样的文件不再提示
+        keep_mine(&mut editor, id);
+        let buffer = &editor.buffers[index];
+        assert_eq!((buffer.disk_status, buffer.modified), (DiskStatus::Synced, true));
+        assert_eq!(buffer.disk_state, DiskState::read(&path));
+        assert!(!check(&mut editor, index, None));
+
+        write_at(&path, "theirs again\n", 3_000);
+        assert!(check(&mut editor, index, None));
+        take_theirs(&mut editor, id).unwrap();
+        let buffer = &editor.buffers[index];
+        assert_eq!(buffer.content, "theirs again\n");
+        assert_eq!((buffer.disk_status, buffer.modified), (DiskStatus::Synced, false));
+        assert_eq!(buffer.disk_state, DiskState::read(&path));
+    }
+
+    #[test]
+    fn saving_over_a_changed_file_asks_first() {
+        let dir = scratch("before-save");
+        let path = dir.join("a.txt");
+        write_at(&path, "one\n", 1_000);
+        let (mut editor, index, id) = open(&path);
+        editor.buffers[index].insert(0, "mine ");
+        assert_eq!(conflict_before_save(&mut editor, index), None);
+
+        write_at(&path, "one\n", 2_000);
+        assert_eq!(conflict_before_save(&mut editor, index), Some(id));
+        assert_eq!(editor.buffers[index].disk_status, DiskStatus::Changed);
+
+        // 文件没了就直接保存，重新建出来
+        fs::remove_file(&path).unwrap();
+        assert_eq!(conflict_before_save(&mut editor, index), None);
+    }
+
+    #[test]
+    fn deleted_and_moved_files() {
+        let dir = scratch("deleted");
+        let path = dir.join("a.txt");
+        write_at(&path, "one\n", 1_000);
+        let (mut editor, index, _) = open(&path);
+
+        fs::remove_file(&path).unwrap();
+        assert!(!check(&mut editor, index, None));
+        assert_eq!(editor.buffers[index].disk_status, DiskStatus::Deleted);
+        // 原样放回来
+        write_at(&path, "one\n", 1_000);
+        assert!(!check(&mut editor, index, None));
+        assert_eq!(editor.buffers[index].disk_status, DiskStatus::Synced);
+
+        // 改名：缓冲区跟着新路径走，内容不动
+        let moved = dir.join("b.txt");
+        fs::rename(&path, &moved).unwrap();
+        assert!(!check(&mut editor, index, Some(moved.clone())));
+        let buffer = &editor.buffers[index];
+        assert_eq!(buffer.file_path.as_deref(), Some(moved.as_path()));
+        assert_eq!((buffer.disk_status, buffer.content.as_str()), (DiskStatus::Synced, "one\n"));
+    }
+}